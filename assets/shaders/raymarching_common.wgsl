@@ -7,10 +7,28 @@ struct BlobEntity {
     direction: f32,
     last_ate: f32,
     color: vec3<f32>,
+    // squash and stretch from the blob's velocity, 1.0 when round
+    stretch: f32,
+    stretch_direction: vec2<f32>,
+    // how much spawn protection is left, 1.0 right after spawning, 0.0 once it's gone
+    protection: f32,
+    // faded out blobs are left out of the pixels whose dither_threshold is above this
+    opacity: f32,
+    // height of the idle bob, 0.0 while the blob is moving
+    bob: f32,
+    bob_phase: f32,
 }
 
 struct BlobData {
+    // gameplay time, stops while paused. Use this instead of globals.time for blob animations
+    time: f32,
     blob_count: u32,
+    // raymarching step limit, lowered when there are lots of blobs
+    max_steps: u32,
+    // index of the blob to outline, or -1
+    outlined: i32,
+    bob_frequency: f32,
+    outline_color: vec4<f32>,
     blobs: array<BlobEntity, 64>,
 }
 
@@ -19,9 +37,10 @@ struct BvhNode {
     min: vec3<f32>,
     /// Maximum of the AABB
     max: vec3<f32>,
-    /// Left child index, or -1 if leaf node
+    /// Left child index, or minus the number of entities if leaf node
     left: i32,
-    /// Right child index, or entity index if leaf node
+    /// Right child index, or entity index if leaf node. A leaf with more than one entity is
+    /// followed by a node for each of the others, with the entity's own AABB and index.
     right: i32,
 }
 
@@ -36,7 +55,20 @@ struct HitEntities {
 
 var<private> hit_entities: HitEntities;
 
-@group(1) @binding(0) var<uniform> blob_data: BlobData;
+// screen door transparency, set per pixel with set_dither_threshold before raymarching. Blobs
+// with opacity at or below it are skipped, 0.0 skips only fully faded ones.
+var<private> dither_threshold: f32 = 0.0;
+
+fn set_dither_threshold(fragment_position: vec4<f32>) {
+    // interleaved gradient noise
+    dither_threshold = fract(52.9829189 * fract(dot(fragment_position.xy, vec2(0.06711056, 0.00583715))));
+}
+
+fn is_faded_out(blob: BlobEntity) -> bool {
+    return blob.opacity <= dither_threshold;
+}
+
+@group(1) @binding(0) var<storage> blob_data: BlobData;
 @group(1) @binding(1) var<storage> bvh: BvhTree;
 
 fn opSmoothUnion(d1: f32, d2: f32, k: f32) -> f32 {
@@ -78,15 +110,27 @@ fn ease_out(x: f32) -> f32 {
 }
 
 fn sdf_blob(ray_position: vec3<f32>, blob: BlobEntity, index: f32) -> f32 {
-        let t = 0.7 + sin(globals.time + index) * 0.3;
+        let t = 0.7 + sin(blob_data.time + index) * 0.3;
         let t2 = 15.0 * pow(abs(t), 0.5) * sign(t);
-        let ray_local = ray_position - vec3(blob.position, 0.4);
-        let ray_rotated = rotate_x(rotate_z(ray_local, -blob.direction), -globals.time);
+        let bob = blob.bob * sin(blob_data.time * blob_data.bob_frequency + blob.bob_phase);
+        let ray_offset = ray_position - vec3(blob.position, 0.4 + bob);
+        // stretch along the velocity and squash across it, keeping the volume
+        let along = dot(ray_offset.xy, blob.stretch_direction);
+        let across = ray_offset.xy - blob.stretch_direction * along;
+        let squash = sqrt(blob.stretch);
+        let ray_local = vec3(blob.stretch_direction * along / blob.stretch + across * squash, ray_offset.z * squash);
+        // blob space, facing -y (same as the move vector in handle_player_input)
+        let ray_facing = rotate_z(ray_local, -blob.direction);
+        let ray_rotated = rotate_x(ray_facing, -blob_data.time);
         var displacement = sin(t2 * ray_rotated.x) * sin(t2 * ray_rotated.y) * sin(t2 * ray_rotated.z);
-        let blob_size = blob.size * ease_out(globals.time - blob.last_ate);
-        let distance_local = length(ray_rotated) - blob_size * (sin(globals.time * 2.54) * 0.1 + 0.9) + displacement * 0.06;
+        let blob_size = blob.size * ease_out(blob_data.time - blob.last_ate);
+        let distance_local = length(ray_rotated) - blob_size * (sin(blob_data.time * 2.54) * 0.1 + 0.9) + displacement * 0.06;
 
-        return distance_local;
+        // small "nose" bump on the front, so you can tell which way the blob is going
+        let nose = length(ray_facing - vec3(0., -blob_size * 0.75, 0.)) - blob_size * 0.35;
+
+        // squashing stretches blob space by up to `squash`, scale back so the raymarcher doesn't overshoot
+        return opSmoothUnion(distance_local, nose, blob_size * 0.3) / squash;
 }
 
 fn sdf(ray_position: vec3<f32>) -> f32 {
@@ -94,6 +138,9 @@ fn sdf(ray_position: vec3<f32>) -> f32 {
 
     for (var i = 0u; i < hit_entities.count; i++) {
         let blob = hit_entities.entities[i];
+        if (is_faded_out(blob)) {
+            continue;
+        }
         acc = opSmoothUnion(acc, sdf_blob(ray_position, blob, 0.0), 0.6);
     }
 
@@ -105,6 +152,42 @@ fn sdf(ray_position: vec3<f32>) -> f32 {
     return acc;
 }
 
+// blob colors mixed by how close their surfaces are to ray_position, so the smooth unions
+// between blobs blend their colors too. Away from every blob, on the dish, it's the dish color.
+fn blob_color(ray_position: vec3<f32>) -> vec3<f32> {
+    let dish_color = vec3(1.0, 0.51, 0.41);
+    var color = vec3(0.0);
+    var total = 0.0;
+    for (var i = 0u; i < hit_entities.count; i++) {
+        let blob = hit_entities.entities[i];
+        if (is_faded_out(blob)) {
+            continue;
+        }
+        let weight = exp(-max(sdf_blob(ray_position, blob, 0.0), 0.0) * 20.0);
+        color += blob.color * weight;
+        total += weight;
+    }
+    return mix(dish_color, color / max(total, 0.0001), clamp(total, 0.0, 1.0));
+}
+
+// the hit entity whose surface is closest to ray_position, call after raymarch
+fn closest_hit_blob(ray_position: vec3<f32>) -> BlobEntity {
+    var closest = hit_entities.entities[0];
+    var closest_distance = 9000.0;
+    for (var i = 0u; i < hit_entities.count; i++) {
+        let blob = hit_entities.entities[i];
+        if (is_faded_out(blob)) {
+            continue;
+        }
+        let distance = sdf_blob(ray_position, blob, 0.0);
+        if (distance < closest_distance) {
+            closest = blob;
+            closest_distance = distance;
+        }
+    }
+    return closest;
+}
+
 fn set_up_ray(fragment_position: vec4<f32>) -> vec3<f32> {
     let fragment_ndc = vec2(fragment_position.x / view.viewport.z, fragment_position.y / view.viewport.w);
     let aspect_ratio = vec2(1.0, -1.0);
@@ -122,7 +205,8 @@ fn raymarch(ray_origin: vec3<f32>, ray_direction: vec3<f32>, max_distance: f32)
     var ray_position = ray_origin;
     var distance_acc = 0.0;
 
-    for (var i = 0; i < MAX_RT_STEPS; i++) {
+    let max_steps = min(MAX_RT_STEPS, i32(blob_data.max_steps));
+    for (var i = 0; i < max_steps; i++) {
         let closest_surface = sdf(ray_position);
 
         if (closest_surface <= RT_EPSILON) {
@@ -235,10 +319,18 @@ fn bvh_lookup_ray(ray_pos: vec3<f32>, ray_dir: vec3<f32>) {
 
         let ray_hit = ray_intersects_aabb(ray_pos, ray_dir, node.min, node.max);
         if (ray_hit) {
-            if (node.left == -1) {
-                // leaf node, right is entity data index
-                hit_entities.entities[hit_entities.count] = blob_data.blobs[node.right];
-                hit_entities.count++;
+            if (node.left < 0) {
+                // leaf node, right is entity data index, or -1 if the blob didn't fit in the buffer.
+                // the extra entities of the leaf come right after it
+                let entity_count = u32(-node.left);
+                for (var i = 0u; i < entity_count; i++) {
+                    let entry = bvh.tree[node_id + i];
+                    let entry_hit = i == 0u || ray_intersects_aabb(ray_pos, ray_dir, entry.min, entry.max);
+                    if (entry_hit && entry.right >= 0 && hit_entities.count < 10u) {
+                        hit_entities.entities[hit_entities.count] = blob_data.blobs[entry.right];
+                        hit_entities.count++;
+                    }
+                }
             } else {
                 // branch node, left and right are indices for the child nodes
                 // push the child nodes to queue
@@ -30,6 +30,7 @@ fn fragment(
 ) -> FragmentOutput {
     var out: FragmentOutput;
 
+    set_dither_threshold(fragment_position);
     let ray_direction = set_up_ray(fragment_position);
     let ray_origin = view.world_position;
 
@@ -50,8 +51,9 @@ fn fragment(
     let thickness = 1.0 - calculate_thickness(ray_hit, normal);
 
     var pbr_input: PbrInput = pbr_input_new();
-    pbr_input.material.base_color = vec4(1.0, 0.51, 0.41, 1.0);
-    pbr_input.material.emissive = vec4(3.9, 0.1, 0.0, 1.0) * (thickness + 0.1) * 0.3 * (sin(globals.time * 1.61) * 0.4 + 0.6);
+    let color = blob_color(ray_hit);
+    pbr_input.material.base_color = vec4(color, 1.0);
+    pbr_input.material.emissive = vec4(color * 2.0, 1.0) * (thickness + 0.1) * 0.3 * (sin(blob_data.time * 1.61) * 0.4 + 0.6);
     pbr_input.material.reflectance = 0.6;
     pbr_input.material.perceptual_roughness = 0.17;
     pbr_input.material.metallic = 0.3;
@@ -63,6 +65,23 @@ fn fragment(
     pbr_input.V = -ray_direction;
 
     out.color = pbr(pbr_input);
+
+    // spawn protected blobs shimmer towards white
+    let hit_blob = closest_hit_blob(ray_hit);
+    if (hit_blob.protection > 0.0) {
+        let shimmer = (sin(blob_data.time * 12.0) * 0.5 + 0.5) * 0.35 * hit_blob.protection;
+        out.color = vec4(mix(out.color.rgb, vec3(1.0), shimmer), out.color.a);
+    }
+
+    // rim outline on the selected blob, only where the surface belongs to it
+    if (blob_data.outlined >= 0) {
+        let outlined_blob = blob_data.blobs[u32(blob_data.outlined)];
+        if (sdf_blob(ray_hit, outlined_blob, 0.0) < 0.05) {
+            let rim = pow(1.0 - max(dot(normal, -ray_direction), 0.0), 3.0);
+            out.color = mix(out.color, blob_data.outline_color, rim);
+        }
+    }
+
     out.depth = depth;
 
     return out;
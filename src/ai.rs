@@ -0,0 +1,222 @@
+//! Steering for the computer controlled blobs
+use crate::raymarching::{Blob, MergeConfig};
+use crate::{direction_towards, turn_towards};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiDifficulty>()
+            .add_system(difficulty_window);
+    }
+}
+
+/// How fast AI blobs move, the player moves at 3.1
+const AI_SPEED: f32 = 2.5;
+
+/// Marks a blob as steered by [`ai_steering`]
+#[derive(Component, Default)]
+pub struct AiBlob {
+    /// Seconds until the blob looks around again
+    next_decision: f32,
+    /// Direction picked on the last decision, the blob turns towards it in between
+    target_direction: Option<f32>,
+}
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AiDifficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// What an [`AiDifficulty`] boils down to
+#[derive(Clone, Copy, Debug)]
+pub struct AiTuning {
+    /// Seconds between decisions
+    pub reaction_time: f32,
+    /// Radians per second
+    pub turn_rate: f32,
+    /// Smaller blobs further away than this are ignored
+    pub chase_distance: f32,
+    /// Bigger blobs closer than this are run away from
+    pub flee_distance: f32,
+}
+
+impl AiDifficulty {
+    pub fn tuning(self) -> AiTuning {
+        match self {
+            AiDifficulty::Easy => AiTuning {
+                reaction_time: 0.8,
+                turn_rate: 1.0,
+                chase_distance: 3.0,
+                flee_distance: 1.5,
+            },
+            AiDifficulty::Normal => AiTuning {
+                reaction_time: 0.4,
+                turn_rate: 2.0,
+                chase_distance: 5.0,
+                flee_distance: 3.0,
+            },
+            AiDifficulty::Hard => AiTuning {
+                reaction_time: 0.1,
+                turn_rate: 4.0,
+                chase_distance: 8.0,
+                flee_distance: 5.0,
+            },
+        }
+    }
+}
+
+/// Picks the direction a blob at `position` wants to go, fleeing the closest threat first and
+/// chasing the closest prey otherwise. Returns None if there is nothing worth reacting to.
+pub fn choose_direction(
+    position: Vec2,
+    size: f32,
+    others: impl Iterator<Item = (Vec2, f32)>,
+    tuning: &AiTuning,
+    size_ratio: f32,
+) -> Option<f32> {
+    let mut threat: Option<(f32, Vec2)> = None;
+    let mut prey: Option<(f32, Vec2)> = None;
+
+    for (other_position, other_size) in others {
+        let distance = position.distance(other_position);
+        if other_size >= size * size_ratio {
+            if distance < tuning.flee_distance && threat.map_or(true, |(d, _)| distance < d) {
+                threat = Some((distance, other_position));
+            }
+        } else if size >= other_size * size_ratio
+            && distance < tuning.chase_distance
+            && prey.map_or(true, |(d, _)| distance < d)
+        {
+            prey = Some((distance, other_position));
+        }
+    }
+
+    if let Some((_, threat_position)) = threat {
+        // towards the point mirrored through ourselves, i.e. straight away
+        Some(direction_towards(
+            position,
+            position * 2.0 - threat_position,
+        ))
+    } else {
+        prey.map(|(_, prey_position)| direction_towards(position, prey_position))
+    }
+}
+
+pub(crate) fn ai_steering(
+    mut blobs: Query<(Entity, &mut Transform, &mut Blob, Option<&mut AiBlob>)>,
+    difficulty: Res<AiDifficulty>,
+    merge_config: Res<MergeConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    let delta = fixed_time.period.as_secs_f32();
+    let tuning = difficulty.tuning();
+
+    let snapshot: Vec<(Entity, Vec2, f32)> = blobs
+        .iter()
+        .map(|(entity, transform, blob, _)| (entity, transform.translation.xy(), blob.size))
+        .collect();
+
+    for (entity, mut transform, mut blob, ai) in blobs.iter_mut() {
+        let Some(mut ai) = ai else {
+            continue;
+        };
+
+        ai.next_decision -= delta;
+        if ai.next_decision <= 0.0 {
+            ai.next_decision = tuning.reaction_time;
+            let others = snapshot
+                .iter()
+                .filter(|(other, _, _)| *other != entity)
+                .map(|(_, position, size)| (*position, *size));
+            ai.target_direction = choose_direction(
+                transform.translation.xy(),
+                blob.size,
+                others,
+                &tuning,
+                merge_config.size_ratio,
+            );
+        }
+
+        if let Some(target) = ai.target_direction {
+            blob.direction = turn_towards(blob.direction, target, tuning.turn_rate * delta);
+        }
+
+        transform.translation +=
+            Quat::from_rotation_z(blob.direction) * Vec3::NEG_Y * AI_SPEED * delta;
+    }
+}
+
+fn difficulty_window(mut difficulty: ResMut<AiDifficulty>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("AI").show(egui_contexts.ctx_mut(), |ui| {
+        let mut selected = *difficulty;
+        egui::ComboBox::from_label("Difficulty")
+            .selected_text(format!("{:?}", selected))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut selected, AiDifficulty::Easy, "Easy");
+                ui.selectable_value(&mut selected, AiDifficulty::Normal, "Normal");
+                ui.selectable_value(&mut selected, AiDifficulty::Hard, "Hard");
+            });
+
+        // only touch the resource on actual edits, so change detection stays quiet
+        if selected != *difficulty {
+            *difficulty = selected;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle_difference;
+
+    /// How far an AI blob still has to turn towards prey off to its side after `ticks` ticks
+    fn remaining_turn(difficulty: AiDifficulty, ticks: usize) -> f32 {
+        let mut app = App::new();
+        app.insert_resource(difficulty)
+            .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+            .init_resource::<MergeConfig>()
+            .add_system(ai_steering);
+        let hunter = app
+            .world
+            .spawn((
+                Transform::default(),
+                Blob {
+                    size: 0.5,
+                    ..default()
+                },
+                AiBlob::default(),
+            ))
+            .id();
+        let prey = Vec2::new(2.0, 0.0);
+        app.world.spawn((
+            Transform::from_translation(prey.extend(0.0)),
+            Blob {
+                size: 0.2,
+                ..default()
+            },
+        ));
+
+        for _ in 0..ticks {
+            app.update();
+        }
+        let position = app.world.get::<Transform>(hunter).unwrap().translation.xy();
+        let direction = app.world.get::<Blob>(hunter).unwrap().direction;
+        angle_difference(direction, direction_towards(position, prey)).abs()
+    }
+
+    #[test]
+    fn hard_ai_turns_towards_prey_faster_than_easy() {
+        let easy = remaining_turn(AiDifficulty::Easy, 20);
+        let normal = remaining_turn(AiDifficulty::Normal, 20);
+        let hard = remaining_turn(AiDifficulty::Hard, 20);
+        assert!(hard < normal, "hard {} normal {}", hard, normal);
+        assert!(normal < easy, "normal {} easy {}", normal, easy);
+    }
+}
@@ -0,0 +1,125 @@
+//! Battle royale mode, the arena closes in over time
+use crate::raymarching::Blob;
+use crate::Arena;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct ArenaShrinkPlugin;
+
+impl Plugin for ArenaShrinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ArenaShrink>()
+            .add_system(arena_shrink_window);
+    }
+}
+
+#[derive(Resource)]
+pub struct ArenaShrink {
+    pub enabled: bool,
+    /// How fast the edge moves in, units per second
+    pub rate: f32,
+    /// The arena stops shrinking at this inner radius
+    pub min_radius: f32,
+    /// Size per second lost by blobs sticking out past the edge
+    pub damage: f32,
+    /// Damage doesn't shrink blobs below this size
+    pub min_blob_size: f32,
+}
+
+impl Default for ArenaShrink {
+    fn default() -> Self {
+        ArenaShrink {
+            enabled: false,
+            rate: 0.1,
+            min_radius: 3.0,
+            damage: 0.2,
+            min_blob_size: 0.2,
+        }
+    }
+}
+
+pub(crate) fn shrink_arena(
+    mut arena: ResMut<Arena>,
+    mut blobs: Query<(&Transform, &mut Blob)>,
+    config: Res<ArenaShrink>,
+    fixed_time: Res<FixedTime>,
+) {
+    if !config.enabled {
+        return;
+    }
+
+    let delta = fixed_time.period.as_secs_f32();
+    if arena.inner_radius() > config.min_radius {
+        arena.shrink(config.rate * delta, config.min_radius);
+    }
+
+    let damage = config.damage * delta;
+    // `apply_arena_bounds` pushes the blobs back in after this
+    for (transform, mut blob) in blobs.iter_mut() {
+        // any part of the blob outside the edge hurts
+        if arena.clamp_inset(transform.translation, blob.radius()) == transform.translation {
+            continue;
+        }
+
+        let floor = config.min_blob_size.min(blob.size);
+        blob.size = (blob.size - damage).max(floor);
+        blob.target_size = blob.target_size.map(|target| (target - damage).max(floor));
+    }
+}
+
+fn arena_shrink_window(mut config: ResMut<ArenaShrink>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("Shrinking arena").show(egui_contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut config.enabled, "Enabled");
+        ui.add(egui::Slider::new(&mut config.rate, 0.0..=1.0).text("Shrink rate"));
+        ui.add(egui::Slider::new(&mut config.min_radius, 0.5..=10.0).text("Safe zone radius"));
+        ui.add(egui::Slider::new(&mut config.damage, 0.0..=1.0).text("Damage"));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_arena_bounds, ArenaBounds};
+
+    #[test]
+    fn edge_closes_in_and_pushes_blobs_inside() {
+        let mut app = App::new();
+        app.insert_resource(Arena::Circle { radius: 10.0 })
+            .insert_resource(ArenaShrink {
+                enabled: true,
+                rate: 1.0,
+                min_radius: 8.0,
+                ..default()
+            })
+            .init_resource::<ArenaBounds>()
+            .insert_resource(FixedTime::new_from_secs(0.1))
+            .add_systems((shrink_arena, apply_arena_bounds).chain());
+        let blob = app
+            .world
+            .spawn((
+                Transform::from_xyz(9.5, 0.0, 1.0),
+                Blob {
+                    size: 0.5,
+                    ..default()
+                },
+            ))
+            .id();
+
+        // a second at 0.1 per tick
+        for _ in 0..10 {
+            app.update();
+        }
+        let radius = app.world.resource::<Arena>().inner_radius();
+        assert!((radius - 9.0).abs() < 1e-3, "{}", radius);
+        let size = app.world.get::<Blob>(blob).unwrap().size;
+        assert!(size < 0.5, "{}", size);
+        let x = app.world.get::<Transform>(blob).unwrap().translation.x;
+        assert!(x < 9.5, "{}", x);
+
+        // and stops at the safe zone
+        for _ in 0..30 {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<Arena>().inner_radius(), 8.0);
+    }
+}
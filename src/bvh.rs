@@ -1,5 +1,8 @@
 //! Bounding volume hierarchy
-use crate::raymarching::{EntityBufferIndex, VoxelMaterial};
+use crate::raymarching::{Blob, BlobMaterial, EntityBufferIndex, VoxelMaterial};
+use crate::rotating_buffer::RotatingBuffer;
+use crate::simulation::SimulatedPosition;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
 use bevy::pbr::{MaterialPipeline, MaterialPipelineKey, RenderMaterials};
 use bevy::prelude::*;
 use bevy::reflect::TypeUuid;
@@ -11,7 +14,11 @@ use bevy::render::render_resource::{
 };
 use bevy::render::renderer::{RenderDevice, RenderQueue};
 use bevy::render::{extract_resource::ExtractResource, Extract, RenderApp, RenderSet};
+use bevy::utils::{Duration, HashMap, HashSet, Instant};
 use bevy_mod_gizmos::draw_gizmos_with_line;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 #[derive(Component)]
 pub struct CalculateBvh;
@@ -23,8 +30,18 @@ pub struct LocalBoundingBox {
     pub max: Vec3,
 }
 
+impl LocalBoundingBox {
+    /// Fits the blob cube mesh, blob size comes in through the transform scale
+    pub fn blob() -> Self {
+        LocalBoundingBox {
+            min: Vec3::splat(-1.),
+            max: Vec3::splat(1.),
+        }
+    }
+}
+
 /// Axis-aligned bounding box in world space
-#[derive(Component, Copy, Clone, Debug)]
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq)]
 pub struct Aabb {
     pub min: Vec3,
     pub max: Vec3,
@@ -35,6 +52,53 @@ impl Aabb {
         self.min + (self.max - self.min) * 0.5
     }
 
+    pub fn is_finite(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite()
+    }
+
+    /// Distance from `point` to the closest point of the box, zero inside it
+    pub fn distance_to_point(&self, point: Vec3) -> f32 {
+        (self.min - point)
+            .max(point - self.max)
+            .max(Vec3::ZERO)
+            .length()
+    }
+
+    /// Distance along the ray to where it enters the box, zero if it starts inside. None if it
+    /// misses, or only gets there past `max_distance`.
+    pub fn ray_intersection(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<f32> {
+        // slab test, the reciprocal is infinite along axes the ray doesn't move on
+        let inverse = direction.recip();
+        let t1 = (self.min - origin) * inverse;
+        let t2 = (self.max - origin) * inverse;
+        let enter = t1.min(t2).max_element().max(0.0);
+        let exit = t1.max(t2).min_element().min(max_distance);
+        (enter <= exit).then_some(enter)
+    }
+
+    /// Whether `other` is entirely inside this box
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.min).all() && self.max.cmpge(other.max).all()
+    }
+
+    /// Smallest box containing both
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Covers the box both where it is and moved by `offset`
+    pub fn swept(&self, offset: Vec3) -> Aabb {
+        self.merge(&(self + offset))
+    }
+
     pub fn total_surface_area(&self) -> f32 {
         let extents = self.max - self.min;
         return extents.x * extents.y * 2.
@@ -56,36 +120,571 @@ impl Default for BvhTree {
                     min: Default::default(),
                     max: Default::default(),
                 },
-                kind: BvhNodeKind::Leaf(Entity::from_raw(0)),
+                kind: BvhNodeKind::Leaf(vec![(Entity::from_raw(0), Aabb::default())]),
             },
         }
     }
 }
 
+impl BvhTree {
+    pub fn root_aabb(&self) -> &Aabb {
+        &self.root.aabb
+    }
+
+    /// Total number of nodes, same as the length of the GPU buffer
+    pub fn node_count(&self) -> usize {
+        self.root.node_count()
+    }
+
+    /// Every node with its AABB, depth first with the left child before the right one
+    pub fn iter_nodes(&self) -> BvhNodes<'_> {
+        BvhNodes {
+            stack: vec![&self.root],
+        }
+    }
+
+    /// The entity closest to `point` that passes `filter`, and its distance. Distances are
+    /// measured to the AABBs, so anything overlapping `point` is at zero.
+    pub fn nearest(
+        &self,
+        point: Vec3,
+        mut filter: impl FnMut(Entity) -> bool,
+    ) -> Option<(Entity, f32)> {
+        let mut best = None;
+        self.root.nearest(point, &mut filter, &mut best);
+        best
+    }
+
+    /// Writes every node's AABB as a wireframe box to a Wavefront OBJ file, for looking at in
+    /// Blender and the like. With `group_by_depth` each tree level goes in its own `g` group.
+    pub fn export_obj(&self, path: impl AsRef<Path>, group_by_depth: bool) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_obj(&mut writer, group_by_depth)?;
+        writer.flush()
+    }
+
+    pub fn write_obj(&self, writer: &mut impl Write, group_by_depth: bool) -> io::Result<()> {
+        let mut levels: Vec<Vec<&Aabb>> = Vec::new();
+        self.root.collect_levels(0, &mut levels);
+
+        let mut vertex_count = 0;
+        for (depth, aabbs) in levels.iter().enumerate() {
+            if group_by_depth {
+                writeln!(writer, "g depth_{}", depth)?;
+            }
+
+            for aabb in aabbs {
+                for corner in 0..8 {
+                    let v = Vec3::select(
+                        BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                        aabb.max,
+                        aabb.min,
+                    );
+                    writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?;
+                }
+
+                // corners that differ in exactly one axis
+                for a in 0..8 {
+                    for axis in [1, 2, 4] {
+                        let b = a | axis;
+                        if b != a {
+                            writeln!(
+                                writer,
+                                "l {} {}",
+                                vertex_count + a + 1,
+                                vertex_count + b + 1
+                            )?;
+                        }
+                    }
+                }
+                vertex_count += 8;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Calls `f` for every entity whose AABB is within `radius` of `center`
+    pub fn query_sphere(&self, center: Vec3, radius: f32, mut f: impl FnMut(Entity)) {
+        self.root.query_sphere(center, radius, &mut f);
+    }
+
+    /// Calls `f` for every entity whose AABB the ray from `origin` along the normalized
+    /// `direction` passes through within `max_distance`, in no particular order
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mut f: impl FnMut(Entity),
+    ) {
+        self.root.raycast(origin, direction, max_distance, &mut f);
+    }
+
+    /// Adds a leaf for `entity` without rebuilding. It goes next to whichever node makes the
+    /// cheapest sibling by surface area, same cost model as `split_node`.
+    pub fn insert(&mut self, entity: Entity, aabb: Aabb) {
+        self.root.insert(entity, aabb);
+    }
+
+    /// Removes the leaf for `entity`, its sibling takes the parent's place. Returns false if the
+    /// entity isn't in the tree, or is the only entity left since the tree can't be empty.
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        self.root.remove(entity)
+    }
+
+    /// Checks that every node's AABB contains its children's and that no entity is in more than
+    /// one leaf. Describes the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        self.root.validate(&mut seen)
+    }
+
+    /// Updates leaf AABBs from `aabbs` and refits the branches above them. Leaves missing from
+    /// `aabbs` keep their old box.
+    pub fn refit(&mut self, aabbs: &HashMap<Entity, Aabb>) {
+        self.root.refit(aabbs);
+    }
+}
+
 #[derive(Clone)]
 pub struct BvhNode {
     aabb: Aabb,
     kind: BvhNodeKind,
 }
 
+impl BvhNode {
+    fn node_count(&self) -> usize {
+        match &self.kind {
+            // extra entities in a leaf get a node each in the GPU buffer
+            BvhNodeKind::Leaf(entries) => entries.len(),
+            BvhNodeKind::Branch(left, right) => 1 + left.node_count() + right.node_count(),
+        }
+    }
+
+    fn nearest(
+        &self,
+        point: Vec3,
+        filter: &mut impl FnMut(Entity) -> bool,
+        best: &mut Option<(Entity, f32)>,
+    ) {
+        let distance = self.aabb.distance_to_point(point);
+        if best.map_or(false, |(_, best_distance)| distance >= best_distance) {
+            return;
+        }
+
+        match &self.kind {
+            BvhNodeKind::Leaf(entries) => {
+                for (entity, aabb) in entries {
+                    let distance = aabb.distance_to_point(point);
+                    if best.map_or(true, |(_, best_distance)| distance < best_distance)
+                        && filter(*entity)
+                    {
+                        *best = Some((*entity, distance));
+                    }
+                }
+            }
+            BvhNodeKind::Branch(left, right) => {
+                // closer child first, so the other one is more likely to get culled
+                let (first, second) =
+                    if left.aabb.distance_to_point(point) <= right.aabb.distance_to_point(point) {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                first.nearest(point, filter, best);
+                second.nearest(point, filter, best);
+            }
+        }
+    }
+
+    fn validate(&self, seen: &mut HashSet<Entity>) -> Result<(), String> {
+        match &self.kind {
+            BvhNodeKind::Leaf(entries) => {
+                if entries.is_empty() {
+                    return Err("empty leaf".to_string());
+                }
+                if entries.len() > MAX_LEAF_ENTITIES {
+                    return Err(format!("leaf with {} entities", entries.len()));
+                }
+                for (entity, aabb) in entries {
+                    if !seen.insert(*entity) {
+                        return Err(format!("{:?} is in more than one leaf", entity));
+                    }
+                    if !self.aabb.contains(aabb) {
+                        return Err(format!(
+                            "leaf {:?} doesn't contain {:?} at {:?}",
+                            self.aabb, entity, aabb
+                        ));
+                    }
+                }
+            }
+            BvhNodeKind::Branch(left, right) => {
+                for child in [left, right] {
+                    if !self.aabb.contains(&child.aabb) {
+                        return Err(format!(
+                            "branch {:?} doesn't contain its child {:?}",
+                            self.aabb, child.aabb
+                        ));
+                    }
+                    child.validate(seen)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_levels<'a>(&'a self, depth: usize, levels: &mut Vec<Vec<&'a Aabb>>) {
+        if levels.len() <= depth {
+            levels.push(Vec::new());
+        }
+        levels[depth].push(&self.aabb);
+
+        if let BvhNodeKind::Branch(left, right) = &self.kind {
+            left.collect_levels(depth + 1, levels);
+            right.collect_levels(depth + 1, levels);
+        }
+    }
+
+    fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        f: &mut impl FnMut(Entity),
+    ) {
+        if self
+            .aabb
+            .ray_intersection(origin, direction, max_distance)
+            .is_none()
+        {
+            return;
+        }
+
+        match &self.kind {
+            BvhNodeKind::Leaf(entries) => {
+                for (entity, aabb) in entries {
+                    if aabb
+                        .ray_intersection(origin, direction, max_distance)
+                        .is_some()
+                    {
+                        f(*entity);
+                    }
+                }
+            }
+            BvhNodeKind::Branch(left, right) => {
+                left.raycast(origin, direction, max_distance, f);
+                right.raycast(origin, direction, max_distance, f);
+            }
+        }
+    }
+
+    /// How much the tree grows if `aabb` is inserted somewhere below or next to this node
+    fn insertion_cost(&self, aabb: &Aabb) -> f32 {
+        let merged = self.aabb.merge(aabb).total_surface_area();
+        match &self.kind {
+            BvhNodeKind::Leaf(_) => merged,
+            BvhNodeKind::Branch(..) => merged - self.aabb.total_surface_area(),
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, aabb: Aabb) {
+        let merged = self.aabb.merge(&aabb);
+
+        if let BvhNodeKind::Branch(left, right) = &mut self.kind {
+            // making a new parent here vs. pushing the box down into a child, which grows
+            // this node either way
+            let new_parent_cost = merged.total_surface_area();
+            let inherited_cost = new_parent_cost - self.aabb.total_surface_area();
+            let left_cost = left.insertion_cost(&aabb) + inherited_cost;
+            let right_cost = right.insertion_cost(&aabb) + inherited_cost;
+
+            if left_cost.min(right_cost) < new_parent_cost {
+                if left_cost <= right_cost {
+                    left.insert(entity, aabb);
+                } else {
+                    right.insert(entity, aabb);
+                }
+                self.aabb = merged;
+                return;
+            }
+        }
+
+        // this node becomes the new leaf's sibling
+        let sibling = BvhNode {
+            aabb: self.aabb,
+            kind: std::mem::replace(&mut self.kind, BvhNodeKind::Leaf(Vec::new())),
+        };
+        let leaf = BvhNode {
+            aabb,
+            kind: BvhNodeKind::Leaf(vec![(entity, aabb)]),
+        };
+        self.kind = BvhNodeKind::Branch(Box::new(sibling), Box::new(leaf));
+        self.aabb = merged;
+    }
+
+    fn remove(&mut self, entity: Entity) -> bool {
+        let (left, right) = match &mut self.kind {
+            BvhNodeKind::Leaf(entries) => {
+                // a leaf can't remove its last entity by itself, the parent collapses it
+                let Some(index) = entries.iter().position(|(e, _)| *e == entity) else {
+                    return false;
+                };
+                if entries.len() == 1 {
+                    return false;
+                }
+                entries.swap_remove(index);
+                self.aabb = merge_aabbs(entries);
+                return true;
+            }
+            BvhNodeKind::Branch(left, right) => (left, right),
+        };
+
+        let is_only_entity = |node: &BvhNode| match &node.kind {
+            BvhNodeKind::Leaf(entries) => entries.len() == 1 && entries[0].0 == entity,
+            BvhNodeKind::Branch(..) => false,
+        };
+        let remove_left = is_only_entity(left);
+        if remove_left || is_only_entity(right) {
+            let kind = std::mem::replace(&mut self.kind, BvhNodeKind::Leaf(Vec::new()));
+            if let BvhNodeKind::Branch(left, right) = kind {
+                *self = if remove_left { *right } else { *left };
+            }
+            return true;
+        }
+
+        if left.remove(entity) || right.remove(entity) {
+            self.aabb = left.aabb.merge(&right.aabb);
+            return true;
+        }
+
+        false
+    }
+
+    fn refit(&mut self, aabbs: &HashMap<Entity, Aabb>) {
+        match &mut self.kind {
+            BvhNodeKind::Leaf(entries) => {
+                for (entity, aabb) in entries.iter_mut() {
+                    if let Some(new_aabb) = aabbs.get(entity) {
+                        *aabb = *new_aabb;
+                    }
+                }
+                self.aabb = merge_aabbs(entries);
+            }
+            BvhNodeKind::Branch(left, right) => {
+                left.refit(aabbs);
+                right.refit(aabbs);
+                self.aabb = left.aabb.merge(&right.aabb);
+            }
+        }
+    }
+
+    fn query_sphere(&self, center: Vec3, radius: f32, f: &mut impl FnMut(Entity)) {
+        if self.aabb.distance_to_point(center) > radius {
+            return;
+        }
+
+        match &self.kind {
+            BvhNodeKind::Leaf(entries) => {
+                for (entity, aabb) in entries {
+                    if aabb.distance_to_point(center) <= radius {
+                        f(*entity);
+                    }
+                }
+            }
+            BvhNodeKind::Branch(left, right) => {
+                left.query_sphere(center, radius, f);
+                right.query_sphere(center, radius, f);
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum BvhNodeKind {
-    Leaf(Entity),
+    /// Up to [`BvhLeafSize`] entities from a build, the incremental paths can't grow it past
+    /// that
+    Leaf(Vec<(Entity, Aabb)>),
     Branch(Box<BvhNode>, Box<BvhNode>),
 }
 
-#[derive(Resource)]
-pub struct BvhBuffer(pub StorageBuffer<GpuTree>);
+/// Read-only view of a node handed out by [`BvhTree::iter_nodes`]
+#[derive(Clone, Copy, Debug)]
+pub enum BvhNodeRef<'a> {
+    /// The entities in the leaf and their own AABBs
+    Leaf(&'a [(Entity, Aabb)]),
+    /// Its children come right after it in the iteration
+    Branch,
+}
+
+/// Iterator returned by [`BvhTree::iter_nodes`]
+pub struct BvhNodes<'a> {
+    stack: Vec<&'a BvhNode>,
+}
+
+impl<'a> Iterator for BvhNodes<'a> {
+    type Item = (&'a Aabb, BvhNodeRef<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let kind = match &node.kind {
+            BvhNodeKind::Leaf(entries) => BvhNodeRef::Leaf(entries),
+            BvhNodeKind::Branch(left, right) => {
+                self.stack.push(right);
+                self.stack.push(left);
+                BvhNodeRef::Branch
+            }
+        };
+        Some((&node.aabb, kind))
+    }
+}
+
+/// Bounds of everything in the BVH, the root node's AABB of the current tree
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct SceneBounds(pub Aabb);
+
+/// GPU copies of the BVH, see [`RotatingBuffer`]
+pub type BvhBuffer = RotatingBuffer<GpuTree>;
 
 pub struct BvhPlugin;
 
+/// Number of nodes uploaded to the GPU
+pub const BVH_NODE_COUNT: DiagnosticId =
+    DiagnosticId::from_u128(101658617297203946307154302460431340061);
+
+/// Time spent updating the tree per frame, full rebuild or not. Only measured while
+/// [`BvhBuildTiming`] is enabled.
+pub const BVH_BUILD_TIME: DiagnosticId =
+    DiagnosticId::from_u128(178251370911203557153419286104775412293);
+
+/// Profiling for the BVH builder
+#[derive(Resource, Default)]
+pub struct BvhBuildTiming {
+    pub enabled: bool,
+    /// How long the last build took, only updated while enabled
+    pub last_build: Option<Duration>,
+}
+
+/// When [`update_bvh`] can patch the tree instead of rebuilding it
+#[derive(Resource)]
+pub struct BvhRebuildPolicy {
+    /// Frames with a single spawn or despawn insert or remove one leaf, everything else
+    /// rebuilds. Incremental inserts drift away from what `split_node` would build, so the tree
+    /// is rebuilt from scratch at least this often anyway.
+    pub full_rebuild_interval: u32,
+}
+
+impl Default for BvhRebuildPolicy {
+    fn default() -> Self {
+        BvhRebuildPolicy {
+            full_rebuild_interval: 30,
+        }
+    }
+}
+
+/// How many entities `split_node` groups into a leaf. The shader tests all of a leaf's blobs
+/// once it reaches the leaf, so bigger leaves mean a shallower tree but more blob tests.
+/// Changing it rebuilds the tree.
+#[derive(Resource)]
+pub struct BvhLeafSize(pub usize);
+
+impl Default for BvhLeafSize {
+    fn default() -> Self {
+        BvhLeafSize(4)
+    }
+}
+
+impl BvhLeafSize {
+    /// The size clamped to `1..=MAX_LEAF_ENTITIES`
+    pub fn get(&self) -> usize {
+        self.0.clamp(1, MAX_LEAF_ENTITIES)
+    }
+}
+
+/// Stretches blob AABBs along the way they moved last simulation tick, so the boxes also cover
+/// where a fast blob will be next tick instead of only where it is. `blob_merger` sweeps the
+/// blobs the same way, so fast blobs can't skip over smaller ones between ticks.
+#[derive(Resource)]
+pub struct SweptAabbs {
+    pub enabled: bool,
+    /// The stretch is cut down to this length, so a teleport doesn't make a huge box
+    pub max_padding: f32,
+}
+
+impl Default for SweptAabbs {
+    fn default() -> Self {
+        SweptAabbs {
+            enabled: false,
+            max_padding: 1.0,
+        }
+    }
+}
+
+impl SweptAabbs {
+    /// `aabb` padded for a blob that moved from `previous` to `current` last tick
+    pub fn pad(&self, aabb: Aabb, previous: Vec3, current: Vec3) -> Aabb {
+        if !self.enabled {
+            return aabb;
+        }
+        aabb.swept((current - previous).clamp_length_max(self.max_padding))
+    }
+
+    /// How far a blob that moved from `previous` to `current` gets padded in every direction
+    pub fn padding(&self, previous: Vec2, current: Vec2) -> f32 {
+        if !self.enabled {
+            return 0.0;
+        }
+        previous.distance(current).min(self.max_padding)
+    }
+
+    /// Closest the centers of blobs `a` and `b` got while moving in a straight line from their
+    /// previous to their current positions, just the current distance when disabled
+    pub fn closest_approach(
+        &self,
+        a_previous: Vec2,
+        a_current: Vec2,
+        b_previous: Vec2,
+        b_current: Vec2,
+    ) -> f32 {
+        let end = b_current - a_current;
+        if !self.enabled {
+            return end.length();
+        }
+
+        let a_start = a_current - (a_current - a_previous).clamp_length_max(self.max_padding);
+        let b_start = b_current - (b_current - b_previous).clamp_length_max(self.max_padding);
+        let start = b_start - a_start;
+        // closest point to the origin on the segment from start to end
+        let along = end - start;
+        let t = if along.length_squared() > 0.0 {
+            (-start.dot(along) / along.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (start + along * t).length()
+    }
+}
+
+pub(crate) fn setup_bvh_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(BVH_BUILD_TIME, "bvh_build_time", 20).with_suffix("ms"));
+    diagnostics
+        .add(Diagnostic::new(BVH_NODE_COUNT, "bvh_node_count", 20).with_smoothing_factor(0.0));
+}
+
 impl Plugin for BvhPlugin {
     fn build(&self, app: &mut App) {
         app
             // .add_plugin(ExtractResourcePlugin::<BvhTree>::default())
             // .add_startup_system(setup_bvh)
+            .add_startup_system(setup_bvh_diagnostics)
             .add_system(update_bvh_aabb)
             .insert_resource(BvhTree::default())
+            .init_resource::<BvhBuildTiming>()
+            .init_resource::<BvhRebuildPolicy>()
+            .init_resource::<BvhLeafSize>()
+            .init_resource::<SweptAabbs>()
+            .init_resource::<SceneBounds>()
+            .init_resource::<BvhBuffer>()
             .add_system(update_bvh)
             .add_system(update_bvh_buffer.after(update_bvh))
             .add_system(update_material_buffer.in_base_set(CoreSet::PostUpdate));
@@ -105,16 +704,15 @@ impl Plugin for BvhPlugin {
     }
 }
 
-fn update_material_buffer(
-    instances: Query<&Handle<VoxelMaterial>>,
+pub(crate) fn update_material_buffer(
+    blob_material: Res<BlobMaterial>,
     mut mats: ResMut<Assets<VoxelMaterial>>,
     bvh: Res<BvhBuffer>,
 ) {
-    if let Some(buffer) = bvh.0.buffer() {
-        for instance in instances.iter() {
-            let material = mats.get_mut(instance).unwrap();
-            material.bvh = buffer.clone();
-        }
+    // every blob shares the one material, the prepass render path copies it from there
+    if let (Some(buffer), Some(material)) = (bvh.current().buffer(), mats.get_mut(&blob_material.0))
+    {
+        material.bvh = buffer.clone();
     }
 }
 
@@ -124,18 +722,84 @@ pub struct GpuNode {
     min: Vec3,
     /// Maximum of the AABB
     max: Vec3,
-    /// Left child index, or -1 if leaf node
+    /// Left child index, or minus the number of entities if leaf node
     left: i32,
-    /// Right child index, or entity index if leaf node
+    /// Right child index, or entity index if leaf node. A leaf with more than one entity is
+    /// followed by a node for each of the others, with the entity's own AABB and index.
     right: i32,
 }
 
-#[derive(Debug, Clone, ShaderType)]
+#[derive(Debug, Clone, Default, ShaderType)]
 pub struct GpuTree {
     #[size(runtime)]
     tree: Vec<GpuNode>,
 }
 
+impl GpuTree {
+    /// Checks that every child index points further down the buffer, where the shader expects
+    /// it, and that leaves don't run past the end
+    pub fn validate(&self) -> Result<(), String> {
+        let len = self.tree.len();
+        for (index, node) in self.tree.iter().enumerate() {
+            if node.left >= 0 {
+                for child in [node.left, node.right] {
+                    if child as usize <= index || child as usize >= len {
+                        return Err(format!(
+                            "node {} has child {} out of range {}..{}",
+                            index,
+                            child,
+                            index + 1,
+                            len
+                        ));
+                    }
+                }
+            } else {
+                let entities = node.left.unsigned_abs() as usize;
+                if index + entities > len {
+                    return Err(format!(
+                        "leaf {} has {} entities but only {} nodes are left",
+                        index,
+                        entities,
+                        len - index
+                    ));
+                }
+                if node.right < -1 {
+                    return Err(format!("leaf {} has entity index {}", index, node.right));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many nodes fit in a storage buffer binding of `max_binding_size` bytes, see
+/// `WgpuLimits::max_storage_buffer_binding_size`
+pub fn max_gpu_nodes(max_binding_size: u32) -> usize {
+    (u64::from(max_binding_size) / GpuNode::min_size().get()) as usize
+}
+
+/// Cuts `nodes` down to `max_nodes`. Branches pointing past the end become empty leaves, so the
+/// shader skips whatever got cut instead of reading out of bounds. Returns whether anything was
+/// cut.
+pub fn clamp_gpu_nodes(nodes: &mut Vec<GpuNode>, max_nodes: usize) -> bool {
+    if nodes.len() <= max_nodes {
+        return false;
+    }
+
+    nodes.truncate(max_nodes);
+    for (index, node) in nodes.iter_mut().enumerate() {
+        if node.left >= 0 && (node.left as usize >= max_nodes || node.right as usize >= max_nodes) {
+            node.left = -1;
+            node.right = -1;
+        } else if node.left < 0 {
+            // leaf whose extra entities got cut off
+            let entities = (node.left.unsigned_abs() as usize).min(max_nodes - index);
+            node.left = -(entities as i32);
+        }
+    }
+    true
+}
+
 fn extract_aabb(
     mut commands: Commands,
     entities: Extract<Query<(Entity, &Aabb), With<CalculateBvh>>>,
@@ -148,23 +812,54 @@ fn extract_aabb(
     commands.insert_or_spawn_batch(values);
 }
 
-fn update_bvh_aabb(
+pub(crate) fn update_bvh_aabb(
     mut query: Query<
-        (Entity, &LocalBoundingBox, &Transform, Option<&mut Aabb>),
+        (
+            Entity,
+            &LocalBoundingBox,
+            &Transform,
+            Option<&Blob>,
+            Option<&SimulatedPosition>,
+            Option<&mut Aabb>,
+        ),
         (
             With<CalculateBvh>,
-            Or<(Changed<Transform>, Changed<LocalBoundingBox>)>,
+            Or<(Changed<Transform>, Changed<LocalBoundingBox>, Changed<Blob>)>,
         ),
     >,
     mut commands: Commands,
+    swept: Res<SweptAabbs>,
+    mut warned: Local<HashSet<Entity>>,
 ) {
-    for (entity, local_bb, transform, maybe_aabb) in query.iter_mut() {
+    for (entity, local_bb, transform, blob, simulated, maybe_aabb) in query.iter_mut() {
         let local_bb: &LocalBoundingBox = local_bb;
         let transform: &Transform = transform;
         let maybe_aabb: Option<Mut<Aabb>> = maybe_aabb;
 
+        // blobs go by their gameplay size, not the transform scale, so squashing the mesh for
+        // looks doesn't change the bounds
+        let scale = blob.map_or(transform.scale, Blob::scale);
+
+        // a NaN here would poison the whole tree, leave the entity out until it recovers
+        if !transform.translation.is_finite() || !scale.is_finite() {
+            if warned.insert(entity) {
+                warn!(
+                    "{:?} has a non-finite transform, leaving it out of the BVH",
+                    entity
+                );
+            }
+            if maybe_aabb.is_some() {
+                commands.entity(entity).remove::<Aabb>();
+            }
+            continue;
+        }
+        warned.remove(&entity);
+
         // TODO: rotation
-        let new_aabb = &local_bb.into() * transform.scale + transform.translation;
+        let mut new_aabb = &local_bb.into() * scale + transform.translation;
+        if let Some(simulated) = simulated {
+            new_aabb = swept.pad(new_aabb, simulated.previous, simulated.current);
+        }
         if let Some(mut aabb) = maybe_aabb {
             *aabb = new_aabb
         } else {
@@ -173,16 +868,25 @@ fn update_bvh_aabb(
     }
 }
 
-fn update_bvh(
-    mut commands: Commands,
+pub(crate) fn update_bvh(
     objects: Query<(Entity, &Aabb), With<CalculateBvh>>,
     mut entities: Local<Vec<(Entity, Aabb)>>,
+    mut known: Local<HashMap<Entity, Aabb>>,
+    mut frames_since_rebuild: Local<u32>,
     mut finished: Local<bool>,
+    mut tree: ResMut<BvhTree>,
+    mut scene_bounds: ResMut<SceneBounds>,
+    policy: Res<BvhRebuildPolicy>,
+    leaf_size: Res<BvhLeafSize>,
+    mut timing: ResMut<BvhBuildTiming>,
+    mut diagnostics: ResMut<Diagnostics>,
 ) {
     entities.clear();
-    // collect all entities
+    // collect all entities, skipping any that somehow ended up with a non-finite AABB
     for (entity, aabb) in objects.iter() {
-        entities.push((entity, aabb.clone()));
+        if aabb.is_finite() {
+            entities.push((entity, aabb.clone()));
+        }
     }
 
     if entities.is_empty() {
@@ -190,41 +894,106 @@ fn update_bvh(
         return;
     }
 
-    // make root node
-    let root = split_node(&mut entities);
+    let start = timing.enabled.then(Instant::now);
+
+    let current: HashMap<Entity, Aabb> = entities.iter().copied().collect();
+    let added: Vec<(Entity, Aabb)> = entities
+        .iter()
+        .filter(|(entity, _)| !known.contains_key(entity))
+        .copied()
+        .collect();
+    let removed: Vec<Entity> = known
+        .keys()
+        .filter(|entity| !current.contains_key(entity))
+        .copied()
+        .collect();
+
+    *frames_since_rebuild += 1;
+    let incremental = *finished
+        && !leaf_size.is_changed()
+        && added.len() + removed.len() <= 1
+        && *frames_since_rebuild < policy.full_rebuild_interval;
+
+    // everything moves every frame, so even the incremental path refits all the boxes
+    let patched = incremental
+        && match (added.first(), removed.first()) {
+            (Some((entity, aabb)), None) => {
+                tree.insert(*entity, *aabb);
+                true
+            }
+            (None, Some(entity)) => tree.remove(*entity),
+            _ => true,
+        };
+
+    if patched {
+        tree.refit(&current);
+    } else {
+        tree.root = split_node(&mut entities, 0, leaf_size.get());
+        *frames_since_rebuild = 0;
+    }
+
+    if let Some(start) = start {
+        let elapsed = start.elapsed();
+        debug!(
+            "BVH {} over {} entities took {:?}",
+            if patched { "update" } else { "build" },
+            entities.len(),
+            elapsed
+        );
+        diagnostics.add_measurement(BVH_BUILD_TIME, || elapsed.as_secs_f64() * 1000.);
+        timing.last_build = Some(elapsed);
+    }
 
-    // if let BvhNodeKind::Branch(left, right) = &root.kind {
+    // if let BvhNodeKind::Branch(left, right) = &tree.root.kind {
     //     spawn_debug_cubes(&mut commands, left);
     //     spawn_debug_cubes(&mut commands, right);
     // }
 
-    commands
-        .spawn(TransformBundle::from_transform(
-            Transform::from_translation(Vec3::new(0., 0., 0.)),
-        ))
-        .insert((root.aabb.clone(),));
+    #[cfg(debug_assertions)]
+    if let Err(error) = tree.validate() {
+        error!(
+            "BVH {} left a broken tree: {}",
+            if patched { "update" } else { "build" },
+            error
+        );
+    }
 
-    commands.insert_resource(BvhTree { root });
+    diagnostics.add_measurement(BVH_NODE_COUNT, || tree.node_count() as f64);
+    scene_bounds.0 = tree.root.aabb;
+    *known = current;
     *finished = true;
 }
 
 fn update_bvh_buffer(
-    mut commands: Commands,
     tree: Res<BvhTree>,
+    mut bvh_buffer: ResMut<BvhBuffer>,
     entity_to_index: Query<&EntityBufferIndex>,
     render_device: Res<RenderDevice>,
     render_queue: Res<RenderQueue>,
+    mut warned: Local<bool>,
 ) {
     let mut nodes = Vec::new();
 
     push_node_to_buffer(&tree.root, &mut nodes, &entity_to_index);
 
-    let gpu_tree = GpuTree { tree: nodes };
+    // a buffer bigger than the device allows is a hard error, losing some blobs is not
+    let node_count = nodes.len();
+    let max_nodes = max_gpu_nodes(render_device.limits().max_storage_buffer_binding_size);
+    if clamp_gpu_nodes(&mut nodes, max_nodes) && !*warned {
+        warn!(
+            "BVH has {} nodes but the device only fits {}, some blobs won't be drawn",
+            node_count, max_nodes
+        );
+        *warned = true;
+    }
 
-    let mut buffer = StorageBuffer::from(gpu_tree);
-    buffer.write_buffer(&render_device, &render_queue);
+    let gpu_tree = GpuTree { tree: nodes };
+    #[cfg(debug_assertions)]
+    if let Err(error) = gpu_tree.validate() {
+        error!("BVH buffer is broken: {}", error);
+    }
 
-    commands.insert_resource(BvhBuffer(buffer));
+    bvh_buffer.write_next(&render_device, &render_queue, |tree| *tree = gpu_tree);
 }
 
 fn push_node_to_buffer(
@@ -233,15 +1002,25 @@ fn push_node_to_buffer(
     entity_to_index: &Query<&EntityBufferIndex>,
 ) {
     match &node.kind {
-        BvhNodeKind::Leaf(entity) => buffer.push(GpuNode {
-            min: node.aabb.min,
-            max: node.aabb.max,
-            left: -1,
-            right: entity_to_index
-                .get(*entity)
-                .unwrap_or(&EntityBufferIndex(-1))
-                .0,
-        }),
+        BvhNodeKind::Leaf(entries) => {
+            for (i, (entity, aabb)) in entries.iter().enumerate() {
+                // the first node is the leaf itself, the rest only carry an entity
+                let (aabb, left) = if i == 0 {
+                    (&node.aabb, -(entries.len() as i32))
+                } else {
+                    (aabb, -1)
+                };
+                buffer.push(GpuNode {
+                    min: aabb.min,
+                    max: aabb.max,
+                    left,
+                    right: entity_to_index
+                        .get(*entity)
+                        .unwrap_or(&EntityBufferIndex(-1))
+                        .0,
+                });
+            }
+        }
         BvhNodeKind::Branch(left, right) => {
             let own_index = buffer.len();
             buffer.push(GpuNode {
@@ -263,13 +1042,24 @@ fn push_node_to_buffer(
     }
 }
 
-fn split_node(aabbs: &mut [(Entity, Aabb)]) -> BvhNode {
+/// Upper limit for [`BvhLeafSize`]. The shader keeps at most 10 blobs per ray, a leaf shouldn't
+/// fill that up on its own.
+pub const MAX_LEAF_ENTITIES: usize = 8;
+
+/// Past this depth `split_node` always splits at the median, which halves the group every time.
+/// Together with the fallback for clustered centroids it keeps the tree depth logarithmic, the
+/// shader's traversal stack is only so deep.
+pub const MAX_SAH_DEPTH: usize = 24;
+
+/// Groups of `max_leaf` entities or fewer become a single leaf instead of being split further
+fn split_node(aabbs: &mut [(Entity, Aabb)], depth: usize, max_leaf: usize) -> BvhNode {
     assert!(aabbs.len() > 0);
 
-    if aabbs.len() == 1 {
+    let aabb = merge_aabbs(aabbs);
+    if aabbs.len() <= max_leaf {
         return BvhNode {
-            aabb: aabbs[0].1,
-            kind: BvhNodeKind::Leaf(aabbs[0].0),
+            aabb,
+            kind: BvhNodeKind::Leaf(aabbs.to_vec()),
         };
     }
 
@@ -286,26 +1076,55 @@ fn split_node(aabbs: &mut [(Entity, Aabb)]) -> BvhNode {
         find_split_index_and_cost(&aabbs)
     };
 
-    let (left, right) =
-        if x_index_and_cost.1 < y_index_and_cost.1 && x_index_and_cost.1 < z_index_and_cost.1 {
-            aabbs.sort_by(|a, b| a.1.centroid().x.total_cmp(&b.1.centroid().x));
-            aabbs.split_at_mut(x_index_and_cost.0)
-        } else if y_index_and_cost.1 < z_index_and_cost.1 {
-            aabbs.sort_by(|a, b| a.1.centroid().y.total_cmp(&b.1.centroid().y));
-            aabbs.split_at_mut(y_index_and_cost.0)
-        } else {
-            aabbs.split_at_mut(z_index_and_cost.0)
-        };
+    // the best split has to beat keeping everything together. If it doesn't the centroids are
+    // too clustered to tell apart, with identical ones every split costs the same and the first
+    // one peels off a single entity, so the tree would end up as deep as there are entities.
+    let best_cost = x_index_and_cost
+        .1
+        .min(y_index_and_cost.1)
+        .min(z_index_and_cost.1);
+    let sah_improves = best_cost < aabb.total_surface_area() * aabbs.len() as f32;
+
+    let (left, right) = if depth >= MAX_SAH_DEPTH || !sah_improves {
+        median_split(aabbs, &aabb)
+    } else if x_index_and_cost.1 < y_index_and_cost.1 && x_index_and_cost.1 < z_index_and_cost.1 {
+        aabbs.sort_by(|a, b| a.1.centroid().x.total_cmp(&b.1.centroid().x));
+        aabbs.split_at_mut(x_index_and_cost.0)
+    } else if y_index_and_cost.1 < z_index_and_cost.1 {
+        aabbs.sort_by(|a, b| a.1.centroid().y.total_cmp(&b.1.centroid().y));
+        aabbs.split_at_mut(y_index_and_cost.0)
+    } else {
+        aabbs.split_at_mut(z_index_and_cost.0)
+    };
 
-    let left_node = split_node(left);
-    let right_node = split_node(right);
+    let left_node = split_node(left, depth + 1, max_leaf);
+    let right_node = split_node(right, depth + 1, max_leaf);
 
     BvhNode {
-        aabb: merge_aabbs(aabbs),
+        aabb,
         kind: BvhNodeKind::Branch(Box::new(left_node), Box::new(right_node)),
     }
 }
 
+/// Halves `aabbs` along the longest axis of their `bounds`
+fn median_split<'a>(
+    aabbs: &'a mut [(Entity, Aabb)],
+    bounds: &Aabb,
+) -> (&'a mut [(Entity, Aabb)], &'a mut [(Entity, Aabb)]) {
+    let extents = bounds.max - bounds.min;
+    let axis = if extents.x >= extents.y && extents.x >= extents.z {
+        0
+    } else if extents.y >= extents.z {
+        1
+    } else {
+        2
+    };
+
+    aabbs.sort_by(|a, b| a.1.centroid()[axis].total_cmp(&b.1.centroid()[axis]));
+    let middle = aabbs.len() / 2;
+    aabbs.split_at_mut(middle)
+}
+
 fn find_split_index_and_cost(aabbs: &[(Entity, Aabb)]) -> (usize, f32) {
     assert!(aabbs.len() > 1);
     let mut min = (1, f32::INFINITY);
@@ -420,3 +1239,543 @@ impl std::ops::Mul<Vec3> for Aabb {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raymarching::Velocity;
+    use crate::test_support::TestApp;
+    use bevy::ecs::system::SystemState;
+
+    fn unit_box() -> Aabb {
+        Aabb {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        }
+    }
+
+    /// A tree over `aabbs`, entity `i` gets `aabbs[i]`
+    fn tree_of(aabbs: &[Aabb], leaf_size: usize) -> BvhTree {
+        let mut entries: Vec<(Entity, Aabb)> = aabbs
+            .iter()
+            .enumerate()
+            .map(|(i, aabb)| (Entity::from_raw(i as u32), *aabb))
+            .collect();
+        BvhTree {
+            root: split_node(&mut entries, 0, leaf_size),
+        }
+    }
+
+    /// Half-unit boxes on a `size` × `size` grid, one unit apart
+    fn grid(size: usize) -> Vec<Aabb> {
+        (0..size * size)
+            .map(|i| {
+                let center = Vec3::new((i % size) as f32, (i / size) as f32, 0.0);
+                Aabb {
+                    min: center - 0.25,
+                    max: center + 0.25,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fast_blobs_box_extends_in_its_travel_direction() {
+        let swept = SweptAabbs {
+            enabled: true,
+            max_padding: 1.0,
+        };
+        let padded = swept.pad(unit_box(), Vec3::new(-0.5, 0.0, 0.0), Vec3::ZERO);
+        assert_eq!(padded.min, Vec3::splat(-1.0));
+        assert_eq!(padded.max, Vec3::new(1.5, 1.0, 1.0));
+
+        // capped, a teleport across the map stays a sane size
+        let teleported = swept.pad(unit_box(), Vec3::new(0.0, 50.0, 0.0), Vec3::ZERO);
+        assert_eq!(teleported.min, Vec3::new(-1.0, -2.0, -1.0));
+        assert_eq!(swept.padding(Vec2::new(0.0, 50.0), Vec2::ZERO), 1.0);
+    }
+
+    #[test]
+    fn node_count_diagnostic_follows_the_build() {
+        let mut app = TestApp::new();
+        app.world().insert_resource(BvhLeafSize(1));
+        for i in 0..5 {
+            app.spawn_blob(Vec3::new(i as f32 * 2.0, 0.0, 1.0), 0.5);
+        }
+        // the AABBs get added on the first frame and built on the second
+        app.step_frames(2);
+
+        let node_count = |app: &mut TestApp| {
+            app.world()
+                .resource::<Diagnostics>()
+                .get(BVH_NODE_COUNT)
+                .and_then(|diagnostic| diagnostic.value())
+        };
+        // a leaf per blob and the branches joining them
+        assert_eq!(node_count(&mut app), Some(9.0));
+        assert_eq!(app.world().resource::<BvhTree>().node_count(), 9);
+
+        app.spawn_blob(Vec3::new(0.0, 5.0, 1.0), 0.5);
+        app.step_frames(2);
+        assert_eq!(node_count(&mut app), Some(11.0));
+    }
+
+    #[test]
+    fn build_timing_is_only_recorded_while_enabled() {
+        let mut app = TestApp::new();
+        for i in 0..3 {
+            app.spawn_blob(Vec3::new(i as f32, 0.0, 1.0), 0.5);
+        }
+        app.step_frames(2);
+        assert!(app
+            .world()
+            .resource::<BvhBuildTiming>()
+            .last_build
+            .is_none());
+        let measured = |app: &mut TestApp| {
+            app.world()
+                .resource::<Diagnostics>()
+                .get(BVH_BUILD_TIME)
+                .and_then(|diagnostic| diagnostic.value())
+        };
+        assert_eq!(measured(&mut app), None);
+
+        app.world().resource_mut::<BvhBuildTiming>().enabled = true;
+        app.step();
+        assert!(app
+            .world()
+            .resource::<BvhBuildTiming>()
+            .last_build
+            .is_some());
+        assert!(measured(&mut app).is_some());
+    }
+
+    #[test]
+    fn scene_bounds_match_the_root_after_a_build() {
+        let mut app = TestApp::new();
+        app.spawn_blob(Vec3::new(-3.0, 1.0, 1.0), 0.5);
+        app.spawn_blob(Vec3::new(2.0, -4.0, 1.0), 1.0);
+        app.step_frames(2);
+
+        let bounds = app.world().resource::<SceneBounds>().0;
+        assert_eq!(bounds, *app.world().resource::<BvhTree>().root_aabb());
+        assert!(bounds.min.x <= -3.5 && bounds.max.x >= 3.0);
+        assert!(bounds.min.y <= -5.0 && bounds.max.y >= 1.5);
+    }
+
+    #[test]
+    fn bvh_buffer_rotates_through_three_buffers() {
+        let mut buffer = BvhBuffer::default();
+        let indices: Vec<usize> = (0..6).map(|_| buffer.rotate()).collect();
+        assert_eq!(indices, [1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn disabled_sweep_leaves_boxes_alone() {
+        let swept = SweptAabbs::default();
+        assert!(!swept.enabled);
+        assert_eq!(swept.pad(unit_box(), Vec3::X, Vec3::ZERO), unit_box());
+        assert_eq!(swept.padding(Vec2::X, Vec2::ZERO), 0.0);
+        assert_eq!(
+            swept.closest_approach(Vec2::new(-1.0, 0.0), Vec2::X, Vec2::ZERO, Vec2::ZERO),
+            1.0
+        );
+    }
+
+    #[test]
+    fn closest_approach_finds_the_pass_between_ticks() {
+        let swept = SweptAabbs {
+            enabled: true,
+            max_padding: 1.0,
+        };
+        // a went straight through b's center
+        let through = swept.closest_approach(
+            Vec2::new(-0.5, 0.0),
+            Vec2::new(0.5, 0.0),
+            Vec2::ZERO,
+            Vec2::ZERO,
+        );
+        assert!(through.abs() < 1e-6);
+
+        // passing by 0.2 to the side, both moving
+        let beside = swept.closest_approach(
+            Vec2::new(-0.5, 0.2),
+            Vec2::new(0.5, 0.2),
+            Vec2::new(0.5, 0.0),
+            Vec2::new(-0.5, 0.0),
+        );
+        assert!((beside - 0.2).abs() < 1e-6);
+
+        // moving apart, closest at the start
+        let apart = swept.closest_approach(
+            Vec2::new(1.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::ZERO,
+            Vec2::ZERO,
+        );
+        assert!((apart - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn query_sphere_finds_exactly_the_boxes_in_range() {
+        let aabbs = grid(6);
+        let tree = tree_of(&aabbs, 2);
+        for (center, radius) in [
+            (Vec3::new(2.5, 2.5, 0.0), 1.0),
+            (Vec3::new(0.0, 0.0, 0.0), 0.1),
+            (Vec3::new(-3.0, 0.0, 0.0), 2.0),
+            (Vec3::new(1.0, 4.0, 0.5), 2.5),
+        ] {
+            let mut found = Vec::new();
+            tree.query_sphere(center, radius, |entity| found.push(entity.index()));
+            found.sort();
+
+            let expected: Vec<u32> = (0..aabbs.len() as u32)
+                .filter(|&i| aabbs[i as usize].distance_to_point(center) <= radius)
+                .collect();
+            assert_eq!(found, expected, "sphere at {} radius {}", center, radius);
+        }
+    }
+
+    #[test]
+    fn obj_export_writes_a_box_per_node() {
+        let tree = tree_of(&grid(2)[..2], 1);
+        assert_eq!(tree.node_count(), 3);
+
+        let mut obj = Vec::new();
+        tree.write_obj(&mut obj, true).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        // 8 corners and 12 edges per box
+        assert_eq!(count("v "), 24);
+        assert_eq!(count("l "), 36);
+        assert_eq!(count("g "), 2);
+        // lines only point at vertices that exist
+        for line in obj.lines().filter(|line| line.starts_with("l ")) {
+            for index in line[2..].split(' ') {
+                let index: usize = index.parse().unwrap();
+                assert!((1..=24).contains(&index), "{}", line);
+            }
+        }
+
+        let mut ungrouped = Vec::new();
+        tree.write_obj(&mut ungrouped, false).unwrap();
+        assert!(!String::from_utf8(ungrouped).unwrap().contains("g "));
+    }
+
+    #[test]
+    fn non_finite_transforms_stay_out_of_the_tree() {
+        let mut app = TestApp::new();
+        let a = app.spawn_blob(Vec3::new(-2.0, 0.0, 1.0), 0.5);
+        let b = app.spawn_blob(Vec3::new(2.0, 0.0, 1.0), 0.5);
+        let broken = app.spawn_blob(Vec3::new(0.0, 3.0, 1.0), 0.5);
+        app.world()
+            .get_mut::<Transform>(broken)
+            .unwrap()
+            .translation
+            .x = f32::NAN;
+        app.step_frames(2);
+
+        let in_tree = |app: &mut TestApp| {
+            let mut found = Vec::new();
+            let tree = app.world().resource::<BvhTree>();
+            tree.query_sphere(Vec3::ZERO, 100.0, |entity| found.push(entity));
+            found.sort();
+            (found, tree.root_aabb().is_finite())
+        };
+        assert_eq!(in_tree(&mut app), (vec![a, b], true));
+        assert!(app.world().get::<Aabb>(broken).is_none());
+
+        // back in once it recovers, teleported somewhere sane
+        let recovered = Vec3::new(0.0, 3.0, 1.0);
+        app.world().entity_mut(broken).insert((
+            Transform::from_translation(recovered).with_scale(Blob::default().scale()),
+            SimulatedPosition {
+                previous: recovered,
+                current: recovered,
+            },
+        ));
+        app.step_frames(2);
+        assert_eq!(in_tree(&mut app), (vec![a, b, broken], true));
+    }
+
+    fn entities_in(tree: &BvhTree) -> Vec<u32> {
+        let mut found = Vec::new();
+        tree.query_sphere(Vec3::ZERO, f32::MAX, |entity| found.push(entity.index()));
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn insert_and_remove_keep_the_tree_covering_everything() {
+        let mut aabbs = grid(4);
+        let mut tree = tree_of(&aabbs, 2);
+
+        for i in 0..4 {
+            let center = Vec3::new(10.0 + i as f32, -3.0, 0.5);
+            let aabb = Aabb {
+                min: center - 0.5,
+                max: center + 0.5,
+            };
+            tree.insert(Entity::from_raw(aabbs.len() as u32), aabb);
+            aabbs.push(aabb);
+            tree.validate().unwrap();
+            assert!(tree.root_aabb().contains(&aabb));
+        }
+        assert_eq!(entities_in(&tree), (0..20).collect::<Vec<u32>>());
+
+        for i in [3, 17, 0, 9, 19] {
+            assert!(tree.remove(Entity::from_raw(i)));
+            tree.validate().unwrap();
+        }
+        assert!(!tree.remove(Entity::from_raw(3)), "removed twice");
+        assert!(!tree.remove(Entity::from_raw(99)));
+        let left: Vec<u32> = (0..20).filter(|i| ![3, 17, 0, 9, 19].contains(i)).collect();
+        assert_eq!(entities_in(&tree), left);
+
+        // the last entity stays, the tree can't be empty
+        for &i in &left[1..] {
+            assert!(tree.remove(Entity::from_raw(i)));
+        }
+        assert!(!tree.remove(Entity::from_raw(left[0])));
+        assert_eq!(entities_in(&tree), [left[0]]);
+    }
+
+    fn gpu_node(left: i32, right: i32) -> GpuNode {
+        GpuNode {
+            min: Vec3::ZERO,
+            max: Vec3::ONE,
+            left,
+            right,
+        }
+    }
+
+    #[test]
+    fn clamped_gpu_nodes_stay_valid() {
+        let node_size = GpuNode::min_size().get() as u32;
+        assert_eq!(max_gpu_nodes(node_size * 10), 10);
+        assert_eq!(max_gpu_nodes(node_size * 10 + node_size / 2), 10);
+
+        // root with a two leaf branch on the left and a three entity leaf on the right
+        let mut nodes = vec![
+            gpu_node(1, 4),
+            gpu_node(2, 3),
+            gpu_node(-1, 0),
+            gpu_node(-1, 1),
+            gpu_node(-3, 2),
+            gpu_node(-1, 3),
+            gpu_node(-1, 4),
+        ];
+        assert!(GpuTree {
+            tree: nodes.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(!clamp_gpu_nodes(&mut nodes, 7));
+        assert_eq!(nodes.len(), 7);
+
+        // the right leaf loses two of its entities
+        let mut cut = nodes.clone();
+        assert!(clamp_gpu_nodes(&mut cut, 5));
+        assert_eq!(cut.len(), 5);
+        assert_eq!((cut[4].left, cut[4].right), (-1, 2));
+        GpuTree { tree: cut }.validate().unwrap();
+
+        // the root's children are cut off, it turns into an empty leaf
+        let mut cut = nodes;
+        assert!(clamp_gpu_nodes(&mut cut, 1));
+        assert_eq!((cut[0].left, cut[0].right), (-1, -1));
+        GpuTree { tree: cut }.validate().unwrap();
+    }
+
+    #[test]
+    fn rebuilding_doesnt_leave_entities_behind() {
+        let mut app = TestApp::new();
+        for i in 0..3 {
+            let blob = app.spawn_blob(Vec3::new(i as f32 * 3.0, 0.0, 1.0), 0.5);
+            // moving blobs rebuild the tree every frame
+            app.world()
+                .entity_mut(blob)
+                .insert(Velocity(Vec2::new(0.0, 3.0)));
+        }
+        app.step_frames(2);
+        let entity_count = app.world().entities().len();
+
+        app.step_frames(20);
+        assert_eq!(app.world().entities().len(), entity_count);
+        let mut aabbs = app.world().query::<&Aabb>();
+        assert_eq!(aabbs.iter(app.world()).count(), 3);
+    }
+
+    fn depth(node: &BvhNode) -> usize {
+        match &node.kind {
+            BvhNodeKind::Leaf(_) => 1,
+            BvhNodeKind::Branch(left, right) => 1 + depth(left).max(depth(right)),
+        }
+    }
+
+    #[test]
+    fn identical_centroids_keep_the_tree_shallow() {
+        let aabbs = vec![unit_box(); 1000];
+        let tree = tree_of(&aabbs, 4);
+
+        // halving every level, 1000 entities fit into leaves of 4 in about 8 levels
+        assert!(depth(&tree.root) <= 10, "depth {}", depth(&tree.root));
+        assert!(tree.iter_nodes().all(|(_, node)| match node {
+            BvhNodeRef::Leaf(entries) => entries.len() <= 4,
+            BvhNodeRef::Branch => true,
+        }));
+        assert_eq!(entities_in(&tree), (0..1000).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn stretched_blob_keeps_a_square_aabb() {
+        let mut app = App::new();
+        app.init_resource::<SweptAabbs>()
+            .add_system(update_bvh_aabb);
+        let blob = Blob {
+            size: 0.8,
+            ..default()
+        };
+        let scale = blob.scale();
+        let entity = app
+            .world
+            .spawn((
+                Transform::from_xyz(1.0, 2.0, 1.0).with_scale(scale * Vec3::new(1.6, 0.6, 1.0)),
+                blob,
+                CalculateBvh,
+                LocalBoundingBox::blob(),
+            ))
+            .id();
+
+        app.update();
+        let aabb = *app.world.get::<Aabb>(entity).unwrap();
+        let expected = Aabb {
+            min: Vec3::new(1.0, 2.0, 1.0) - scale,
+            max: Vec3::new(1.0, 2.0, 1.0) + scale,
+        };
+        assert!(aabb.min.abs_diff_eq(expected.min, 1e-5), "{:?}", aabb);
+        assert!(aabb.max.abs_diff_eq(expected.max, 1e-5), "{:?}", aabb);
+    }
+
+    #[test]
+    fn validate_catches_loose_boxes_and_duplicates() {
+        let tree = tree_of(&grid(4), 2);
+        tree.validate().unwrap();
+
+        // a parent box that no longer covers its children
+        let mut shrunk = tree.clone();
+        shrunk.root.aabb.max.x -= 1.0;
+        assert!(shrunk.validate().unwrap_err().contains("doesn't contain"));
+
+        // the same entity in two leaves
+        let mut duplicated = tree.clone();
+        let BvhNodeKind::Branch(left, right) = &mut duplicated.root.kind else {
+            panic!("16 boxes in leaves of 2 make a branch");
+        };
+        let first = first_leaf_entry(left);
+        first_leaf_entries(right).push(first);
+        assert!(duplicated
+            .validate()
+            .unwrap_err()
+            .contains("more than one leaf"));
+    }
+
+    fn first_leaf_entry(node: &BvhNode) -> (Entity, Aabb) {
+        match &node.kind {
+            BvhNodeKind::Leaf(entries) => entries[0],
+            BvhNodeKind::Branch(left, _) => first_leaf_entry(left),
+        }
+    }
+
+    fn first_leaf_entries(node: &mut BvhNode) -> &mut Vec<(Entity, Aabb)> {
+        match &mut node.kind {
+            BvhNodeKind::Leaf(entries) => entries,
+            BvhNodeKind::Branch(left, _) => first_leaf_entries(left),
+        }
+    }
+
+    #[test]
+    fn leaves_hold_at_most_the_leaf_size() {
+        for leaf_size in 1..=MAX_LEAF_ENTITIES {
+            let tree = tree_of(&grid(6), leaf_size);
+            tree.validate().unwrap();
+            assert!(tree.iter_nodes().all(|(_, node)| match node {
+                BvhNodeRef::Leaf(entries) => entries.len() <= leaf_size,
+                BvhNodeRef::Branch => true,
+            }));
+            assert_eq!(entities_in(&tree), (0..36).collect::<Vec<u32>>());
+        }
+
+        assert_eq!(BvhLeafSize(0).get(), 1);
+        assert_eq!(BvhLeafSize(100).get(), MAX_LEAF_ENTITIES);
+    }
+
+    #[test]
+    fn gpu_leaves_list_every_buffer_index_once() {
+        let mut world = World::new();
+        let mut entries: Vec<(Entity, Aabb)> = grid(4)
+            .into_iter()
+            .enumerate()
+            .map(|(i, aabb)| (world.spawn(EntityBufferIndex(i as i32)).id(), aabb))
+            .collect();
+        let root = split_node(&mut entries, 0, 3);
+
+        let mut state = SystemState::<Query<&EntityBufferIndex>>::new(&mut world);
+        let mut nodes = Vec::new();
+        push_node_to_buffer(&root, &mut nodes, &state.get(&world));
+        let gpu_tree = GpuTree { tree: nodes };
+        gpu_tree.validate().unwrap();
+
+        // walked the way the shader does, a leaf's entities are on it and the nodes after it
+        fn leaf_indices(nodes: &[GpuNode], i: usize, found: &mut Vec<i32>) {
+            let node = &nodes[i];
+            if node.left >= 0 {
+                leaf_indices(nodes, node.left as usize, found);
+                leaf_indices(nodes, node.right as usize, found);
+            } else {
+                let count = node.left.unsigned_abs() as usize;
+                assert!(count <= 3, "leaf {} has {} entities", i, count);
+                found.extend(nodes[i..i + count].iter().map(|node| node.right));
+            }
+        }
+        let mut indices = Vec::new();
+        leaf_indices(&gpu_tree.tree, 0, &mut indices);
+        indices.sort();
+        assert_eq!(indices, (0..16).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn iteration_visits_every_node_once() {
+        /// Every node's AABB, depth first with the left child first
+        fn walk<'a>(node: &'a BvhNode, found: &mut Vec<&'a Aabb>) {
+            found.push(&node.aabb);
+            if let BvhNodeKind::Branch(left, right) = &node.kind {
+                walk(left, found);
+                walk(right, found);
+            }
+        }
+
+        let tree = tree_of(&grid(5), 2);
+        let mut expected = Vec::new();
+        walk(&tree.root, &mut expected);
+
+        let visited: Vec<&Aabb> = tree.iter_nodes().map(|(aabb, _)| aabb).collect();
+        assert_eq!(visited.len(), expected.len());
+        assert!(visited
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| std::ptr::eq(*a, *b)));
+
+        let mut entities: Vec<u32> = tree
+            .iter_nodes()
+            .flat_map(|(_, node)| match node {
+                BvhNodeRef::Leaf(entries) => entries.to_vec(),
+                BvhNodeRef::Branch => Vec::new(),
+            })
+            .map(|(entity, _)| entity.index())
+            .collect();
+        entities.sort();
+        assert_eq!(entities, (0..25).collect::<Vec<u32>>());
+    }
+}
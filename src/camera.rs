@@ -1,25 +1,359 @@
-//! Pan orbit camera
+//! Follow camera, with free-fly and pan orbit modes
 use bevy::core_pipeline::clear_color::ClearColorConfig;
 use bevy::core_pipeline::core_3d::Camera3dDepthLoadOp;
 use bevy::input::mouse::{MouseMotion, MouseWheel};
 use bevy::prelude::*;
 use bevy::render::camera::Projection;
+use bevy::window::WindowResized;
 use bevy_egui::{egui, EguiContext, EguiContexts};
 
+use crate::simulation::SimulationSet;
+use crate::{Arena, WORLD_UP};
+use smooth_bevy_cameras::{LookTransform, Smoother};
+
 pub struct CameraPlugin;
 
+/// Anything past this and the perspective projection starts to degenerate
+const MAX_FOV_DEGREES: f32 = 150.0;
+const MIN_FOV_DEGREES: f32 = 10.0;
+
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(pan_orbit_camera).add_system(fov_slider);
+        app.init_resource::<CameraSettings>()
+            .init_resource::<CameraBounds>()
+            .init_resource::<CameraMode>()
+            .init_resource::<FreeFlyPose>()
+            .init_resource::<CameraTransition>()
+            .add_system(toggle_camera_mode)
+            .add_system(free_fly_camera.after(toggle_camera_mode))
+            .add_system(
+                blend_camera_transition
+                    .after(free_fly_camera)
+                    .after(SimulationSet::Interpolate),
+            )
+            .add_system(pan_orbit_camera)
+            .add_system(fov_slider)
+            .add_system(apply_follow_smoothing.after(fov_slider));
+    }
+}
+
+/// User preferences for the pan orbit camera controls
+#[derive(Resource)]
+pub struct CameraSettings {
+    /// Scroll up zooms out instead of in
+    pub invert_scroll: bool,
+    pub orbit_sensitivity: f32,
+    pub pan_sensitivity: f32,
+    /// Lag weight of the follow camera's `Smoother`, 0 snaps straight to the player and values
+    /// towards [`MAX_FOLLOW_SMOOTHING`] float behind
+    pub follow_smoothing: f32,
+    /// Seconds the camera takes to move over when switching [`CameraMode`]
+    pub transition_duration: f32,
+    /// Units per second the free-fly camera moves at
+    pub free_fly_speed: f32,
+}
+
+impl CameraSettings {
+    /// Zoom steps for `scroll` lines of mouse wheel, see [`PanOrbitCamera::zoom`]
+    pub fn zoom_steps(&self, scroll: f32) -> f32 {
+        if self.invert_scroll {
+            -scroll
+        } else {
+            scroll
+        }
+    }
+}
+
+/// At 1.0 the follow camera would never move
+pub const MAX_FOLLOW_SMOOTHING: f32 = 0.95;
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            invert_scroll: false,
+            orbit_sensitivity: 1.0,
+            pan_sensitivity: 1.0,
+            follow_smoothing: 0.6,
+            transition_duration: 0.5,
+            free_fly_speed: 6.0,
+        }
     }
 }
 
-/// Tags an entity as capable of panning and orbiting.
+/// Keeps the camera from wandering off, can be turned off for debugging
+#[derive(Resource)]
+pub struct CameraBounds {
+    pub enabled: bool,
+    /// How far past the edge of the `Arena` the focus point can go
+    pub margin: f32,
+    /// Lowest the camera eye can go, the floor is at z=0
+    pub min_height: f32,
+}
+
+impl Default for CameraBounds {
+    fn default() -> Self {
+        CameraBounds {
+            enabled: true,
+            margin: 2.0,
+            min_height: 0.2,
+        }
+    }
+}
+
+impl CameraBounds {
+    /// Pulls `focus` back within `margin` of the arena, horizontally
+    pub fn clamp_focus(&self, focus: Vec3, arena: &Arena) -> Vec3 {
+        if !self.enabled {
+            return focus;
+        }
+
+        arena.clamp_inset(focus, -self.margin)
+    }
+
+    /// Keeps `eye` above the floor
+    pub fn clamp_eye(&self, eye: Vec3) -> Vec3 {
+        if !self.enabled {
+            return eye;
+        }
+
+        eye.truncate().extend(eye.z.max(self.min_height))
+    }
+}
+
+/// What moves the camera's `LookTransform`. F toggles free-fly and O the pan orbit camera,
+/// pressing the same key again goes back to following.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Behind the player, see `follow_player`
+    #[default]
+    Follow,
+    /// Flies with the arrow keys, Page Up and Page Down, and looks around while the right mouse
+    /// button is held
+    FreeFly,
+    /// Orbits around a focus point, see `pan_orbit_camera`
+    Orbit,
+}
+
+impl CameraMode {
+    /// The mode after pressing the key for `mode`
+    pub fn toggled(self, mode: CameraMode) -> CameraMode {
+        if self == mode {
+            CameraMode::Follow
+        } else {
+            mode
+        }
+    }
+}
+
+/// Run condition for systems that move the camera in [`CameraMode::Follow`]
+pub fn following(mode: Res<CameraMode>) -> bool {
+    *mode == CameraMode::Follow
+}
+
+/// Where a `LookTransform` is and what it looks at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub eye: Vec3,
+    pub target: Vec3,
+}
+
+impl CameraPose {
+    pub fn of(look: &LookTransform) -> Self {
+        CameraPose {
+            eye: look.eye,
+            target: look.target,
+        }
+    }
+
+    pub fn lerp(self, other: CameraPose, t: f32) -> CameraPose {
+        CameraPose {
+            eye: self.eye.lerp(other.eye, t),
+            target: self.target.lerp(other.target, t),
+        }
+    }
+}
+
+/// The free-fly camera's pose, kept while following so switching back returns to it. None until
+/// free-fly is used the first time, it then starts wherever the camera was.
+#[derive(Resource, Default)]
+pub struct FreeFlyPose(pub Option<CameraPose>);
+
+/// Eases the camera over from where it was when the [`CameraMode`] changed, instead of letting
+/// it snap to the new mode's pose
+#[derive(Resource, Default)]
+pub struct CameraTransition {
+    from: Option<CameraPose>,
+    /// Seconds since the switch
+    elapsed: f32,
+}
+
+impl CameraTransition {
+    pub fn start(&mut self, from: CameraPose) {
+        self.from = Some(from);
+        self.elapsed = 0.0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.from.is_some()
+    }
+
+    /// The pose `elapsed` seconds into a transition of `duration` seconds towards `to`, eased in
+    /// and out. Just `to` without a transition, or once it's over.
+    pub fn blend(&self, to: CameraPose, duration: f32) -> CameraPose {
+        let Some(from) = self.from else {
+            return to;
+        };
+        let t = (self.elapsed / duration.max(f32::EPSILON)).clamp(0.0, 1.0);
+        // smoothstep
+        from.lerp(to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+fn toggle_camera_mode(
+    keys: Res<Input<KeyCode>>,
+    mut mode: ResMut<CameraMode>,
+    mut free_fly: ResMut<FreeFlyPose>,
+    mut transition: ResMut<CameraTransition>,
+    mut cameras: Query<(&LookTransform, Option<&mut PanOrbitCamera>)>,
+) {
+    let pressed = if keys.just_pressed(KeyCode::F) {
+        CameraMode::FreeFly
+    } else if keys.just_pressed(KeyCode::O) {
+        CameraMode::Orbit
+    } else {
+        return;
+    };
+    let Ok((look, pan_orbit)) = cameras.get_single_mut() else {
+        return;
+    };
+
+    // mid-transition this is the blended pose, so switching again doesn't jump either
+    let current = CameraPose::of(look);
+    transition.start(current);
+    *mode = mode.toggled(pressed);
+    match *mode {
+        CameraMode::FreeFly => {
+            free_fly.0.get_or_insert(current);
+        }
+        // orbits around whatever the camera was looking at
+        CameraMode::Orbit => {
+            if let Some(mut pan_orbit) = pan_orbit {
+                pan_orbit.look_from(current);
+            }
+        }
+        CameraMode::Follow => {}
+    }
+}
+
+fn free_fly_camera(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mode: Res<CameraMode>,
+    mut free_fly: ResMut<FreeFlyPose>,
+    mut cameras: Query<&mut LookTransform>,
+    settings: Res<CameraSettings>,
+    bounds: Res<CameraBounds>,
+    time: Res<Time>,
+) {
+    let look_delta: Vec2 = mouse_motion.iter().map(|event| event.delta).sum();
+    if *mode != CameraMode::FreeFly {
+        return;
+    }
+    let Some(pose) = free_fly.0.as_mut() else {
+        return;
+    };
+
+    let forward = (pose.target - pose.eye)
+        .truncate()
+        .normalize_or_zero()
+        .extend(0.0);
+    let right = forward.cross(WORLD_UP);
+    let mut movement = Vec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::Up, forward),
+        (KeyCode::Down, -forward),
+        (KeyCode::Right, right),
+        (KeyCode::Left, -right),
+        (KeyCode::PageUp, WORLD_UP),
+        (KeyCode::PageDown, -WORLD_UP),
+    ] {
+        if keys.pressed(key) {
+            movement += direction;
+        }
+    }
+    // real time, so flying around works the same in slow motion
+    let step = movement.normalize_or_zero() * settings.free_fly_speed * time.raw_delta_seconds();
+    let eye = bounds.clamp_eye(pose.eye + step);
+    pose.target += eye - pose.eye;
+    pose.eye = eye;
+
+    if mouse_buttons.pressed(MouseButton::Right) && look_delta != Vec2::ZERO {
+        let angles = -look_delta * 0.005 * settings.orbit_sensitivity;
+        let view = pose.target - pose.eye;
+        let yawed = Quat::from_axis_angle(WORLD_UP, angles.x) * view;
+        let pitched =
+            Quat::from_axis_angle(yawed.cross(WORLD_UP).normalize_or_zero(), angles.y) * yawed;
+        // looking straight up or down would leave the yaw axis undefined
+        let view = if pitched.normalize_or_zero().dot(WORLD_UP).abs() < 0.99 {
+            pitched
+        } else {
+            yawed
+        };
+        pose.target = pose.eye + view;
+    }
+
+    for mut look in cameras.iter_mut() {
+        look.eye = pose.eye;
+        look.target = pose.target;
+    }
+}
+
+/// Runs after whichever system set the new mode's pose this frame and blends it with the pose
+/// the camera had when the mode changed
+pub(crate) fn blend_camera_transition(
+    mut transition: ResMut<CameraTransition>,
+    mut cameras: Query<&mut LookTransform>,
+    settings: Res<CameraSettings>,
+    time: Res<Time>,
+) {
+    if !transition.is_active() {
+        return;
+    }
+
+    for mut look in cameras.iter_mut() {
+        let pose = transition.blend(CameraPose::of(&look), settings.transition_duration);
+        look.eye = pose.eye;
+        look.target = pose.target;
+    }
+
+    transition.elapsed += time.raw_delta_seconds();
+    if transition.elapsed >= settings.transition_duration {
+        transition.from = None;
+    }
+}
+
+/// Tags an entity as capable of panning and orbiting, in [`CameraMode::Orbit`]. Like the free-fly
+/// camera it keeps its own pose and moves the `LookTransform` to it.
 #[derive(Component)]
 pub struct PanOrbitCamera {
     /// The "focus point" to orbit around. It is automatically updated when panning the camera
     pub focus: Vec3,
     pub radius: f32,
+    /// The camera sits `radius` along this rotation's local z axis from `focus`
+    pub rotation: Quat,
+    /// Closest the camera can zoom in. Don't let this reach zero or you get stuck
+    pub min_radius: f32,
+    /// Furthest the camera can zoom out
+    pub max_radius: f32,
+    /// How much of the current radius one scroll step zooms by
+    pub zoom_step: f32,
+    /// Lowest the camera can orbit to, in radians of [`elevation`]. Negative looks up from
+    /// under the floor.
+    pub min_pitch: f32,
+    /// Highest the camera can orbit to, in radians of [`elevation`]. Keep it below 90 degrees
+    /// or orbiting can go over the top and flip the view.
+    pub max_pitch: f32,
     pub upside_down: bool,
     pub auto_rotate: bool,
 }
@@ -29,21 +363,116 @@ impl Default for PanOrbitCamera {
         PanOrbitCamera {
             focus: Vec3::ZERO,
             radius: 5.0,
+            rotation: Quat::IDENTITY,
+            min_radius: 2.0,
+            max_radius: 175.0,
+            zoom_step: 0.2,
+            min_pitch: 5f32.to_radians(),
+            max_pitch: 85f32.to_radians(),
             upside_down: false,
             auto_rotate: false,
         }
     }
 }
 
+impl PanOrbitCamera {
+    /// `elevation` kept within `min_pitch..=max_pitch`
+    pub fn clamp_pitch(&self, elevation: f32) -> f32 {
+        elevation.clamp(self.min_pitch, self.max_pitch.max(self.min_pitch))
+    }
+
+    /// Turns by `yaw` around the world up axis and raises the camera by `pitch`, in radians.
+    /// Stops at the pitch limits instead of going over the top.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let current = elevation(self.rotation);
+        let target = self.clamp_pitch(current + pitch);
+        // rotate around the world up axis, then around the local x axis
+        self.rotation = Quat::from_axis_angle(WORLD_UP, -yaw)
+            * self.rotation
+            * Quat::from_rotation_x(current - target);
+    }
+
+    /// Moves the focus along the ground plane by `pan`, already scaled to the view. Garbage in,
+    /// like a NaN from a degenerate projection, leaves it where it is.
+    pub fn pan(&mut self, pan: Vec2) {
+        if !pan.is_finite() {
+            return;
+        }
+        // vertical mouse movement goes along the camera's forward direction flattened onto the
+        // ground, which unlike the camera's local up doesn't vanish when looking at the horizon
+        let camera_right = self.rotation * Vec3::X;
+        let right = camera_right * -pan.x;
+        let up = WORLD_UP.cross(camera_right).normalize_or_zero() * pan.y;
+        // make panning proportional to distance away from focus point
+        self.focus += (right + up) * self.radius;
+    }
+
+    /// Zooms in by `scroll` steps, out when negative, staying within the radius limits
+    pub fn zoom(&mut self, scroll: f32) {
+        self.radius -= scroll * self.radius * self.zoom_step;
+        self.radius = self.radius.clamp(self.min_radius, self.max_radius);
+    }
+
+    /// Where the camera is, `radius` away from `focus`
+    pub fn eye(&self) -> Vec3 {
+        self.focus + self.rotation * Vec3::new(0.0, 0.0, self.radius)
+    }
+
+    /// Picks up orbiting from wherever `pose` has the camera
+    pub fn look_from(&mut self, pose: CameraPose) {
+        self.focus = pose.target;
+        self.radius = pose
+            .eye
+            .distance(pose.target)
+            .clamp(self.min_radius, self.max_radius);
+        self.rotation = Transform::from_translation(pose.eye)
+            .looking_at(pose.target, WORLD_UP)
+            .rotation;
+    }
+}
+
+/// Angle of a camera with `rotation` above the ground plane as seen from its focus point, in
+/// radians. 0 looks along the floor, 90 degrees looks straight down.
+pub fn elevation(rotation: Quat) -> f32 {
+    // the camera sits along its local z axis from the focus
+    (rotation * Vec3::Z).dot(WORLD_UP).clamp(-1.0, 1.0).asin()
+}
+
 fn fov_slider(
     mut query: Query<(&mut Projection, &mut PanOrbitCamera)>,
+    mut settings: ResMut<CameraSettings>,
+    mut bounds: ResMut<CameraBounds>,
     mut egui_contexts: EguiContexts,
 ) {
     egui::Window::new("Camera").show(egui_contexts.ctx_mut(), |ui| {
+        ui.add(egui::Checkbox::new(
+            &mut settings.invert_scroll,
+            "Invert scroll",
+        ));
+        ui.add(
+            egui::Slider::new(&mut settings.orbit_sensitivity, 0.1..=5.0).text("Orbit sensitivity"),
+        );
+        ui.add(egui::Slider::new(&mut settings.pan_sensitivity, 0.1..=5.0).text("Pan sensitivity"));
+        ui.add(
+            egui::Slider::new(&mut settings.follow_smoothing, 0.0..=MAX_FOLLOW_SMOOTHING)
+                .text("Follow smoothing"),
+        );
+        ui.add(
+            egui::Slider::new(&mut settings.transition_duration, 0.0..=2.0)
+                .text("Mode switch time"),
+        );
+        ui.add(egui::Checkbox::new(&mut bounds.enabled, "Keep in arena"));
+
         for (mut projection, mut pan_orbit) in query.iter_mut() {
             if let Projection::Perspective(ref mut pers) = &mut *projection {
-                let mut temp = pers.fov.to_degrees();
-                ui.add(egui::Slider::new(&mut temp, 10.0..=180.0));
+                let mut temp = pers
+                    .fov
+                    .to_degrees()
+                    .clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES);
+                ui.add(egui::Slider::new(
+                    &mut temp,
+                    MIN_FOV_DEGREES..=MAX_FOV_DEGREES,
+                ));
                 pers.fov = temp.to_radians();
             }
 
@@ -51,19 +480,54 @@ fn fov_slider(
                 &mut pan_orbit.auto_rotate,
                 "Auto rotate",
             ));
+
+            let mut min_pitch = pan_orbit.min_pitch.to_degrees();
+            let mut max_pitch = pan_orbit.max_pitch.to_degrees();
+            ui.add(egui::Slider::new(&mut min_pitch, -89.0..=89.0).text("Min pitch"));
+            ui.add(egui::Slider::new(&mut max_pitch, -89.0..=89.0).text("Max pitch"));
+            // only touch the component on actual edits, so change detection stays quiet
+            if min_pitch != pan_orbit.min_pitch.to_degrees()
+                || max_pitch != pan_orbit.max_pitch.to_degrees()
+            {
+                pan_orbit.min_pitch = min_pitch.to_radians();
+                pan_orbit.max_pitch = max_pitch.to_radians();
+            }
         }
     });
 }
 
+fn apply_follow_smoothing(mut smoothers: Query<&mut Smoother>, settings: Res<CameraSettings>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let weight = settings.follow_smoothing.clamp(0.0, MAX_FOLLOW_SMOOTHING);
+    for mut smoother in smoothers.iter_mut() {
+        smoother.set_lag_weight(weight);
+    }
+}
+
 /// Pan the camera with middle mouse click, zoom with scroll wheel, orbit with right mouse click.
 fn pan_orbit_camera(
     windows: Query<&Window>,
     mut ev_motion: EventReader<MouseMotion>,
     mut ev_scroll: EventReader<MouseWheel>,
+    mut ev_resized: EventReader<WindowResized>,
     input_mouse: Res<Input<MouseButton>>,
-    mut query: Query<(&mut PanOrbitCamera, &mut Transform, &Projection)>,
+    mut query: Query<(&mut PanOrbitCamera, &mut LookTransform, &Projection)>,
+    mode: Res<CameraMode>,
+    settings: Res<CameraSettings>,
+    bounds: Res<CameraBounds>,
+    arena: Res<Arena>,
     time: Res<Time>,
 ) {
+    if *mode != CameraMode::Orbit {
+        // don't let the motion pile up for when orbiting starts
+        ev_motion.clear();
+        ev_scroll.clear();
+        return;
+    }
+
     // change input mapping for orbit and panning here
     let orbit_button = MouseButton::Right;
     let pan_button = MouseButton::Middle;
@@ -86,22 +550,30 @@ fn pan_orbit_camera(
     for ev in ev_scroll.iter() {
         scroll += ev.y;
     }
+    let scroll = settings.zoom_steps(scroll);
+    rotation_move *= settings.orbit_sensitivity;
+    pan *= settings.pan_sensitivity;
     if input_mouse.just_released(orbit_button) || input_mouse.just_pressed(orbit_button) {
         orbit_button_changed = true;
     }
+    let resized_to = ev_resized
+        .iter()
+        .last()
+        .map(|ev| Vec2::new(ev.width, ev.height));
 
-    for (mut pan_orbit, mut transform, projection) in query.iter_mut() {
+    for (mut pan_orbit, mut look, projection) in query.iter_mut() {
         if orbit_button_changed {
             // only check for upside down when orbiting started or ended this frame
             // if the camera is "upside" down, panning horizontally would be inverted, so invert the input to make it correct
-            let up = transform.rotation * Vec3::Z;
-            pan_orbit.upside_down = up.z <= 0.0;
+            let up = pan_orbit.rotation * Vec3::Y;
+            pan_orbit.upside_down = up.dot(WORLD_UP) <= 0.0;
         }
 
         if pan_orbit.auto_rotate {
-            rotation_move += Vec2::new(1., 0.) * time.delta_seconds() * 5.;
+            rotation_move += Vec2::new(1., 0.) * time.raw_delta_seconds() * 5.;
         }
-        let window = get_primary_window_size(windows.get_single().unwrap());
+        let window =
+            resized_to.unwrap_or_else(|| get_primary_window_size(windows.get_single().unwrap()));
 
         let mut any = false;
         if rotation_move.length_squared() > 0.0 {
@@ -116,44 +588,318 @@ fn pan_orbit_camera(
                 }
             };
             let delta_y = rotation_move.y / window.y * std::f32::consts::PI;
-            let yaw = Quat::from_rotation_z(-delta_x);
-            let pitch = Quat::from_rotation_x(-delta_y);
-            transform.rotation = yaw * transform.rotation; // rotate around global y axis
-            transform.rotation = transform.rotation * pitch; // rotate around local x axis
+            pan_orbit.orbit(delta_x, delta_y);
         } else if pan.length_squared() > 0.0 {
             any = true;
             // make panning distance independent of resolution and FOV,
             if let Projection::Perspective(projection) = projection {
-                pan *= Vec2::new(projection.fov * projection.aspect_ratio, projection.fov) / window;
+                if let Some(scale) = pan_scale(projection.fov, window) {
+                    pan *= scale;
+                }
             }
-            // translate by local axes
-            let right = transform.rotation * Vec3::X * -pan.x;
-            let mut up: Vec3 = transform.rotation * Vec3::Y * pan.y;
-            up.z = 0.;
-            // up = up.normalize_or_zero();
-            // let right = Vec3::X * pan.x;
-            // let up = Vec3::Y * -pan.y;
-            // make panning proportional to distance away from focus point
-            let translation = (right + up) * pan_orbit.radius;
-            pan_orbit.focus += translation;
+            pan_orbit.pan(pan);
         } else if scroll.abs() > 0.0 {
             any = true;
-            pan_orbit.radius -= scroll * pan_orbit.radius * 0.2;
-            // dont allow zoom to reach zero or you get stuck
-            pan_orbit.radius = pan_orbit.radius.clamp(2.0, 175.0);
+            pan_orbit.zoom(scroll);
         }
 
         if any {
-            // emulating parent/child to make the yaw/y-axis rotation behave like a turntable
-            // parent = x and y rotation
-            // child = z-offset
-            let rot_matrix = Mat3::from_quat(transform.rotation);
-            transform.translation =
-                pan_orbit.focus + rot_matrix.mul_vec3(Vec3::new(0.0, 0.0, pan_orbit.radius));
+            pan_orbit.focus = bounds.clamp_focus(pan_orbit.focus, &arena);
         }
+        // every frame, like the other modes, a mode transition blends from whatever is here
+        look.eye = bounds.clamp_eye(pan_orbit.eye());
+        look.target = pan_orbit.focus;
     }
 }
 
 fn get_primary_window_size(window: &Window) -> Vec2 {
     Vec2::new(window.width() as f32, window.height() as f32)
 }
+
+/// Scales mouse movement in pixels to pan distance for the given vertical FOV.
+///
+/// The aspect ratio comes from the window size, the one in the `Projection` only gets updated
+/// after a resize in `PostUpdate`, a frame late for us.
+pub fn pan_scale(fov: f32, window: Vec2) -> Option<Vec2> {
+    if !fov.is_finite() || fov <= f32::EPSILON || window.min_element() <= 0.0 {
+        return None;
+    }
+
+    let aspect_ratio = window.x / window.y;
+    Some(Vec2::new(fov * aspect_ratio, fov) / window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orbiting_at_45_degrees() -> PanOrbitCamera {
+        let mut pan_orbit = PanOrbitCamera::default();
+        pan_orbit.look_from(CameraPose {
+            eye: Vec3::new(0.0, -5.0, 5.0),
+            target: Vec3::ZERO,
+        });
+        pan_orbit
+    }
+
+    #[test]
+    fn dragging_far_in_pitch_stops_at_the_limit() {
+        let mut pan_orbit = orbiting_at_45_degrees();
+        assert!((elevation(pan_orbit.rotation) - 45f32.to_radians()).abs() < 1e-4);
+        assert!(pan_orbit.eye().distance(Vec3::new(0.0, -5.0, 5.0)) < 1e-4);
+
+        for _ in 0..20 {
+            pan_orbit.orbit(0.0, 0.5);
+        }
+        assert!((elevation(pan_orbit.rotation) - pan_orbit.max_pitch).abs() < 1e-4);
+        // still on the near side of the focus, it didn't go over the top
+        assert!(pan_orbit.eye().y < 0.0);
+
+        for _ in 0..20 {
+            pan_orbit.orbit(0.0, -0.5);
+        }
+        assert!((elevation(pan_orbit.rotation) - pan_orbit.min_pitch).abs() < 1e-4);
+        assert!(pan_orbit.eye().z > 0.0);
+    }
+
+    #[test]
+    fn extreme_fov_never_puts_nan_in_the_transform() {
+        let window = Vec2::new(1280.0, 720.0);
+        for fov in [
+            0.0,
+            f32::EPSILON,
+            MAX_FOV_DEGREES.to_radians(),
+            std::f32::consts::PI,
+            f32::NAN,
+            f32::INFINITY,
+        ] {
+            let mut pan_orbit = orbiting_at_45_degrees();
+            let mut pan = Vec2::new(300.0, -200.0);
+            if let Some(scale) = pan_scale(fov, window) {
+                pan *= scale;
+            }
+            pan_orbit.pan(pan);
+
+            let transform =
+                Transform::from_translation(pan_orbit.eye()).looking_at(pan_orbit.focus, WORLD_UP);
+            assert!(transform.translation.is_finite(), "fov {}", fov);
+            assert!(transform.rotation.is_finite(), "fov {}", fov);
+        }
+        assert_eq!(pan_scale(f32::NAN, window), None);
+        assert_eq!(pan_scale(0.0, window), None);
+    }
+
+    #[test]
+    fn scrolling_out_stops_at_the_configured_max() {
+        let mut pan_orbit = PanOrbitCamera {
+            radius: 5.0,
+            max_radius: 12.0,
+            ..default()
+        };
+        for _ in 0..50 {
+            pan_orbit.zoom(-1.0);
+        }
+        assert_eq!(pan_orbit.radius, 12.0);
+
+        for _ in 0..50 {
+            pan_orbit.zoom(1.0);
+        }
+        assert_eq!(pan_orbit.radius, pan_orbit.min_radius);
+    }
+
+    #[test]
+    fn inverted_scroll_zooms_the_other_way() {
+        let mut settings = CameraSettings::default();
+        let zoomed = |settings: &CameraSettings| {
+            let mut pan_orbit = PanOrbitCamera::default();
+            pan_orbit.zoom(settings.zoom_steps(1.0));
+            pan_orbit.radius - PanOrbitCamera::default().radius
+        };
+
+        let normal = zoomed(&settings);
+        settings.invert_scroll = true;
+        let inverted = zoomed(&settings);
+        assert!(normal < 0.0, "scrolling up zooms in");
+        assert!(inverted > 0.0, "and out when inverted");
+    }
+
+    #[test]
+    fn pan_scale_follows_a_window_resize() {
+        let fov = 45f32.to_radians();
+        let before = pan_scale(fov, Vec2::new(1280.0, 720.0)).unwrap();
+        assert!(before.abs_diff_eq(Vec2::splat(fov / 720.0), 1e-6));
+
+        // half as tall, so a pixel covers twice as much of the view either way
+        let shorter = pan_scale(fov, Vec2::new(1280.0, 360.0)).unwrap();
+        assert!(shorter.abs_diff_eq(before * 2.0, 1e-6));
+
+        // only wider, the extra pixels show extra view and a pixel covers just as much
+        let wider = pan_scale(fov, Vec2::new(2560.0, 720.0)).unwrap();
+        assert!(wider.abs_diff_eq(before, 1e-6));
+
+        // a window dragged down to nothing doesn't divide by zero
+        assert_eq!(pan_scale(fov, Vec2::new(0.0, 720.0)), None);
+    }
+
+    #[test]
+    fn yaw_keeps_the_distance_and_pitch() {
+        let mut pan_orbit = orbiting_at_45_degrees();
+        let before = elevation(pan_orbit.rotation);
+        pan_orbit.orbit(1.0, 0.0);
+        assert!((elevation(pan_orbit.rotation) - before).abs() < 1e-4);
+        assert!((pan_orbit.eye().distance(pan_orbit.focus) - pan_orbit.radius).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orbiting_never_rolls_the_camera() {
+        let mut pan_orbit = orbiting_at_45_degrees();
+        for step in 0..200 {
+            let pitch = if step % 20 < 10 { 0.05 } else { -0.07 };
+            pan_orbit.orbit(0.13, pitch);
+
+            // the horizon stays level and the camera stays the right way up
+            let right = pan_orbit.rotation * Vec3::X;
+            let up = pan_orbit.rotation * Vec3::Y;
+            assert!(
+                right.dot(WORLD_UP).abs() < 1e-3,
+                "rolled after {} steps",
+                step
+            );
+            assert!(up.dot(WORLD_UP) > 0.0, "upside down after {} steps", step);
+        }
+    }
+
+    #[test]
+    fn same_key_goes_back_to_following() {
+        assert_eq!(
+            CameraMode::Follow.toggled(CameraMode::Orbit),
+            CameraMode::Orbit
+        );
+        assert_eq!(
+            CameraMode::Orbit.toggled(CameraMode::Orbit),
+            CameraMode::Follow
+        );
+        assert_eq!(
+            CameraMode::FreeFly.toggled(CameraMode::Orbit),
+            CameraMode::Orbit
+        );
+    }
+
+    #[test]
+    fn focus_is_kept_near_the_arena_and_the_eye_above_the_floor() {
+        let bounds = CameraBounds {
+            enabled: true,
+            margin: 2.0,
+            min_height: 0.2,
+        };
+        let arena = Arena::Circle { radius: 10.0 };
+
+        let inside = Vec3::new(3.0, -4.0, 1.0);
+        assert_eq!(bounds.clamp_focus(inside, &arena), inside);
+        let far_out = bounds.clamp_focus(Vec3::new(30.0, 0.0, 1.0), &arena);
+        assert!(
+            far_out.abs_diff_eq(Vec3::new(12.0, 0.0, 1.0), 1e-5),
+            "{}",
+            far_out
+        );
+
+        let rect = Arena::Rect {
+            half_extents: Vec2::new(5.0, 3.0),
+        };
+        let corner = bounds.clamp_focus(Vec3::new(-20.0, 20.0, 0.0), &rect);
+        assert_eq!(corner, Vec3::new(-7.0, 5.0, 0.0));
+
+        assert_eq!(
+            bounds.clamp_eye(Vec3::new(1.0, 2.0, -3.0)),
+            Vec3::new(1.0, 2.0, 0.2)
+        );
+
+        let disabled = CameraBounds {
+            enabled: false,
+            ..bounds
+        };
+        let anywhere = Vec3::new(30.0, 0.0, -3.0);
+        assert_eq!(disabled.clamp_focus(anywhere, &arena), anywhere);
+        assert_eq!(disabled.clamp_eye(anywhere), anywhere);
+    }
+
+    #[test]
+    fn follow_smoothing_reaches_the_smoother() {
+        let mut app = App::new();
+        app.init_resource::<CameraSettings>()
+            .add_system(apply_follow_smoothing);
+        let from = LookTransform::new(Vec3::ZERO, Vec3::X, WORLD_UP);
+        let to = LookTransform::new(Vec3::new(0.0, 10.0, 0.0), Vec3::X, WORLD_UP);
+        // where a fresh camera's smoother ends up one step after jumping from `from` to `to`
+        let step = |app: &mut App, follow_smoothing: f32| {
+            let camera = app.world.spawn(Smoother::new(0.6)).id();
+            app.world.resource_mut::<CameraSettings>().follow_smoothing = follow_smoothing;
+            app.update();
+            let mut smoother = app.world.get_mut::<Smoother>(camera).unwrap();
+            smoother.smooth_transform(&from);
+            smoother.smooth_transform(&to).eye
+        };
+
+        assert_eq!(step(&mut app, 0.0), to.eye);
+        assert_eq!(step(&mut app, 0.5).y, 5.0);
+        // capped, the camera still catches up eventually
+        let capped = step(&mut app, 2.0).y;
+        assert!(
+            (capped - 10.0 * (1.0 - MAX_FOLLOW_SMOOTHING)).abs() < 1e-4,
+            "{}",
+            capped
+        );
+    }
+
+    #[test]
+    fn switching_modes_eases_between_the_poses() {
+        let mut app = App::new();
+        app.init_resource::<CameraSettings>()
+            .init_resource::<CameraTransition>()
+            .init_resource::<Time>()
+            .add_system(blend_camera_transition);
+        let from = CameraPose {
+            eye: Vec3::new(0.0, -10.0, 5.0),
+            target: Vec3::ZERO,
+        };
+        let to = CameraPose {
+            eye: Vec3::new(10.0, -10.0, 5.0),
+            target: Vec3::X * 10.0,
+        };
+        let camera = app
+            .world
+            .spawn(LookTransform::new(to.eye, to.target, WORLD_UP))
+            .id();
+        app.world.resource_mut::<CameraTransition>().start(from);
+
+        let mut now = app.world.resource::<Time>().startup();
+        // the first update only starts the clock
+        app.world.resource_mut::<Time>().update_with_instant(now);
+        // the new mode puts the camera at `to` every frame, the transition then blends it
+        let mut step = |app: &mut App| {
+            now += std::time::Duration::from_millis(100);
+            app.world.resource_mut::<Time>().update_with_instant(now);
+            let mut look = app.world.get_mut::<LookTransform>(camera).unwrap();
+            look.eye = to.eye;
+            look.target = to.target;
+            app.update();
+            CameraPose::of(app.world.get::<LookTransform>(camera).unwrap())
+        };
+
+        assert_eq!(step(&mut app), from);
+        let pose = step(&mut app);
+        assert!(
+            pose.eye.x > from.eye.x && pose.eye.x < to.eye.x,
+            "{:?}",
+            pose
+        );
+        assert!(pose.target.x > 0.0 && pose.target.x < 10.0, "{:?}", pose);
+
+        // the default half a second later it's done
+        for _ in 0..5 {
+            step(&mut app);
+        }
+        assert_eq!(step(&mut app), to);
+        assert!(!app.world.resource::<CameraTransition>().is_active());
+    }
+}
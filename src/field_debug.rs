@@ -0,0 +1,131 @@
+//! Debug view of the blob distance field, sampled on the CPU
+use crate::raymarching::Blob;
+use crate::Arena;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_mod_gizmos::Gizmo;
+
+pub struct FieldDebugPlugin;
+
+impl Plugin for FieldDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FieldDebugConfig>()
+            .add_system(field_debug_window)
+            .add_system(draw_field.after(field_debug_window));
+    }
+}
+
+/// Height of blob centers above the floor, `vec3(blob.position, 0.4)` in `sdf_blob`
+pub const BLOB_CENTER_HEIGHT: f32 = 0.4;
+/// Smoothing between blobs, the `k` of the `opSmoothUnion` in the shader's `sdf`
+pub const BLOB_SMOOTHING: f32 = 0.6;
+
+#[derive(Resource)]
+pub struct FieldDebugConfig {
+    pub enabled: bool,
+    /// Samples along each side of the arena
+    pub resolution: usize,
+    /// Height of the sampled plane
+    pub height: f32,
+    /// Samples further than this from any surface aren't drawn
+    pub max_distance: f32,
+}
+
+impl Default for FieldDebugConfig {
+    fn default() -> Self {
+        FieldDebugConfig {
+            enabled: false,
+            resolution: 32,
+            height: BLOB_CENTER_HEIGHT,
+            max_distance: 1.0,
+        }
+    }
+}
+
+/// `opSmoothUnion` from `raymarching_common.wgsl`
+pub fn smooth_union(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+    d2 + (d1 - d2) * h - k * h * (1.0 - h)
+}
+
+/// Distance from `point` to the blended blob surfaces, given `(position, size)` for each blob.
+/// Leaves out the wobble, nose and squash the shader adds, which don't change where blobs merge.
+pub fn blob_field(point: Vec3, blobs: impl IntoIterator<Item = (Vec2, f32)>) -> f32 {
+    // same starting value as the shader
+    let mut field = 9000.0;
+    for (position, size) in blobs {
+        let distance = point.distance(position.extend(BLOB_CENTER_HEIGHT)) - size;
+        field = smooth_union(field, distance, BLOB_SMOOTHING);
+    }
+    field
+}
+
+fn field_debug_window(mut config: ResMut<FieldDebugConfig>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("SDF field").show(egui_contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut config.enabled, "Show");
+        ui.add(egui::Slider::new(&mut config.resolution, 4..=96).text("Resolution"));
+        ui.add(egui::Slider::new(&mut config.height, 0.0..=2.0).text("Height"));
+        ui.add(egui::Slider::new(&mut config.max_distance, 0.1..=3.0).text("Max distance"));
+    });
+}
+
+/// Dots on a grid over the arena, red inside a blob and fading through blue outside
+fn draw_field(blobs: Query<(&Transform, &Blob)>, config: Res<FieldDebugConfig>, arena: Res<Arena>) {
+    if !config.enabled || config.resolution < 2 {
+        return;
+    }
+
+    let blobs: Vec<(Vec2, f32)> = blobs
+        .iter()
+        .map(|(transform, blob)| (transform.translation.truncate(), blob.size))
+        .collect();
+    let extent = arena.extent();
+    let step = extent * 2.0 / (config.resolution - 1) as f32;
+
+    for y in 0..config.resolution {
+        for x in 0..config.resolution {
+            let point = (-extent + step * Vec2::new(x as f32, y as f32)).extend(config.height);
+            let distance = blob_field(point, blobs.iter().copied());
+            if distance > config.max_distance {
+                continue;
+            }
+
+            let color = if distance < 0.0 {
+                Color::RED
+            } else {
+                let t = distance / config.max_distance;
+                Color::rgba(0.0, 0.5 * (1.0 - t), 1.0, 1.0 - t)
+            };
+            bevy_mod_gizmos::draw_gizmo(Gizmo::sphere(point, step.min_element() * 0.2, color));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_blob_field_is_the_sphere_distance() {
+        let blobs = [(Vec2::new(1.0, 2.0), 0.5)];
+        let center = Vec3::new(1.0, 2.0, BLOB_CENTER_HEIGHT);
+        assert!((blob_field(center, blobs) + 0.5).abs() < 1e-4);
+        assert!(blob_field(center + Vec3::X * 0.5, blobs).abs() < 1e-4);
+        assert!((blob_field(center + Vec3::Y * 3.0, blobs) - 2.5).abs() < 1e-4);
+        // nothing to measure against
+        assert_eq!(blob_field(Vec3::ZERO, []), 9000.0);
+    }
+
+    #[test]
+    fn nearby_blobs_blend_together() {
+        let a = (Vec2::new(-0.6, 0.0), 0.5);
+        let b = (Vec2::new(0.6, 0.0), 0.5);
+        let between = Vec3::new(0.0, 0.0, BLOB_CENTER_HEIGHT);
+        // outside both spheres, but inside the smoothed union
+        assert!(blob_field(between, [a]) > 0.0);
+        assert!(blob_field(between, [b]) > 0.0);
+        assert!(blob_field(between, [a, b]) < 0.0);
+
+        assert_eq!(smooth_union(-1.0, 5.0, BLOB_SMOOTHING), -1.0);
+    }
+}
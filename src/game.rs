@@ -0,0 +1,705 @@
+//! Game state and the gameplay clock
+use crate::bvh::BvhTree;
+use crate::high_score::HighScore;
+use crate::particles::Particle;
+use crate::progress::PlayerProgress;
+use crate::raymarching::{
+    spawn_starting_blobs, Blob, BlobBundle, BlobLimit, BlobMaterial, BlobMergeEvent, BlobMesh,
+    DebugGridConfig, StartingLayout,
+};
+use crate::rng::GameRng;
+use crate::selection::SelectedBlob;
+use crate::simulation::SimulationSet;
+use crate::split::{DoubleClick, SplitBuffer};
+use crate::teams::PLAYER_TEAM;
+use crate::trail::Trail;
+use crate::{Arena, PlayerInput};
+use bevy::math::vec3;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .init_resource::<GameTime>()
+            .init_resource::<Score>()
+            .init_resource::<ComboState>()
+            .init_resource::<GameConfig>()
+            .insert_resource(BlobPalette::load(Path::new(PALETTE_PATH)))
+            .add_event::<RestartEvent>()
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                schedule
+                    .configure_set(SimulationSet::Gameplay.run_if(in_state(GameState::Playing)));
+            })
+            .add_system(advance_game_time.in_base_set(CoreSet::PreUpdate))
+            .add_system(toggle_pause)
+            .add_system(award_score)
+            .add_system(expire_combo.after(award_score))
+            .add_system(score_window.run_if(in_state(GameState::Playing)))
+            .add_system(detect_game_over.run_if(in_state(GameState::Playing)))
+            .add_system(game_over_window.run_if(in_state(GameState::GameOver)))
+            .add_system(respawn_player.run_if(in_state(GameState::GameOver)))
+            .add_system(request_restart)
+            .add_system(
+                restart_game
+                    .after(request_restart)
+                    .after(score_window)
+                    .after(game_over_window),
+            );
+    }
+}
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    #[default]
+    Playing,
+    Paused,
+    GameOver,
+}
+
+/// Sizes blobs start out at and can grow to
+#[derive(Resource, Debug)]
+pub struct GameConfig {
+    /// `Blob::size` of the player at startup and after respawning
+    pub player_start_size: f32,
+    /// `Blob::size` of the AI blobs spawned at startup
+    pub ai_start_size: f32,
+    /// Eating doesn't grow blobs past this. The player scores the growth it misses out on.
+    pub max_size: f32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            player_start_size: Blob::default().size,
+            ai_start_size: Blob::default().size,
+            max_size: 4.0,
+        }
+    }
+}
+
+impl GameConfig {
+    /// `size` grown by `amount` but no further than `max_size`, and how much of `amount` didn't
+    /// fit. Blobs already past the cap keep their size.
+    pub fn grow(&self, size: f32, amount: f32) -> (f32, f32) {
+        let cap = self.max_size.max(size);
+        let grown = (size + amount).min(cap);
+        (grown, size + amount - grown)
+    }
+}
+
+/// Where [`BlobPalette`] is loaded from at startup
+pub const PALETTE_PATH: &str = "assets/palette.ron";
+
+/// Colors spawned blobs get, so they don't all need one picked by hand
+#[derive(Resource, Deserialize, Debug)]
+#[serde(default)]
+pub struct BlobPalette {
+    /// AI blobs, pellets and debug spawns pick one of these at random
+    pub colors: Vec<Color>,
+    /// Reserved for the player, never picked for anything else even if it's in `colors`
+    pub player: Color,
+}
+
+impl Default for BlobPalette {
+    fn default() -> Self {
+        BlobPalette {
+            colors: vec![
+                Color::rgb(0.4, 0.9, 0.4),
+                Color::rgb(0.3, 0.6, 1.0),
+                Color::rgb(1.0, 0.85, 0.2),
+                Color::rgb(0.75, 0.4, 1.0),
+                Color::rgb(0.3, 0.9, 0.9),
+                Color::rgb(1.0, 0.45, 0.75),
+            ],
+            player: Blob::default().color,
+        }
+    }
+}
+
+impl BlobPalette {
+    /// The default palette if the file is missing or can't be read
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path).map(|text| ron::from_str(&text)) {
+            Ok(Ok(palette)) => palette,
+            Ok(Err(error)) => {
+                warn!("couldn't read the palette in {}: {}", path.display(), error);
+                BlobPalette::default()
+            }
+            Err(_) => BlobPalette::default(),
+        }
+    }
+
+    /// A random color out of `colors`, skipping the player's. Grey if that leaves nothing.
+    pub fn pick(&self, rng: &mut GameRng) -> Color {
+        let weights = self
+            .colors
+            .iter()
+            .map(|&color| if color == self.player { 0.0 } else { 1.0 });
+        rng.weighted_index(weights)
+            .map_or(Color::GRAY, |index| self.colors[index])
+    }
+}
+
+/// Seconds of gameplay so far. Unlike `Time` this stops while the game isn't being played, so
+/// animations and timestamps like `Blob::last_ate` don't jump after a pause.
+#[derive(Resource, Default, Debug)]
+pub struct GameTime {
+    pub elapsed: f32,
+}
+
+impl GameTime {
+    pub fn advance(&mut self, delta: f32, state: GameState) {
+        if state == GameState::Playing {
+            self.elapsed += delta;
+        }
+    }
+}
+
+pub(crate) fn advance_game_time(
+    mut game_time: ResMut<GameTime>,
+    state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    game_time.advance(time.delta_seconds(), state.0);
+}
+
+fn toggle_pause(
+    keys: Res<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    match state.0 {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        GameState::GameOver => {}
+    }
+}
+
+/// Points the player has earned this life
+#[derive(Resource, Default, Debug)]
+pub struct Score {
+    pub points: u32,
+}
+
+/// Points per unit of blob size eaten
+const POINTS_PER_SIZE: f32 = 100.;
+
+/// Points per unit of growth a capped blob missed out on. That's in the eater's size, not the
+/// eaten blob's, so it doesn't share [`POINTS_PER_SIZE`].
+const POINTS_PER_OVERFLOW: f32 = 50.;
+
+/// Merges in quick succession raise a score multiplier
+#[derive(Resource, Debug)]
+pub struct ComboState {
+    /// Merges in the current combo
+    pub count: u32,
+    pub multiplier: f32,
+    /// `GameTime` of the latest merge in the combo
+    pub last_merge_time: f32,
+    /// The combo ends if there's no merge for this many seconds
+    pub window: f32,
+    /// How much each merge in a combo adds to the multiplier
+    pub step: f32,
+    pub max_multiplier: f32,
+}
+
+impl Default for ComboState {
+    fn default() -> Self {
+        ComboState {
+            count: 0,
+            multiplier: 1.0,
+            last_merge_time: 0.0,
+            window: 2.0,
+            step: 0.5,
+            max_multiplier: 4.0,
+        }
+    }
+}
+
+impl ComboState {
+    /// Registers a merge at `now` and returns the multiplier it scores with
+    pub fn merge(&mut self, now: f32) -> f32 {
+        if self.count > 0 && now - self.last_merge_time > self.window {
+            self.reset();
+        }
+
+        self.count += 1;
+        self.multiplier =
+            (1.0 + (self.count - 1) as f32 * self.step).min(self.max_multiplier.max(1.0));
+        self.last_merge_time = now;
+        self.multiplier
+    }
+
+    /// Ends the combo if the window has passed since the last merge
+    pub fn expire(&mut self, now: f32) {
+        if self.count > 0 && now - self.last_merge_time > self.window {
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.multiplier = 1.0;
+    }
+}
+
+fn award_score(
+    mut merge_events: EventReader<BlobMergeEvent>,
+    players: Query<(), With<PlayerInput>>,
+    mut score: ResMut<Score>,
+    mut combo: ResMut<ComboState>,
+    game_time: Res<GameTime>,
+) {
+    for event in merge_events.iter() {
+        if players.contains(event.eater) {
+            let multiplier = combo.merge(game_time.elapsed);
+            // growth lost to the size cap counts on top
+            let points = event.eaten_size * POINTS_PER_SIZE + event.overflow * POINTS_PER_OVERFLOW;
+            score.points += (points * multiplier).round() as u32;
+        }
+    }
+}
+
+fn expire_combo(mut combo: ResMut<ComboState>, game_time: Res<GameTime>) {
+    combo.expire(game_time.elapsed);
+}
+
+fn score_window(
+    score: Res<Score>,
+    high_score: Res<HighScore>,
+    combo: Res<ComboState>,
+    mut restart_events: EventWriter<RestartEvent>,
+    mut egui_contexts: EguiContexts,
+) {
+    egui::Window::new("Score").show(egui_contexts.ctx_mut(), |ui| {
+        ui.label(format!("Score: {}", score.points));
+        ui.label(format!("Best: {}", high_score.points));
+        if combo.count > 1 {
+            ui.label(format!("Combo x{} ({:.1}x)", combo.count, combo.multiplier));
+        }
+        if ui.button("Restart (R)").clicked() {
+            restart_events.send(RestartEvent);
+        }
+    });
+}
+
+fn detect_game_over(
+    players: Query<(), With<PlayerInput>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if players.is_empty() {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn game_over_window(
+    score: Res<Score>,
+    high_score: Res<HighScore>,
+    mut restart_events: EventWriter<RestartEvent>,
+    mut egui_contexts: EguiContexts,
+) {
+    egui::Window::new("Game over").show(egui_contexts.ctx_mut(), |ui| {
+        ui.label(format!("Score: {}", score.points));
+        if high_score.new_record {
+            ui.label("New high score!");
+        } else {
+            ui.label(format!("Best: {}", high_score.points));
+        }
+        ui.label("Press Enter to respawn");
+        if ui.button("Restart (R)").clicked() {
+            restart_events.send(RestartEvent);
+        }
+    });
+}
+
+/// Ends the current game and starts over from the initial layout, see [`restart_game`]
+pub struct RestartEvent;
+
+fn request_restart(keys: Res<Input<KeyCode>>, mut restart_events: EventWriter<RestartEvent>) {
+    if keys.just_pressed(KeyCode::R) {
+        restart_events.send(RestartEvent);
+    }
+}
+
+/// Throws away every blob, pellet, virus and particle and respawns the starting layout, with the
+/// score, combo, progress, game time and arena back to how they start. The BVH sees everything
+/// change at once and rebuilds, and `update_material` hands out fresh buffer indices.
+fn restart_game(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartEvent>,
+    blobs: Query<Entity, Or<(With<Blob>, With<Particle>)>>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+    // grouped in tuples, systems can't take more than 16 parameters
+    (grid, layout): (Res<DebugGridConfig>, Res<StartingLayout>),
+    limit: Res<BlobLimit>,
+    game_config: Res<GameConfig>,
+    (palette, mut rng): (Res<BlobPalette>, ResMut<GameRng>),
+    (mut score, mut combo): (ResMut<Score>, ResMut<ComboState>),
+    (mut split_buffer, mut double_click): (ResMut<SplitBuffer>, ResMut<DoubleClick>),
+    mut progress: ResMut<PlayerProgress>,
+    mut game_time: ResMut<GameTime>,
+    mut arena: ResMut<Arena>,
+    mut selected: ResMut<SelectedBlob>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if restart_events.iter().count() == 0 {
+        return;
+    }
+
+    for entity in blobs.iter() {
+        commands.entity(entity).despawn();
+    }
+    spawn_starting_blobs(
+        &mut commands,
+        &material,
+        &mesh,
+        &layout.resolve(&grid),
+        &limit,
+        &game_config,
+        &palette,
+        &mut rng,
+    );
+
+    *score = Score::default();
+    combo.reset();
+    // their timestamps are from the old GameTime
+    *split_buffer = SplitBuffer::default();
+    *double_click = DoubleClick::default();
+    *progress = PlayerProgress::default();
+    *game_time = GameTime::default();
+    *arena = Arena::default();
+    *selected = SelectedBlob::default();
+    next_state.set(GameState::Playing);
+}
+
+/// Spawns a new player blob at [`safe_spawn_position`] when Enter is pressed
+pub(crate) fn respawn_player(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    blobs: Query<&Blob>,
+    bvh: Res<BvhTree>,
+    arena: Res<Arena>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+    mut score: ResMut<Score>,
+    mut combo: ResMut<ComboState>,
+    mut progress: ResMut<PlayerProgress>,
+    game_config: Res<GameConfig>,
+    palette: Res<BlobPalette>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+
+    let blob = Blob {
+        size: game_config.player_start_size,
+        color: palette.player,
+        ..default()
+    };
+    let position = safe_spawn_position(&bvh, &arena, |entity| {
+        blobs
+            .get(entity)
+            .map_or(false, |other| other.size >= blob.size)
+    });
+
+    commands.spawn((
+        BlobBundle::new(
+            &material,
+            &mesh,
+            Transform::from_translation(position.extend(1.0)),
+            blob,
+        ),
+        PlayerInput,
+        PLAYER_TEAM,
+        Trail::new(40, Color::WHITE),
+    ));
+
+    *score = Score::default();
+    combo.reset();
+    *progress = PlayerProgress::default();
+    next_state.set(GameState::Playing);
+}
+
+/// The point inside the arena furthest from any entity `is_dangerous` picks out, out of a
+/// few rings of candidates
+pub fn safe_spawn_position(
+    bvh: &BvhTree,
+    arena: &Arena,
+    mut is_dangerous: impl FnMut(Entity) -> bool,
+) -> Vec2 {
+    const RINGS: usize = 4;
+    const POINTS_PER_RING: usize = 8;
+
+    let mut best = (Vec2::ZERO, f32::NEG_INFINITY);
+    for ring in 0..RINGS {
+        let radius = arena.inner_radius() * 0.8 * ring as f32 / (RINGS - 1) as f32;
+        for point in 0..POINTS_PER_RING {
+            let angle = std::f32::consts::TAU * point as f32 / POINTS_PER_RING as f32;
+            let candidate = Vec2::from_angle(angle) * radius;
+            let distance = bvh
+                .nearest(vec3(candidate.x, candidate.y, 1.0), &mut is_dangerous)
+                .map_or(f32::INFINITY, |(_, distance)| distance);
+
+            if distance > best.1 {
+                best = (candidate, distance);
+            }
+        }
+    }
+
+    best.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::split::SplitConfig;
+    use bevy::ecs::system::CommandQueue;
+
+    #[test]
+    fn game_time_stops_while_paused() {
+        let mut game_time = GameTime::default();
+        game_time.advance(0.5, GameState::Playing);
+        assert_eq!(game_time.elapsed, 0.5);
+
+        game_time.advance(10.0, GameState::Paused);
+        game_time.advance(10.0, GameState::GameOver);
+        assert_eq!(game_time.elapsed, 0.5);
+
+        game_time.advance(0.25, GameState::Playing);
+        assert_eq!(game_time.elapsed, 0.75);
+    }
+
+    #[test]
+    fn enter_respawns_the_player_after_game_over() {
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .insert_resource(NextState(Some(GameState::GameOver)))
+            .insert_resource(BlobMaterial(Handle::default()))
+            .insert_resource(BlobMesh(Handle::default()))
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<BvhTree>()
+            .init_resource::<Arena>()
+            .init_resource::<Score>()
+            .init_resource::<ComboState>()
+            .init_resource::<PlayerProgress>()
+            .init_resource::<GameConfig>()
+            .init_resource::<BlobPalette>()
+            .add_system(respawn_player.run_if(in_state(GameState::GameOver)));
+        app.update();
+
+        let mut players = app.world.query_filtered::<&Blob, With<PlayerInput>>();
+        assert_eq!(players.iter(&app.world).count(), 0);
+
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Return);
+        app.update();
+        app.update();
+
+        let player_sizes: Vec<f32> = players.iter(&app.world).map(|blob| blob.size).collect();
+        assert_eq!(player_sizes, [GameConfig::default().player_start_size]);
+        assert_eq!(
+            app.world.resource::<State<GameState>>().0,
+            GameState::Playing
+        );
+    }
+
+    #[test]
+    fn quick_merges_raise_the_combo_multiplier() {
+        let mut combo = ComboState::default();
+        assert_eq!(combo.merge(1.0), 1.0);
+        assert_eq!(combo.merge(2.0), 1.5);
+        assert_eq!(combo.merge(3.5), 2.0);
+        // capped
+        for i in 0..10 {
+            combo.merge(4.0 + i as f32 * 0.1);
+        }
+        assert_eq!(combo.multiplier, combo.max_multiplier);
+
+        // too slow, starts over
+        assert_eq!(combo.merge(10.0), 1.0);
+        assert_eq!(combo.count, 1);
+
+        combo.merge(10.5);
+        combo.expire(11.0);
+        assert_eq!(combo.count, 2);
+        combo.expire(13.0);
+        assert_eq!(combo.count, 0);
+        assert_eq!(combo.multiplier, 1.0);
+    }
+
+    #[test]
+    fn palette_never_picks_the_players_color() {
+        let mut palette = BlobPalette::default();
+        palette.colors.push(palette.player);
+        let mut rng = GameRng::new(7);
+
+        for _ in 0..500 {
+            let color = palette.pick(&mut rng);
+            assert_ne!(color, palette.player);
+            assert!(palette.colors.contains(&color));
+        }
+    }
+
+    #[test]
+    fn palette_with_only_the_players_color_falls_back_to_grey() {
+        let palette = BlobPalette {
+            colors: vec![Color::RED],
+            player: Color::RED,
+        };
+        assert_eq!(palette.pick(&mut GameRng::default()), Color::GRAY);
+    }
+
+    #[test]
+    fn shipped_palette_file_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(PALETTE_PATH);
+        let palette: BlobPalette = ron::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let default = BlobPalette::default();
+        assert_eq!(palette.colors, default.colors);
+        assert_eq!(palette.player, default.player);
+    }
+
+    #[test]
+    fn missing_or_partial_palette_keeps_the_defaults() {
+        let missing = BlobPalette::load(Path::new("does/not/exist.ron"));
+        assert_eq!(missing.colors, BlobPalette::default().colors);
+
+        let partial: BlobPalette =
+            ron::from_str("(colors: [Rgba(red: 0.0, green: 0.0, blue: 1.0, alpha: 1.0)])").unwrap();
+        assert_eq!(partial.colors, vec![Color::BLUE]);
+        assert_eq!(partial.player, BlobPalette::default().player);
+    }
+
+    /// Position, size and whether it's the player, for every blob in `world`, in a stable order
+    fn blob_set(world: &mut World) -> Vec<(Vec3, f32, bool)> {
+        let mut blobs: Vec<(Vec3, f32, bool)> = world
+            .query::<(&Transform, &Blob, Option<&PlayerInput>)>()
+            .iter(world)
+            .map(|(transform, blob, player)| (transform.translation, blob.size, player.is_some()))
+            .collect();
+        blobs.sort_by(|a, b| a.0.x.total_cmp(&b.0.x).then(a.0.y.total_cmp(&b.0.y)));
+        blobs
+    }
+
+    #[test]
+    fn restart_matches_a_fresh_start() {
+        let mut fresh = World::new();
+        let mut queue = CommandQueue::default();
+        spawn_starting_blobs(
+            &mut Commands::new(&mut queue, &fresh),
+            &BlobMaterial(Handle::default()),
+            &BlobMesh(Handle::default()),
+            &StartingLayout::default().resolve(&DebugGridConfig::default()),
+            &BlobLimit::default(),
+            &GameConfig::default(),
+            &BlobPalette::default(),
+            &mut GameRng::default(),
+        );
+        queue.apply(&mut fresh);
+
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .insert_resource(NextState(Some(GameState::GameOver)))
+            .insert_resource(BlobMaterial(Handle::default()))
+            .insert_resource(BlobMesh(Handle::default()))
+            .init_resource::<DebugGridConfig>()
+            .init_resource::<StartingLayout>()
+            .init_resource::<BlobLimit>()
+            .init_resource::<GameConfig>()
+            .init_resource::<BlobPalette>()
+            .init_resource::<GameRng>()
+            .init_resource::<Score>()
+            .init_resource::<ComboState>()
+            .init_resource::<PlayerProgress>()
+            .init_resource::<GameTime>()
+            .init_resource::<Arena>()
+            .init_resource::<SelectedBlob>()
+            .init_resource::<DoubleClick>()
+            .insert_resource(SplitBuffer {
+                request: Some((59.9, Vec3::X)),
+                last_split: Some(59.0),
+            })
+            .add_event::<RestartEvent>()
+            .add_system(restart_game);
+        // a game some way in
+        for i in 0..5 {
+            app.world.spawn(BlobBundle::new(
+                &BlobMaterial(Handle::default()),
+                &BlobMesh(Handle::default()),
+                Transform::from_xyz(i as f32, 3.0, 1.0),
+                Blob {
+                    size: 0.7,
+                    ..default()
+                },
+            ));
+        }
+        app.world.resource_mut::<Score>().points = 500;
+        app.world.resource_mut::<GameTime>().elapsed = 60.0;
+        let window = SplitConfig::default().double_click_window;
+        app.world.resource_mut::<DoubleClick>().click(59.95, window);
+        app.update();
+
+        app.world.send_event(RestartEvent);
+        app.update();
+        app.update();
+
+        assert_eq!(blob_set(&mut app.world), blob_set(&mut fresh));
+        assert_eq!(app.world.resource::<Score>().points, 0);
+        assert_eq!(app.world.resource::<GameTime>().elapsed, 0.0);
+        let split_buffer = app.world.resource::<SplitBuffer>();
+        assert_eq!(split_buffer.request, None);
+        assert_eq!(split_buffer.last_split, None);
+        // the click before the restart doesn't pair up with one after it
+        assert!(!app.world.resource_mut::<DoubleClick>().click(0.0, window));
+        assert_eq!(
+            app.world.resource::<State<GameState>>().0,
+            GameState::Playing
+        );
+    }
+
+    #[test]
+    fn growth_past_the_cap_is_scored_instead() {
+        let config = GameConfig::default();
+        let max = config.max_size;
+        assert_eq!(config.grow(max, 0.5), (max, 0.5));
+        let (size, overflow) = config.grow(max - 0.2, 0.5);
+        assert_eq!(size, max);
+        assert!((overflow - 0.3).abs() < 1e-5, "{}", overflow);
+        // already past the cap from before it was lowered
+        assert_eq!(config.grow(max + 1.0, 0.5), (max + 1.0, 0.5));
+
+        let mut app = App::new();
+        app.init_resource::<Score>()
+            .init_resource::<ComboState>()
+            .init_resource::<GameTime>()
+            .add_event::<BlobMergeEvent>()
+            .add_system(award_score);
+        let player = app.world.spawn(PlayerInput).id();
+        app.world.send_event(BlobMergeEvent {
+            eater: player,
+            eaten: Entity::from_raw(100),
+            eaten_size: 0.5,
+            eaten_color: Color::RED,
+            position: Vec3::ZERO,
+            overflow: 0.5,
+        });
+        app.update();
+        // first merge of a combo, so no multiplier
+        assert_eq!(
+            app.world.resource::<Score>().points,
+            (0.5 * POINTS_PER_SIZE + 0.5 * POINTS_PER_OVERFLOW).round() as u32
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Best score, kept across runs in a file in the platform's data directory
+use crate::game::{GameState, Score};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        let high_score = high_score_path()
+            .map(|path| HighScore::load(&path))
+            .unwrap_or_default();
+        app.insert_resource(high_score)
+            .add_system(record_high_score.in_schedule(OnEnter(GameState::GameOver)));
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct HighScore {
+    pub points: u32,
+    /// Whether the last game beat the previous high score
+    #[serde(skip)]
+    pub new_record: bool,
+}
+
+impl HighScore {
+    /// Zero if the file is missing or can't be read
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| ron::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text =
+            ron::to_string(self).map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        fs::write(path, text)
+    }
+
+    pub fn is_new_record(&self, score: &Score) -> bool {
+        score.points > self.points
+    }
+}
+
+/// `highscore.ron` in the platform's data directory, None if there's no home to find it in
+pub fn high_score_path() -> Option<PathBuf> {
+    let data_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Application Support")
+        })
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+    };
+    Some(data_dir?.join("blob_game").join("highscore.ron"))
+}
+
+fn record_high_score(score: Res<Score>, mut high_score: ResMut<HighScore>) {
+    high_score.new_record = high_score.is_new_record(&score);
+    if !high_score.new_record {
+        return;
+    }
+
+    high_score.points = score.points;
+    if let Some(path) = high_score_path() {
+        if let Err(error) = high_score.save(&path) {
+            warn!(
+                "couldn't save the high score to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_score_survives_a_round_trip() {
+        let dir = std::env::temp_dir().join(format!("blob_game_high_score_{}", std::process::id()));
+        let path = dir.join("highscore.ron");
+        let saved = HighScore {
+            points: 1234,
+            new_record: true,
+        };
+        saved.save(&path).unwrap();
+        let loaded = HighScore::load(&path);
+        // the flag is only for the game that set it
+        assert_eq!(loaded.points, 1234);
+        assert!(!loaded.new_record);
+
+        fs::write(&path, "not ron").unwrap();
+        assert_eq!(HighScore::load(&path), HighScore::default());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(HighScore::load(&path), HighScore::default());
+    }
+
+    #[test]
+    fn only_beating_the_score_is_a_new_record() {
+        let high_score = HighScore {
+            points: 100,
+            ..default()
+        };
+        assert!(!high_score.is_new_record(&Score { points: 99 }));
+        assert!(!high_score.is_new_record(&Score { points: 100 }));
+        assert!(high_score.is_new_record(&Score { points: 101 }));
+    }
+}
@@ -0,0 +1,259 @@
+//! Sun light controls
+use crate::game::GameTime;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SunSettings>()
+            .init_resource::<DayNightCycle>()
+            .add_system(sun_window)
+            .add_system(advance_day_night.after(sun_window))
+            .add_system(update_sun.after(advance_day_night));
+    }
+}
+
+/// Tags the directional 'sun' light
+#[derive(Component)]
+pub struct Sun;
+
+#[derive(Resource)]
+pub struct SunSettings {
+    /// Rotation around the world z axis, in radians
+    pub azimuth: f32,
+    /// Tilt down from the horizon, in radians
+    pub elevation: f32,
+    pub illuminance: f32,
+    pub color: Color,
+    /// Blobs stay `NotShadowCaster` either way, their cube meshes would cast cube shaped shadows
+    pub shadows_enabled: bool,
+}
+
+impl Default for SunSettings {
+    fn default() -> Self {
+        SunSettings {
+            azimuth: 1.13,
+            elevation: std::f32::consts::PI / 4.,
+            illuminance: 30000.,
+            color: Color::WHITE,
+            shadows_enabled: false,
+        }
+    }
+}
+
+impl SunSettings {
+    pub fn rotation(&self) -> Quat {
+        sun_rotation(self.azimuth, self.elevation)
+    }
+}
+
+pub fn sun_rotation(azimuth: f32, elevation: f32) -> Quat {
+    Quat::from_rotation_x(-elevation) * Quat::from_rotation_z(azimuth)
+}
+
+/// Moves the sun around over a day of `period` seconds of gameplay, overriding the
+/// [`SunSettings`] sliders while enabled
+#[derive(Resource)]
+pub struct DayNightCycle {
+    pub enabled: bool,
+    /// Seconds in a day
+    pub period: f32,
+    /// Elevation at noon, in radians
+    pub max_elevation: f32,
+    pub day_illuminance: f32,
+    /// Left over while the sun is down, so the arena doesn't go pitch black
+    pub night_illuminance: f32,
+    /// Sun color near the horizon
+    pub sunset_color: Color,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        DayNightCycle {
+            enabled: false,
+            period: 120.0,
+            max_elevation: std::f32::consts::PI / 3.,
+            day_illuminance: 30000.,
+            night_illuminance: 500.,
+            sunset_color: Color::rgb(1.0, 0.55, 0.3),
+        }
+    }
+}
+
+impl DayNightCycle {
+    /// Puts the sun where it is `time` seconds into the cycle. The day starts at noon, midnight
+    /// is half a period in.
+    pub fn apply(&self, time: f32, settings: &mut SunSettings) {
+        let phase = (time / self.period.max(f32::EPSILON)).fract();
+        let angle = phase * std::f32::consts::TAU;
+        // 1 at noon, 0 at sunrise and sunset, -1 at midnight
+        let height = angle.cos();
+
+        settings.azimuth = angle;
+        // below the horizon the light would come up through the floor, keep it skimming along
+        // instead and let the illuminance do the dimming
+        settings.elevation = (self.max_elevation * height).max(0.05);
+        settings.illuminance = self.night_illuminance
+            + (self.day_illuminance - self.night_illuminance) * height.max(0.0);
+
+        // warm close to the horizon
+        let warmth = 1.0 - (height.abs() * 4.0).min(1.0);
+        let [r, g, b, _] = Color::WHITE.as_rgba_f32();
+        let [sr, sg, sb, _] = self.sunset_color.as_rgba_f32();
+        settings.color = Color::rgb(
+            r + (sr - r) * warmth,
+            g + (sg - g) * warmth,
+            b + (sb - b) * warmth,
+        );
+    }
+}
+
+fn advance_day_night(
+    cycle: Res<DayNightCycle>,
+    game_time: Res<GameTime>,
+    mut settings: ResMut<SunSettings>,
+) {
+    if cycle.enabled {
+        cycle.apply(game_time.elapsed, &mut settings);
+    }
+}
+
+fn sun_window(
+    mut settings: ResMut<SunSettings>,
+    mut cycle: ResMut<DayNightCycle>,
+    mut egui_contexts: EguiContexts,
+) {
+    egui::Window::new("Sun").show(egui_contexts.ctx_mut(), |ui| {
+        let mut cycle_enabled = cycle.enabled;
+        let mut period = cycle.period;
+        ui.checkbox(&mut cycle_enabled, "Day/night cycle");
+        ui.add_enabled(
+            cycle_enabled,
+            egui::Slider::new(&mut period, 10.0..=600.0).text("Day length"),
+        );
+        if cycle_enabled != cycle.enabled || period != cycle.period {
+            cycle.enabled = cycle_enabled;
+            cycle.period = period;
+        }
+
+        let mut azimuth = settings.azimuth.to_degrees();
+        let mut elevation = settings.elevation.to_degrees();
+        let mut illuminance = settings.illuminance;
+        let mut shadows_enabled = settings.shadows_enabled;
+
+        ui.add(egui::Slider::new(&mut azimuth, 0.0..=360.0).text("Azimuth"));
+        ui.add(egui::Slider::new(&mut elevation, 0.0..=90.0).text("Elevation"));
+        ui.add(
+            egui::Slider::new(&mut illuminance, 0.0..=100000.0)
+                .logarithmic(true)
+                .text("Illuminance"),
+        );
+        ui.add(egui::Checkbox::new(&mut shadows_enabled, "Shadows"));
+
+        // only touch the resource on actual edits, so change detection stays quiet
+        if azimuth != settings.azimuth.to_degrees() {
+            settings.azimuth = azimuth.to_radians();
+        }
+        if elevation != settings.elevation.to_degrees() {
+            settings.elevation = elevation.to_radians();
+        }
+        if illuminance != settings.illuminance {
+            settings.illuminance = illuminance;
+        }
+        if shadows_enabled != settings.shadows_enabled {
+            settings.shadows_enabled = shadows_enabled;
+        }
+    });
+}
+
+fn update_sun(
+    settings: Res<SunSettings>,
+    mut suns: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for (mut transform, mut light) in suns.iter_mut() {
+        transform.rotation = settings.rotation();
+        light.illuminance = settings.illuminance;
+        light.color = settings.color;
+        light.shadows_enabled = settings.shadows_enabled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    #[test]
+    fn azimuth_and_elevation_give_the_expected_rotation() {
+        assert!(sun_rotation(0.0, 0.0).abs_diff_eq(Quat::IDENTITY, 1e-6));
+
+        // the angle the sun used to be hardcoded at
+        let settings = SunSettings {
+            azimuth: 1.13,
+            elevation: FRAC_PI_4,
+            ..default()
+        };
+        let expected = Quat::from_rotation_x(-FRAC_PI_4) * Quat::from_rotation_z(1.13);
+        assert!(settings.rotation().abs_diff_eq(expected, 1e-6));
+
+        // a quarter turn of elevation tips the light's forward from -z over to -y
+        let forward = sun_rotation(0.0, FRAC_PI_2) * Vec3::NEG_Z;
+        assert!(forward.abs_diff_eq(Vec3::NEG_Y, 1e-6));
+    }
+
+    #[test]
+    fn shadows_checkbox_toggles_the_light() {
+        let mut app = App::new();
+        app.init_resource::<SunSettings>().add_system(update_sun);
+        let sun = app
+            .world
+            .spawn((Transform::default(), DirectionalLight::default(), Sun))
+            .id();
+        let shadows = |app: &App| {
+            app.world
+                .get::<DirectionalLight>(sun)
+                .unwrap()
+                .shadows_enabled
+        };
+
+        app.update();
+        assert!(!shadows(&app));
+
+        app.world.resource_mut::<SunSettings>().shadows_enabled = true;
+        app.update();
+        assert!(shadows(&app));
+
+        app.world.resource_mut::<SunSettings>().shadows_enabled = false;
+        app.update();
+        assert!(!shadows(&app));
+    }
+
+    #[test]
+    fn noon_and_midnight_are_half_a_period_apart() {
+        let cycle = DayNightCycle::default();
+        let mut noon = SunSettings::default();
+        cycle.apply(0.0, &mut noon);
+        let mut midnight = SunSettings::default();
+        cycle.apply(cycle.period / 2.0, &mut midnight);
+
+        assert_eq!(noon.azimuth, 0.0);
+        assert!((noon.elevation - cycle.max_elevation).abs() < 1e-6);
+        assert_eq!(noon.illuminance, cycle.day_illuminance);
+        assert_eq!(noon.color, Color::WHITE);
+
+        assert!((midnight.azimuth - std::f32::consts::PI).abs() < 1e-5);
+        assert!(midnight.elevation < noon.elevation);
+        assert_eq!(midnight.illuminance, cycle.night_illuminance);
+
+        // a whole day later it's noon again
+        let mut next_noon = SunSettings::default();
+        cycle.apply(cycle.period, &mut next_noon);
+        assert_eq!(next_noon.illuminance, noon.illuminance);
+    }
+}
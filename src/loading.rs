@@ -0,0 +1,152 @@
+//! Watches assets the scene can't do without and falls back when they fail to load
+use crate::sounds::EatSound;
+use bevy::asset::{Asset, LoadState};
+use bevy::prelude::*;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CriticalAssets>()
+            .add_system(check_critical_assets);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CriticalAsset {
+    /// The petri dish scene
+    Arena,
+    EnvironmentMap,
+    Shader,
+    /// See [`EatSound`]
+    EatSound,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fallback {
+    /// Nothing to draw behind the blobs, use a plain clear color instead
+    ClearColor,
+    /// Remove `EnvironmentMapLight` from the cameras
+    SkipEnvironmentMap,
+    /// Nothing sensible to fall back to, just say so
+    WarnOnly,
+    /// Play without sound effects
+    Mute,
+}
+
+impl CriticalAsset {
+    pub fn fallback(self) -> Fallback {
+        match self {
+            CriticalAsset::Arena => Fallback::ClearColor,
+            CriticalAsset::EnvironmentMap => Fallback::SkipEnvironmentMap,
+            CriticalAsset::Shader => Fallback::WarnOnly,
+            CriticalAsset::EatSound => Fallback::Mute,
+        }
+    }
+}
+
+/// What to do about an asset in `state`, None while it's loading or once it has loaded
+pub fn fallback_for(asset: CriticalAsset, state: LoadState) -> Option<Fallback> {
+    match state {
+        LoadState::Failed => Some(asset.fallback()),
+        _ => None,
+    }
+}
+
+/// Assets still being watched by [`check_critical_assets`]
+#[derive(Resource, Default)]
+pub struct CriticalAssets {
+    pending: Vec<(CriticalAsset, String, HandleUntyped)>,
+}
+
+impl CriticalAssets {
+    /// Loads `path` and watches it until it has loaded or failed
+    pub fn load<T: Asset>(
+        &mut self,
+        asset_server: &AssetServer,
+        asset: CriticalAsset,
+        path: &str,
+    ) -> Handle<T> {
+        let handle = asset_server.load(path);
+        self.pending
+            .push((asset, path.to_string(), handle.clone_untyped()));
+        handle
+    }
+}
+
+fn check_critical_assets(
+    mut commands: Commands,
+    mut assets: ResMut<CriticalAssets>,
+    asset_server: Res<AssetServer>,
+    environment_maps: Query<Entity, With<EnvironmentMapLight>>,
+) {
+    if assets.pending.is_empty() {
+        return;
+    }
+
+    assets.pending.retain(|(asset, path, handle)| {
+        let state = asset_server.get_load_state(handle);
+        match fallback_for(*asset, state) {
+            Some(Fallback::ClearColor) => {
+                warn!("{path} failed to load, drawing a plain background instead");
+                commands.insert_resource(ClearColor(Color::rgb(0.08, 0.08, 0.1)));
+            }
+            Some(Fallback::SkipEnvironmentMap) => {
+                warn!("{path} failed to load, disabling the environment map");
+                for entity in environment_maps.iter() {
+                    commands.entity(entity).remove::<EnvironmentMapLight>();
+                }
+            }
+            Some(Fallback::WarnOnly) => {
+                warn!("{path} failed to load, blobs won't be drawn");
+            }
+            Some(Fallback::Mute) => {
+                warn!("{path} failed to load, playing without sound");
+                commands.remove_resource::<EatSound>();
+            }
+            None => return state != LoadState::Loaded,
+        }
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_failed_assets_fall_back() {
+        for asset in [
+            CriticalAsset::Arena,
+            CriticalAsset::EnvironmentMap,
+            CriticalAsset::Shader,
+            CriticalAsset::EatSound,
+        ] {
+            for state in [
+                LoadState::NotLoaded,
+                LoadState::Loading,
+                LoadState::Loaded,
+                LoadState::Unloaded,
+            ] {
+                assert_eq!(fallback_for(asset, state), None, "{:?} {:?}", asset, state);
+            }
+        }
+
+        assert_eq!(
+            fallback_for(CriticalAsset::Arena, LoadState::Failed),
+            Some(Fallback::ClearColor)
+        );
+        assert_eq!(
+            fallback_for(CriticalAsset::EnvironmentMap, LoadState::Failed),
+            Some(Fallback::SkipEnvironmentMap)
+        );
+        assert_eq!(
+            fallback_for(CriticalAsset::Shader, LoadState::Failed),
+            Some(Fallback::WarnOnly)
+        );
+        assert_eq!(
+            fallback_for(CriticalAsset::EatSound, LoadState::Failed),
+            Some(Fallback::Mute)
+        );
+    }
+}
@@ -1,24 +1,72 @@
 use crate::camera::PanOrbitCamera;
+use crate::loading::{CriticalAsset, CriticalAssets};
+use crate::progress::{PlayerProgress, ProgressConfig};
 use crate::raymarching::Blob;
+use crate::rng::GameRng;
+use crate::simulation::SimulationSet;
+use bevy::core_pipeline::clear_color::ClearColorConfig;
 use bevy::core_pipeline::prepass::{DepthPrepass, NormalPrepass};
 use bevy::math::Vec3Swizzles;
 use bevy::pbr::CascadeShadowConfigBuilder;
+use bevy::window::PrimaryWindow;
 use bevy::{
-    core_pipeline::tonemapping::Tonemapping, diagnostic::FrameTimeDiagnosticsPlugin, math::vec3,
-    prelude::*, render::renderer::RenderDevice, window::CursorGrabMode,
+    diagnostic::FrameTimeDiagnosticsPlugin, math::vec3, prelude::*, window::CursorGrabMode,
 };
 use bevy_easings::Lerp;
-use bevy_egui::EguiPlugin;
+use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use smooth_bevy_cameras::controllers::orbit::{
     OrbitCameraBundle, OrbitCameraController, OrbitCameraPlugin,
 };
 use smooth_bevy_cameras::{LookTransform, LookTransformPlugin, Smoother};
 
+mod ai;
+mod arena_shrink;
 mod bvh;
 mod camera;
+mod field_debug;
+mod game;
+mod high_score;
+mod lighting;
+mod loading;
+mod minimap;
+mod name_tags;
+mod occlusion;
+mod particles;
+mod pellets;
+mod perf_overlay;
+mod post_processing;
+mod progress;
+mod quadtree;
 mod raymarching;
+mod reticle;
+mod rng;
+mod rotating_buffer;
+mod selection;
+mod simulation;
+mod sounds;
+mod spawn_tool;
+mod split;
+mod suction;
+mod teams;
+#[cfg(test)]
+mod test_support;
+mod trail;
+mod viruses;
 
 fn main() {
+    // `--simulate <ticks>` plays the starting layout headless instead, for balancing
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--simulate") {
+        let ticks = args
+            .next()
+            .and_then(|ticks| ticks.parse().ok())
+            .unwrap_or(1000);
+        if let Err(error) = simulation::run_balancing(ticks, &mut std::io::stdout()) {
+            eprintln!("balancing run failed: {}", error);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(
             DefaultPlugins
@@ -34,52 +82,96 @@ fn main() {
         .add_plugin(EguiPlugin)
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
         .add_plugin(bevy_fps_window::FpsWindowPlugin)
+        .add_plugin(simulation::SimulationPlugin)
+        .add_plugin(game::GamePlugin)
+        .add_plugin(ai::AiPlugin)
         .add_plugin(raymarching::RaymarchingPlugin)
+        .add_plugin(selection::SelectionPlugin)
         .add_plugin(bevy_mod_gizmos::GizmosPlugin)
         .add_plugin(bvh::BvhPlugin)
+        .add_plugin(minimap::MinimapPlugin)
+        .add_plugin(name_tags::NameTagPlugin)
+        .add_plugin(lighting::LightingPlugin)
+        .add_plugin(loading::LoadingPlugin)
+        .add_plugin(perf_overlay::PerfOverlayPlugin)
+        .add_plugin(trail::TrailPlugin)
+        .add_plugin(split::SplitPlugin)
+        .add_plugin(pellets::PelletPlugin)
+        .add_plugin(post_processing::PostProcessingPlugin)
+        .add_plugin(viruses::VirusPlugin)
+        .add_plugin(particles::ParticlePlugin)
+        .add_plugin(field_debug::FieldDebugPlugin)
+        .add_plugin(occlusion::OcclusionPlugin)
+        .add_plugin(spawn_tool::SpawnToolPlugin)
+        .add_plugin(progress::ProgressPlugin)
+        .add_plugin(arena_shrink::ArenaShrinkPlugin)
+        .add_plugin(sounds::SoundPlugin)
+        .add_plugin(high_score::HighScorePlugin)
+        .add_plugin(teams::TeamPlugin)
+        .add_plugin(suction::SuctionPlugin)
+        .add_plugin(reticle::ReticlePlugin)
+        .init_resource::<Arena>()
+        .init_resource::<ArenaBounds>()
+        .init_resource::<GameRng>()
+        .init_resource::<InputConfig>()
+        .init_resource::<MovementConfig>()
+        .init_resource::<CursorWorldPosition>()
+        .init_resource::<GizmoQuality>()
         .add_startup_system(setup)
-        // .add_startup_system(print_render_limits)
         // .add_system(draw_debug_gizmos)
-        .add_system(handle_player_input)
-        .add_system(follow_player)
+        .add_system(
+            follow_player
+                .after(SimulationSet::Interpolate)
+                .before(camera::blend_camera_transition)
+                .run_if(camera::following),
+        )
+        .add_system(update_cursor_world_position)
+        .add_system(controls_window)
+        .add_system(draw_arena_bounds)
         .run();
 }
 
-fn print_render_limits(dev: Res<RenderDevice>) {
-    println!("{:#?}", dev.limits());
-}
-
 fn draw_debug_gizmos() {
     bevy_mod_gizmos::draw_closed_line(vec![Vec3::ZERO, Vec3::X * 3.], Color::RED);
     bevy_mod_gizmos::draw_closed_line(vec![Vec3::ZERO, Vec3::Y * 3.], Color::GREEN);
     bevy_mod_gizmos::draw_closed_line(vec![Vec3::ZERO, Vec3::Z * 3.], Color::BLUE);
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sun_settings: Res<lighting::SunSettings>,
+    post_processing: Res<post_processing::PostProcessing>,
+    camera_settings: Res<camera::CameraSettings>,
+    mut critical_assets: ResMut<CriticalAssets>,
+) {
     // directional 'sun' light
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            illuminance: 30000.,
-            // shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, 0.0, 4.0),
-            rotation: Quat::from_rotation_x(-std::f32::consts::PI / 4.)
-                * Quat::from_rotation_z(1.13),
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                illuminance: sun_settings.illuminance,
+                color: sun_settings.color,
+                shadows_enabled: sun_settings.shadows_enabled,
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 4.0),
+                rotation: sun_settings.rotation(),
+                ..default()
+            },
+            // The default cascade config is designed to handle large scenes.
+            // As this example has a much smaller world, we can tighten the shadow
+            // bounds for better visual quality.
+            cascade_shadow_config: CascadeShadowConfigBuilder {
+                first_cascade_far_bound: 4.0,
+                maximum_distance: 10.0,
+                ..default()
+            }
+            .into(),
             ..default()
         },
-        // The default cascade config is designed to handle large scenes.
-        // As this example has a much smaller world, we can tighten the shadow
-        // bounds for better visual quality.
-        // cascade_shadow_config: CascadeShadowConfigBuilder {
-        //     first_cascade_far_bound: 4.0,
-        //     maximum_distance: 10.0,
-        //     ..default()
-        // }
-        // .into(),
-        ..default()
-    });
+        lighting::Sun,
+    ));
 
     commands.spawn((
         Camera3dBundle {
@@ -87,97 +179,916 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 hdr: true,
                 ..default()
             },
-            tonemapping: Tonemapping::BlenderFilmic,
+            camera_3d: Camera3d {
+                // the ClearColor resource, which the Post processing window edits
+                clear_color: ClearColorConfig::Default,
+                ..default()
+            },
+            tonemapping: post_processing.tonemapping,
             transform: Transform::from_xyz(0.0, 12., 6.0)
-                .looking_at(Vec3::new(0., 0., 1.), Vec3::Z),
+                .looking_at(Vec3::new(0., 0., 1.), WORLD_UP),
             ..default()
         },
         DepthPrepass::default(),
         NormalPrepass::default(),
-        // camera::PanOrbitCamera {
-        //     radius: 3.0,
-        //     focus: vec3(0.0, 0.0, 1.0),
-        //     ..default()
-        // },
-        LookTransform::new(vec3(0., -7., 5.), Vec3::ZERO, Vec3::Z),
-        Smoother::new(0.6),
+        // only moves the camera in `CameraMode::Orbit`, which sets its focus and radius
+        PanOrbitCamera::default(),
+        LookTransform::new(vec3(0., -7., 5.), Vec3::ZERO, WORLD_UP),
+        Smoother::new(camera_settings.follow_smoothing),
         EnvironmentMapLight {
-            diffuse_map: asset_server.load("environment_maps/diffuse (1).ktx2"),
-            specular_map: asset_server.load("environment_maps/specular (1).ktx2"),
+            diffuse_map: critical_assets.load(
+                &asset_server,
+                CriticalAsset::EnvironmentMap,
+                "environment_maps/diffuse (1).ktx2",
+            ),
+            specular_map: critical_assets.load(
+                &asset_server,
+                CriticalAsset::EnvironmentMap,
+                "environment_maps/specular (1).ktx2",
+            ),
         },
     ));
 
     commands.spawn(SceneBundle {
-        scene: asset_server.load("petri.glb#Scene0"),
+        scene: critical_assets.load(&asset_server, CriticalAsset::Arena, "petri.glb#Scene0"),
         ..default()
     });
+
+    // the material loads these itself, the handles here are just for load state
+    for path in [
+        "shaders/voxel_material.wgsl",
+        "shaders/voxel_raymarch.wgsl",
+        "shaders/raymarching_common.wgsl",
+    ] {
+        let _: Handle<Shader> = critical_assets.load(&asset_server, CriticalAsset::Shader, path);
+    }
 }
 
+/// The world is Z-up: blobs move on the XY plane, turn around Z, and cameras use this as their up
+/// vector. Bevy's own defaults (`Transform::looking_at` callers, `Camera3dBundle`) assume Y-up, so
+/// pass this explicitly.
+pub const WORLD_UP: Vec3 = Vec3::Z;
+
 #[derive(Component)]
 pub struct PlayerInput;
 
-fn handle_player_input(
-    mut player_blob: Query<(&mut Transform, &mut Blob), With<PlayerInput>>,
+/// The player's pieces taken as a whole, after a split there can be several
+pub struct PlayerGroup {
+    /// Area weighted center of all the pieces
+    pub centroid: Vec3,
+    /// Size and direction of the biggest piece, the rest steer the same way
+    pub lead_size: f32,
+    pub lead_direction: f32,
+}
+
+impl PlayerGroup {
+    /// None if there are no pieces
+    pub fn from_pieces<'a>(
+        pieces: impl IntoIterator<Item = (&'a Transform, &'a Blob)>,
+    ) -> Option<Self> {
+        let mut weighted_position = Vec3::ZERO;
+        let mut total_area = 0.0;
+        let mut lead: Option<&Blob> = None;
+        for (transform, blob) in pieces {
+            weighted_position += transform.translation * blob.area();
+            total_area += blob.area();
+            if lead.map_or(true, |lead| blob.size > lead.size) {
+                lead = Some(blob);
+            }
+        }
+
+        let lead = lead?;
+        Some(PlayerGroup {
+            centroid: weighted_position / f32::max(total_area, f32::EPSILON),
+            lead_size: lead.size,
+            lead_direction: lead.direction,
+        })
+    }
+}
+
+/// The shape blobs are kept inside of, centered on the origin
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub enum Arena {
+    Circle { radius: f32 },
+    Rect { half_extents: Vec2 },
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::Circle { radius: 9.8 }
+    }
+}
+
+impl Arena {
+    /// Pulls a blob with radius `blob_size` at `position` back inside the arena, letting it poke
+    /// out by up to two thirds of its radius
+    pub fn clamp(&self, position: Vec3, blob_size: f32) -> Vec3 {
+        self.clamp_inset(position, blob_size * 0.33)
+    }
+
+    /// Pulls `position` back to at least `inset` inside the edge, negative insets allow going
+    /// that far outside. Only x and y are touched.
+    pub fn clamp_inset(&self, position: Vec3, inset: f32) -> Vec3 {
+        let horizontal = match *self {
+            Arena::Circle { radius } => position.xy().clamp_length_max((radius - inset).max(0.0)),
+            Arena::Rect { half_extents } => {
+                let limit = (half_extents - Vec2::splat(inset)).max(Vec2::ZERO);
+                position.xy().clamp(-limit, limit)
+            }
+        };
+        horizontal.extend(position.z)
+    }
+
+    /// Uniformly random point at least `inset` inside the edge, on the z=`height` plane. None if
+    /// a few tries all landed outside, which only happens for tiny arenas.
+    pub fn random_point(&self, rng: &mut GameRng, inset: f32, height: f32) -> Option<Vec3> {
+        // rejection sample the bounding rectangle, clamping would pile points up on the edge
+        let extent = self.extent();
+        (0..8)
+            .map(|_| {
+                Vec3::new(
+                    rng.range(-extent.x, extent.x),
+                    rng.range(-extent.y, extent.y),
+                    height,
+                )
+            })
+            .find(|point| self.clamp_inset(*point, inset) == *point)
+    }
+
+    /// Half extents of the arena's bounding rectangle
+    pub fn extent(&self) -> Vec2 {
+        match *self {
+            Arena::Circle { radius } => Vec2::splat(radius),
+            Arena::Rect { half_extents } => half_extents,
+        }
+    }
+
+    /// Moves every edge in by `amount`, but not past `min_radius` from the center
+    pub fn shrink(&mut self, amount: f32, min_radius: f32) {
+        match self {
+            Arena::Circle { radius } => {
+                *radius = (*radius - amount).max(min_radius.min(*radius));
+            }
+            Arena::Rect { half_extents } => {
+                *half_extents =
+                    (*half_extents - amount).max(Vec2::splat(min_radius).min(*half_extents));
+            }
+        }
+    }
+
+    /// Radius of the largest circle that fits inside
+    pub fn inner_radius(&self) -> f32 {
+        match *self {
+            Arena::Circle { radius } => radius,
+            Arena::Rect { half_extents } => half_extents.min_element(),
+        }
+    }
+
+    /// Points along the edge on the z=0 plane, for drawing. Circles are split into
+    /// `circle_segments` straight segments.
+    pub fn outline(&self, circle_segments: usize) -> Vec<Vec3> {
+        match *self {
+            Arena::Circle { radius } => circle_points(Vec3::ZERO, radius, circle_segments),
+            Arena::Rect { half_extents } => [
+                vec3(-half_extents.x, -half_extents.y, 0.0),
+                vec3(half_extents.x, -half_extents.y, 0.0),
+                vec3(half_extents.x, half_extents.y, 0.0),
+                vec3(-half_extents.x, half_extents.y, 0.0),
+            ]
+            .to_vec(),
+        }
+    }
+}
+
+/// How blobs that went past the arena edge get pushed back in, see [`apply_arena_bounds`]
+#[derive(Resource)]
+pub struct ArenaBounds {
+    /// How hard blobs past the edge are pushed back, higher is closer to a hard clamp
+    pub stiffness: f32,
+    /// Blobs can't end up further past the edge than this, however fast they're going
+    pub max_overshoot: f32,
+}
+
+impl Default for ArenaBounds {
+    fn default() -> Self {
+        ArenaBounds {
+            stiffness: 20.0,
+            max_overshoot: 0.2,
+        }
+    }
+}
+
+impl ArenaBounds {
+    /// Where a blob with radius `blob_size` at `position` ends up after `delta` seconds of being
+    /// pushed back inside `arena`. Only the part of the movement going out is pushed back, so
+    /// blobs slide along the edge.
+    pub fn push(&self, arena: &Arena, position: Vec3, blob_size: f32, delta: f32) -> Vec3 {
+        let inside = arena.clamp(position, blob_size);
+        if inside == position {
+            return position;
+        }
+
+        let pushed = position.lerp(inside, 1.0 - (-self.stiffness * delta).exp());
+        arena.clamp_inset(pushed, blob_size * 0.33 - self.max_overshoot)
+    }
+}
+
+/// Pushes every blob that went past the edge of the [`Arena`] back in
+pub(crate) fn apply_arena_bounds(
+    mut blobs: Query<(&mut Transform, &Blob)>,
+    arena: Res<Arena>,
+    bounds: Res<ArenaBounds>,
+    fixed_time: Res<FixedTime>,
+) {
+    let delta = fixed_time.period.as_secs_f32();
+    for (mut transform, blob) in blobs.iter_mut() {
+        let pushed = bounds.push(&arena, transform.translation, blob.size, delta);
+        // only touch the ones that moved, so change detection stays quiet
+        if pushed != transform.translation {
+            transform.translation = pushed;
+        }
+    }
+}
+
+fn draw_arena_bounds(arena: Res<Arena>, quality: Res<GizmoQuality>) {
+    bevy_mod_gizmos::draw_closed_line(arena.outline(quality.circle_segments), Color::GRAY);
+}
+
+/// Tessellation of the circles drawn with gizmos
+#[derive(Resource)]
+pub struct GizmoQuality {
+    /// Straight segments per full circle
+    pub circle_segments: usize,
+}
+
+impl Default for GizmoQuality {
+    fn default() -> Self {
+        GizmoQuality {
+            circle_segments: 64,
+        }
+    }
+}
+
+/// `segments` evenly spaced points on a circle around `center` on its horizontal plane, for
+/// drawing as a closed line. Fewer than 3 segments can't make a loop and are bumped up to 3.
+pub fn circle_points(center: Vec3, radius: f32, segments: usize) -> Vec<Vec3> {
+    let segments = segments.max(3);
+    (0..segments)
+        .map(|i| {
+            let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+            center + (Vec2::from_angle(angle) * radius).extend(0.0)
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SteerMode {
+    /// Turn with A and D
+    Keys,
+    /// Turn towards the mouse cursor
+    Mouse,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementScheme {
+    /// Always move forward, turning with the `SteerMode`
+    TankTurn,
+    /// W/A/S/D move along the world axes, the blob faces where it's going
+    WorldAxes,
+}
+
+#[derive(Resource)]
+pub struct InputConfig {
+    pub movement_scheme: MovementScheme,
+    /// Only used with `MovementScheme::TankTurn`
+    pub steer_mode: SteerMode,
+    /// How fast the blob turns towards the cursor in mouse steering, in radians per second
+    pub mouse_turn_rate: f32,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            movement_scheme: MovementScheme::TankTurn,
+            steer_mode: SteerMode::Keys,
+            mouse_turn_rate: 4.0,
+        }
+    }
+}
+
+/// How the player blobs move
+#[derive(Resource)]
+pub struct MovementConfig {
+    pub speed: f32,
+    /// Radians per second when steering with keys, for blobs at the starting size
+    pub turn_rate: f32,
+    /// How much slower blobs turn per unit of size above the starting size, 0 for no slowdown
+    pub turn_size_falloff: f32,
+    /// How fast turning speeds up and slows down, in radians per second squared for blobs at
+    /// the starting size. Bigger blobs get less of it the same way they turn slower.
+    pub angular_acceleration: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig {
+            speed: 3.1,
+            turn_rate: 2.0,
+            turn_size_falloff: 0.5,
+            angular_acceleration: 12.0,
+        }
+    }
+}
+
+impl MovementConfig {
+    /// How much of a turn rate a blob of `size` gets, 1 at the starting size and below
+    pub fn turn_factor(&self, size: f32) -> f32 {
+        let growth = (size - Blob::default().size).max(0.0);
+        1.0 / (1.0 + growth * self.turn_size_falloff.max(0.0))
+    }
+
+    /// Key steering turn rate for a blob of `size`
+    pub fn turn_rate(&self, size: f32) -> f32 {
+        self.turn_rate * self.turn_factor(size)
+    }
+
+    /// Angular acceleration for a blob of `size`
+    pub fn angular_acceleration(&self, size: f32) -> f32 {
+        self.angular_acceleration * self.turn_factor(size)
+    }
+}
+
+/// How fast the player group is turning, see [`Steering::turn`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Steering {
+    /// Radians per second, counterclockwise is positive
+    pub angular_velocity: f32,
+}
+
+impl Steering {
+    /// Turns `direction` for `delta` seconds, easing the angular velocity towards `turn_input`
+    /// (-1 to 1) times `max_velocity` by at most `acceleration` per second
+    pub fn turn(
+        &mut self,
+        direction: f32,
+        turn_input: f32,
+        max_velocity: f32,
+        acceleration: f32,
+        delta: f32,
+    ) -> f32 {
+        self.accelerate(
+            turn_input.clamp(-1.0, 1.0) * max_velocity,
+            acceleration,
+            delta,
+        );
+        direction + self.angular_velocity * delta
+    }
+
+    /// Turns `direction` towards `target` the short way around, slowing down in time to stop
+    /// on it instead of swinging past
+    pub fn turn_towards(
+        &mut self,
+        direction: f32,
+        target: f32,
+        max_velocity: f32,
+        acceleration: f32,
+        delta: f32,
+    ) -> f32 {
+        let remaining = angle_difference(direction, target);
+        // fastest speed that can still brake to zero over the remaining angle
+        let braking_velocity = (2.0 * acceleration * remaining.abs()).sqrt();
+        let target_velocity = remaining.signum() * max_velocity.min(braking_velocity);
+        self.accelerate(target_velocity, acceleration, delta);
+
+        let step = self.angular_velocity * delta;
+        if step * remaining > 0.0 && step.abs() >= remaining.abs() {
+            // would overshoot, land on the target
+            self.angular_velocity = 0.0;
+            target
+        } else {
+            direction + step
+        }
+    }
+
+    fn accelerate(&mut self, target_velocity: f32, acceleration: f32, delta: f32) {
+        let max_change = acceleration * delta;
+        self.angular_velocity +=
+            (target_velocity - self.angular_velocity).clamp(-max_change, max_change);
+    }
+}
+
+/// Where the mouse cursor points on the z=0 plane. None when the cursor is outside the window,
+/// or egui is using it.
+#[derive(Resource, Default)]
+pub struct CursorWorldPosition(pub Option<Vec3>);
+
+pub(crate) fn update_cursor_world_position(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut cursor: ResMut<CursorWorldPosition>,
+    mut egui_contexts: EguiContexts,
+) {
+    cursor.0 = None;
+
+    if egui_contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+
+    if let (Ok(window), Ok((camera, camera_transform))) =
+        (windows.get_single(), cameras.get_single())
+    {
+        if let Some(cursor_position) = window.cursor_position() {
+            cursor.0 = cursor_to_world(camera, camera_transform, cursor_position);
+        }
+    }
+}
+
+/// Projects a cursor position in the viewport onto the z=0 plane
+pub fn cursor_to_world(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    cursor_position: Vec2,
+) -> Option<Vec3> {
+    ground_point(camera.viewport_to_world(camera_transform, cursor_position)?)
+}
+
+/// Where `ray` hits the z=0 plane, None if it runs parallel to it or points away
+pub fn ground_point(ray: Ray) -> Option<Vec3> {
+    let distance = ray.intersect_plane(Vec3::ZERO, Vec3::Z)?;
+    Some(ray.get_point(distance))
+}
+
+/// The `Blob::direction` that moves from `from` towards `to`. Blobs move along -y rotated by their
+/// direction.
+pub fn direction_towards(from: Vec2, to: Vec2) -> f32 {
+    let offset = to - from;
+    offset.x.atan2(-offset.y)
+}
+
+/// Turns `direction` towards `target` the short way around, by at most `max_turn` radians
+pub fn turn_towards(direction: f32, target: f32, max_turn: f32) -> f32 {
+    direction + angle_difference(direction, target).clamp(-max_turn, max_turn)
+}
+
+/// How far to turn from `direction` to face `target` the short way around, between -PI and PI
+pub fn angle_difference(direction: f32, target: f32) -> f32 {
+    (target - direction + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU)
+        - std::f32::consts::PI
+}
+
+fn controls_window(mut input_config: ResMut<InputConfig>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("Controls").show(egui_contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Movement");
+            ui.radio_value(
+                &mut input_config.movement_scheme,
+                MovementScheme::TankTurn,
+                "Tank",
+            );
+            ui.radio_value(
+                &mut input_config.movement_scheme,
+                MovementScheme::WorldAxes,
+                "WASD",
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Steering");
+            ui.radio_value(&mut input_config.steer_mode, SteerMode::Keys, "Keys");
+            ui.radio_value(&mut input_config.steer_mode, SteerMode::Mouse, "Mouse");
+        });
+    });
+}
+
+pub(crate) fn handle_player_input(
+    mut player_blobs: Query<(&mut Transform, &mut Blob), With<PlayerInput>>,
     keys: Res<Input<KeyCode>>,
-    time: Res<Time>,
+    input_config: Res<InputConfig>,
+    movement: Res<MovementConfig>,
+    progress: Res<PlayerProgress>,
+    progress_config: Res<ProgressConfig>,
+    cursor: Res<CursorWorldPosition>,
+    fixed_time: Res<FixedTime>,
+    mut steering: Local<Steering>,
 ) {
-    for (mut transform, mut blob) in player_blob.iter_mut() {
-        let mut move_vector = Vec3::ZERO;
-        move_vector.y = -1.0;
-
-        let mut direction = blob.direction;
-
-        // if keys.pressed(KeyCode::W) {
-        //     move_vector.y = 1.0;
-        // }
-        // if keys.pressed(KeyCode::S) {
-        //     move_vector.y = -1.0;
-        // }
-        // if keys.pressed(KeyCode::D) {
-        //     move_vector.x = 1.0;
-        // }
-        // if keys.pressed(KeyCode::A) {
-        //     move_vector.x = -1.0;
-        // }
-        if keys.pressed(KeyCode::A) {
-            direction += 1.0 * 2.0 * time.delta_seconds();
-        }
-        if keys.pressed(KeyCode::D) {
-            direction += -1.0 * 2.0 * time.delta_seconds();
-        }
-
-        // if move_vector.length() == 0.0 {
-        //     continue;
-        // }
+    let delta = fixed_time.period.as_secs_f32();
+    let perk = progress_config.perk_multiplier(progress.level);
 
-        blob.direction = direction;
+    // the pieces of a split player all steer as one, going by the biggest piece
+    let Some(group) = PlayerGroup::from_pieces(player_blobs.iter()) else {
+        return;
+    };
+
+    let mut move_vector = Vec3::ZERO;
+    let mut direction = group.lead_direction;
+    let acceleration = movement.angular_acceleration(group.lead_size) * perk;
 
-        transform.translation +=
-            Quat::from_rotation_z(direction) * move_vector.normalize() * 3.1 * time.delta_seconds();
+    match input_config.movement_scheme {
+        MovementScheme::TankTurn => {
+            move_vector.y = -1.0;
 
-        let transform_length = transform.translation.xy().length();
-        let play_area_size = 9.8;
-        if transform_length > play_area_size - blob.size * 0.33 {
-            let direction_to_center = -transform.translation.xy().normalize();
-            transform.translation += (direction_to_center
-                * (transform_length - play_area_size + blob.size * 0.33))
-                .extend(0.0);
+            match input_config.steer_mode {
+                SteerMode::Keys => {
+                    let mut turn_input = 0.0;
+                    if keys.pressed(KeyCode::A) {
+                        turn_input += 1.0;
+                    }
+                    if keys.pressed(KeyCode::D) {
+                        turn_input -= 1.0;
+                    }
+                    direction = steering.turn(
+                        direction,
+                        turn_input,
+                        movement.turn_rate(group.lead_size) * perk,
+                        acceleration,
+                        delta,
+                    );
+                }
+                SteerMode::Mouse => {
+                    let max_velocity =
+                        input_config.mouse_turn_rate * movement.turn_factor(group.lead_size) * perk;
+                    direction = match cursor.0 {
+                        Some(cursor) => steering.turn_towards(
+                            direction,
+                            direction_towards(group.centroid.xy(), cursor.xy()),
+                            max_velocity,
+                            acceleration,
+                            delta,
+                        ),
+                        // cursor over egui or outside the window, coast to a stop
+                        None => steering.turn(direction, 0.0, max_velocity, acceleration, delta),
+                    };
+                }
+            }
+
+            move_vector = Quat::from_axis_angle(WORLD_UP, direction) * move_vector;
+        }
+        MovementScheme::WorldAxes => {
+            if keys.pressed(KeyCode::W) {
+                move_vector.y += 1.0;
+            }
+            if keys.pressed(KeyCode::S) {
+                move_vector.y -= 1.0;
+            }
+            if keys.pressed(KeyCode::D) {
+                move_vector.x += 1.0;
+            }
+            if keys.pressed(KeyCode::A) {
+                move_vector.x -= 1.0;
+            }
+
+            // the blob moves along the keys right away, but takes a moment to face that way
+            let max_velocity = movement.turn_rate(group.lead_size) * perk;
+            direction = if move_vector != Vec3::ZERO {
+                steering.turn_towards(
+                    direction,
+                    direction_towards(Vec2::ZERO, move_vector.xy()),
+                    max_velocity,
+                    acceleration,
+                    delta,
+                )
+            } else {
+                steering.turn(direction, 0.0, max_velocity, acceleration, delta)
+            };
         }
     }
+
+    for (mut transform, mut blob) in player_blobs.iter_mut() {
+        blob.direction = direction;
+
+        transform.translation += move_vector.normalize_or_zero() * movement.speed * perk * delta;
+    }
 }
 
 fn follow_player(
     mut cameras: Query<&mut LookTransform>,
     player_blobs: Query<(&Transform, &Blob), With<PlayerInput>>,
+    input_config: Res<InputConfig>,
+    bounds: Res<camera::CameraBounds>,
+    arena: Res<Arena>,
 ) {
     let camera_offset = vec3(0., -7., 6.);
 
-    for (transform, blob) in player_blobs.iter() {
-        for mut camera in cameras.iter_mut() {
-            let camera_offset_rotated =
-                Quat::from_rotation_z(blob.direction + std::f32::consts::PI) * camera_offset;
-            camera.eye = transform.translation + camera_offset_rotated;
-            camera.target = transform.translation;
+    // frame all of the player's pieces
+    let Some(group) = PlayerGroup::from_pieces(player_blobs.iter()) else {
+        return;
+    };
+
+    for mut camera in cameras.iter_mut() {
+        // with world axes movement the camera stays put, so W is always up on screen
+        let camera_offset_rotated = match input_config.movement_scheme {
+            MovementScheme::TankTurn => {
+                Quat::from_axis_angle(WORLD_UP, group.lead_direction + std::f32::consts::PI)
+                    * camera_offset
+            }
+            MovementScheme::WorldAxes => camera_offset,
+        };
+        camera.eye = bounds.clamp_eye(group.centroid + camera_offset_rotated);
+        camera.target = bounds.clamp_focus(group.centroid, &arena);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where a blob facing `direction` heads, blobs move along -y rotated by their direction
+    fn heading(direction: f32) -> Vec2 {
+        (Quat::from_axis_angle(WORLD_UP, direction) * Vec3::NEG_Y).xy()
+    }
+
+    #[test]
+    fn cursor_position_becomes_a_direction_towards_it() {
+        let blob = Vec2::new(1.0, 2.0);
+        for cursor in [
+            Vec2::new(1.0, -3.0),
+            Vec2::new(4.0, 2.0),
+            Vec2::new(-2.0, 5.0),
+            Vec2::new(0.0, 0.0),
+        ] {
+            let direction = direction_towards(blob, cursor);
+            let expected = (cursor - blob).normalize();
+            assert!(
+                heading(direction).abs_diff_eq(expected, 1e-5),
+                "{:?} heads {:?}, not towards {:?}",
+                direction,
+                heading(direction),
+                cursor
+            );
         }
+        // straight ahead of a blob that hasn't turned is -y
+        assert_eq!(direction_towards(Vec2::ZERO, Vec2::new(0.0, -1.0)), 0.0);
+    }
+
+    #[test]
+    fn angle_difference_goes_the_short_way_around() {
+        use std::f32::consts::PI;
+        assert!((angle_difference(0.1, -0.1) + 0.2).abs() < 1e-6);
+        assert!((angle_difference(PI - 0.1, -PI + 0.1) - 0.2).abs() < 1e-5);
+        assert!((turn_towards(0.0, 2.0, 0.5) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn arena_clamps_circles_and_rectangles() {
+        let circle = Arena::Circle { radius: 10.0 };
+        let inside = Vec3::new(3.0, 4.0, 1.0);
+        assert_eq!(circle.clamp_inset(inside, 0.0), inside);
+        let clamped = circle.clamp_inset(Vec3::new(30.0, 40.0, 1.0), 0.0);
+        assert!(
+            clamped.abs_diff_eq(Vec3::new(6.0, 8.0, 1.0), 1e-5),
+            "{}",
+            clamped
+        );
+        // a blob can poke out by two thirds of its radius
+        let edge = circle.clamp(Vec3::new(20.0, 0.0, 1.0), 3.0);
+        assert!((edge.x - 9.01).abs() < 1e-5, "{}", edge);
+
+        let rect = Arena::Rect {
+            half_extents: Vec2::new(5.0, 2.0),
+        };
+        let inside_rect = Vec3::new(3.0, 1.0, 1.0);
+        assert_eq!(rect.clamp_inset(inside_rect, 0.0), inside_rect);
+        assert_eq!(
+            rect.clamp_inset(Vec3::new(9.0, 0.5, 1.0), 0.0),
+            Vec3::new(5.0, 0.5, 1.0)
+        );
+        // corners clamp on both axes, not towards the center
+        assert_eq!(
+            rect.clamp_inset(Vec3::new(-9.0, 7.0, 1.0), 0.0),
+            Vec3::new(-5.0, 2.0, 1.0)
+        );
+        assert_eq!(
+            rect.clamp_inset(Vec3::new(9.0, -7.0, 1.0), 1.0),
+            Vec3::new(4.0, -1.0, 1.0)
+        );
+        // an inset wider than the arena pins things to the middle
+        assert_eq!(
+            rect.clamp_inset(Vec3::new(9.0, -7.0, 1.0), 3.0),
+            Vec3::new(2.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn bigger_blobs_turn_slower() {
+        let movement = MovementConfig {
+            turn_rate: 2.0,
+            turn_size_falloff: 0.5,
+            ..default()
+        };
+        let start = Blob::default().size;
+        assert_eq!(movement.turn_rate(start), 2.0);
+        // nothing faster for blobs smaller than the start
+        assert_eq!(movement.turn_rate(start * 0.5), 2.0);
+        assert_eq!(movement.turn_rate(start + 2.0), 1.0);
+        assert!(movement.angular_acceleration(start + 2.0) < movement.angular_acceleration(start));
+
+        let no_falloff = MovementConfig {
+            turn_size_falloff: 0.0,
+            ..movement
+        };
+        assert_eq!(no_falloff.turn_rate(start + 10.0), 2.0);
+    }
+
+    #[test]
+    fn steering_turns_at_most_the_turn_rate() {
+        let mut steering = Steering::default();
+        let mut direction = 0.0;
+        for _ in 0..120 {
+            direction = steering.turn(direction, 1.0, 2.0, 12.0, 1.0 / 60.0);
+            assert!(steering.angular_velocity <= 2.0);
+        }
+        // up to speed after a sixth of a second, then steady at 2 radians per second
+        assert!((steering.angular_velocity - 2.0).abs() < 1e-5);
+        assert!(direction > 3.5 && direction < 4.0, "{}", direction);
+    }
+
+    /// Runs `handle_player_input` on its own, with `key` held down
+    fn player_input_app(movement_scheme: MovementScheme, key: KeyCode) -> App {
+        let mut app = App::new();
+        app.insert_resource(InputConfig {
+            movement_scheme,
+            ..default()
+        })
+        .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+        .init_resource::<Input<KeyCode>>()
+        .init_resource::<MovementConfig>()
+        .init_resource::<PlayerProgress>()
+        .init_resource::<ProgressConfig>()
+        .init_resource::<CursorWorldPosition>()
+        .add_system(handle_player_input);
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+        app
+    }
+
+    #[test]
+    fn wasd_d_moves_the_player_along_x() {
+        let mut app = player_input_app(MovementScheme::WorldAxes, KeyCode::D);
+        let player = app
+            .world
+            .spawn((Transform::default(), Blob::default(), PlayerInput))
+            .id();
+
+        for _ in 0..30 {
+            app.update();
+        }
+
+        let translation = app.world.get::<Transform>(player).unwrap().translation;
+        assert!(translation.x > 0.0, "{}", translation);
+        assert_eq!(translation.y, 0.0);
+        // and it turns to face that way
+        let direction = app.world.get::<Blob>(player).unwrap().direction;
+        let facing = heading(direction);
+        assert!(facing.x > 0.0, "facing {}", facing);
+    }
+
+    #[test]
+    fn split_player_pieces_all_turn_together() {
+        let mut app = player_input_app(MovementScheme::TankTurn, KeyCode::A);
+        let big = Blob {
+            size: 0.6,
+            direction: 0.5,
+            ..default()
+        };
+        let small = Blob {
+            size: 0.3,
+            direction: -1.0,
+            ..default()
+        };
+        let pieces = [
+            app.world
+                .spawn((Transform::from_xyz(-1.0, 0.0, 0.0), big, PlayerInput))
+                .id(),
+            app.world
+                .spawn((Transform::from_xyz(1.0, 0.0, 0.0), small, PlayerInput))
+                .id(),
+        ];
+
+        for _ in 0..30 {
+            app.update();
+        }
+
+        let directions: Vec<f32> = pieces
+            .iter()
+            .map(|&piece| app.world.get::<Blob>(piece).unwrap().direction)
+            .collect();
+        // both follow the biggest piece, turning left
+        assert_eq!(directions[0], directions[1]);
+        assert!(directions[0] > 0.5, "{}", directions[0]);
+        let moved: Vec<Vec3> = pieces
+            .iter()
+            .map(|&piece| app.world.get::<Transform>(piece).unwrap().translation)
+            .collect();
+        assert!((moved[0] - Vec3::new(-1.0, 0.0, 0.0)).abs_diff_eq(moved[1] - Vec3::X, 1e-5));
+    }
+
+    #[test]
+    fn circle_points_make_an_even_closed_loop() {
+        let center = Vec3::new(1.0, 2.0, 0.5);
+        let points = circle_points(center, 3.0, 12);
+        assert_eq!(points.len(), 12);
+        assert!(points
+            .iter()
+            .all(|point| (point.distance(center) - 3.0).abs() < 1e-5 && point.z == center.z));
+
+        // the last point connects back to the first like any other pair
+        let side = points[0].distance(points[1]);
+        for (i, point) in points.iter().enumerate() {
+            let next = points[(i + 1) % points.len()];
+            assert!((point.distance(next) - side).abs() < 1e-4, "side {}", i);
+        }
+
+        assert_eq!(circle_points(center, 3.0, 1).len(), 3);
+    }
+
+    #[test]
+    fn cursor_rays_land_on_the_ground() {
+        let eye = Vec3::new(0.0, -10.0, 10.0);
+        let target = Vec3::new(2.0, 3.0, 0.0);
+        let ray = Ray {
+            origin: eye,
+            direction: (target - eye).normalize(),
+        };
+        assert!(ground_point(ray).unwrap().abs_diff_eq(target, 1e-4));
+
+        let level = Ray {
+            origin: eye,
+            direction: Vec3::X,
+        };
+        assert_eq!(ground_point(level), None);
+        let upwards = Ray {
+            origin: eye,
+            direction: Vec3::Z,
+        };
+        assert_eq!(ground_point(upwards), None);
+    }
+
+    /// Frames `Steering::turn_towards` takes to turn all the way around at 60 fps
+    fn reversal_frames(max_velocity: f32, acceleration: f32) -> usize {
+        let mut steering = Steering::default();
+        let mut direction = 0.0;
+        let target = std::f32::consts::PI;
+        for frame in 1..1000 {
+            direction =
+                steering.turn_towards(direction, target, max_velocity, acceleration, 1.0 / 60.0);
+            assert!(direction <= target, "overshot to {}", direction);
+            if direction == target {
+                return frame;
+            }
+        }
+        panic!("never got there");
+    }
+
+    #[test]
+    fn reversing_takes_as_long_as_the_limits_say() {
+        // accelerating, cruising and braking: PI / max_velocity + max_velocity / acceleration
+        let frames = reversal_frames(2.0, 12.0);
+        let expected = (std::f32::consts::PI / 2.0 + 2.0 / 12.0) * 60.0;
+        assert!((frames as f32 - expected).abs() < 3.0, "{} frames", frames);
+
+        // twice the limits, half the time
+        let faster = reversal_frames(4.0, 24.0);
+        assert!(
+            (faster as f32 - expected / 2.0).abs() < 3.0,
+            "{} frames",
+            faster
+        );
+    }
+
+    #[test]
+    fn ai_blobs_are_kept_inside_the_arena_too() {
+        let mut app = App::new();
+        app.insert_resource(Arena::Circle { radius: 10.0 })
+            .init_resource::<ArenaBounds>()
+            .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+            .add_system(apply_arena_bounds);
+        let blob = || Blob {
+            size: 0.5,
+            ..default()
+        };
+        let outside = app
+            .world
+            .spawn((
+                Transform::from_xyz(12.0, 0.0, 1.0),
+                blob(),
+                ai::AiBlob::default(),
+            ))
+            .id();
+        let inside = app
+            .world
+            .spawn((
+                Transform::from_xyz(0.0, 5.0, 1.0),
+                blob(),
+                ai::AiBlob::default(),
+            ))
+            .id();
+
+        for _ in 0..60 {
+            app.update();
+        }
+
+        let position = app.world.get::<Transform>(outside).unwrap().translation;
+        assert!(position.xy().length() <= 10.0, "{}", position);
+        assert_eq!(position.y, 0.0, "pushed straight back in");
+        let position = app.world.get::<Transform>(inside).unwrap().translation;
+        assert_eq!(position, Vec3::new(0.0, 5.0, 1.0));
     }
 }
@@ -0,0 +1,124 @@
+//! Top-down minimap of the arena
+use crate::raymarching::Blob;
+use crate::teams::{Team, TeamColors};
+use crate::{Arena, GizmoQuality, PlayerInput};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+/// Width and height of the minimap, in egui points
+const MINIMAP_SIZE: f32 = 160.0;
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(draw_minimap);
+    }
+}
+
+fn draw_minimap(
+    blobs: Query<(&Transform, &Blob, Option<&PlayerInput>, Option<&Team>)>,
+    team_colors: Res<TeamColors>,
+    arena: Res<Arena>,
+    quality: Res<GizmoQuality>,
+    mut egui_contexts: EguiContexts,
+) {
+    egui::Window::new("Minimap")
+        .resizable(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let (response, painter) =
+                ui.allocate_painter(egui::Vec2::splat(MINIMAP_SIZE), egui::Sense::hover());
+            let rect = response.rect;
+            let extent = arena.extent().max_element();
+            let scale = rect.width() * 0.5 / extent;
+
+            let outline = arena
+                .outline(quality.circle_segments)
+                .into_iter()
+                .map(|point| world_to_minimap(point.xy(), extent, rect))
+                .collect();
+            painter.add(egui::Shape::closed_line(
+                outline,
+                egui::Stroke::new(1.0, egui::Color32::GRAY),
+            ));
+
+            for (transform, blob, player, team) in blobs.iter() {
+                let position = world_to_minimap(transform.translation.xy(), extent, rect);
+                let radius = (blob.size * scale).max(2.0);
+
+                painter.circle_filled(position, radius, to_egui_color(blob.color));
+                // the player is always ringed, others only when they're on a team
+                let ring = match (player, team) {
+                    (_, Some(team)) => Some(team_colors.color(*team)),
+                    (Some(_), None) => Some(Color::WHITE),
+                    (None, None) => None,
+                };
+                if let Some(ring) = ring {
+                    painter.circle_stroke(
+                        position,
+                        radius + 2.0,
+                        egui::Stroke::new(1.5, to_egui_color(ring)),
+                    );
+                }
+            }
+        });
+}
+
+/// Maps a world space xy position into the minimap rect, a square of `extent` around the origin
+/// touching its edges.
+///
+/// World +y points up on the minimap, so it gets flipped to egui's y-down.
+pub fn world_to_minimap(position: Vec2, extent: f32, rect: egui::Rect) -> egui::Pos2 {
+    let normalized = position / extent;
+    rect.center() + egui::vec2(normalized.x, -normalized.y) * rect.width().min(rect.height()) * 0.5
+}
+
+fn to_egui_color(color: Color) -> egui::Color32 {
+    let [r, g, b, _] = color.as_rgba_f32();
+    egui::Color32::from_rgb((r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: egui::Pos2, expected: egui::Pos2) {
+        assert!(
+            (actual - expected).length() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn world_to_minimap_maps_the_extent_onto_the_rect() {
+        let rect = egui::Rect::from_min_size(egui::pos2(100.0, 50.0), egui::vec2(200.0, 200.0));
+        let extent = 10.0;
+
+        assert_near(world_to_minimap(Vec2::ZERO, extent, rect), rect.center());
+        // +x right, +y up
+        assert_near(
+            world_to_minimap(Vec2::new(extent, 0.0), extent, rect),
+            egui::pos2(300.0, 150.0),
+        );
+        assert_near(
+            world_to_minimap(Vec2::new(0.0, extent), extent, rect),
+            egui::pos2(200.0, 50.0),
+        );
+        assert_near(
+            world_to_minimap(Vec2::new(-5.0, -5.0), extent, rect),
+            egui::pos2(150.0, 200.0),
+        );
+    }
+
+    #[test]
+    fn world_to_minimap_fits_the_shorter_side() {
+        let rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(300.0, 100.0));
+        assert_near(
+            world_to_minimap(Vec2::new(10.0, 10.0), 10.0, rect),
+            egui::pos2(200.0, 0.0),
+        );
+    }
+}
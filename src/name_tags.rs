@@ -0,0 +1,159 @@
+//! Name labels drawn over blobs
+use crate::raymarching::Blob;
+use crate::simulation::SimulationSet;
+use crate::{PlayerInput, WORLD_UP};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct NameTagPlugin;
+
+impl Plugin for NameTagPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NameTagConfig>()
+            .add_system(name_player_blobs)
+            .add_system(draw_name_tags.after(SimulationSet::Interpolate));
+    }
+}
+
+/// Shown above a blob
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct BlobName(pub String);
+
+#[derive(Resource)]
+pub struct NameTagConfig {
+    /// Given to every blob with `PlayerInput`
+    pub player_name: String,
+    /// Labels start fading out this far from the camera
+    pub fade_start: f32,
+    /// and are gone by this distance
+    pub fade_end: f32,
+}
+
+impl Default for NameTagConfig {
+    fn default() -> Self {
+        NameTagConfig {
+            player_name: "Player".to_string(),
+            fade_start: 10.0,
+            fade_end: 20.0,
+        }
+    }
+}
+
+impl NameTagConfig {
+    /// Label opacity at `distance` from the camera, from 1 to 0
+    pub fn opacity(&self, distance: f32) -> f32 {
+        let range = (self.fade_end - self.fade_start).max(f32::EPSILON);
+        (1.0 - (distance - self.fade_start) / range).clamp(0.0, 1.0)
+    }
+}
+
+fn name_player_blobs(
+    mut commands: Commands,
+    players: Query<(Entity, Option<&BlobName>), With<PlayerInput>>,
+    config: Res<NameTagConfig>,
+) {
+    for (entity, name) in players.iter() {
+        if name.map_or(true, |name| name.0 != config.player_name) {
+            commands
+                .entity(entity)
+                .insert(BlobName(config.player_name.clone()));
+        }
+    }
+}
+
+/// Projects `world_position` to logical window coordinates with the origin at the top left, the
+/// way egui wants them. None if the point is behind the camera or off-screen.
+pub fn world_to_screen(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    world_position: Vec3,
+) -> Option<Vec2> {
+    let size = camera.logical_viewport_size()?;
+    let viewport = camera.world_to_viewport(camera_transform, world_position)?;
+    viewport_to_screen(viewport, size)
+}
+
+/// Flips a point in a viewport of `size` from bevy's bottom left origin to egui's top left one.
+/// None if it's outside the viewport.
+pub fn viewport_to_screen(viewport: Vec2, size: Vec2) -> Option<Vec2> {
+    if viewport.x < 0.0 || viewport.y < 0.0 || viewport.x > size.x || viewport.y > size.y {
+        return None;
+    }
+
+    Some(Vec2::new(viewport.x, size.y - viewport.y))
+}
+
+fn draw_name_tags(
+    blobs: Query<(&Transform, &BlobName), With<Blob>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    config: Res<NameTagConfig>,
+    mut egui_contexts: EguiContexts,
+) {
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+
+    let painter = egui_contexts
+        .ctx_mut()
+        .layer_painter(egui::LayerId::background());
+    for (transform, name) in blobs.iter() {
+        // top of the blob's bounding box
+        let anchor = transform.translation + WORLD_UP * transform.scale.z;
+        let Some(position) = world_to_screen(camera, camera_transform, anchor) else {
+            continue;
+        };
+
+        let opacity = config.opacity(camera_transform.translation().distance(anchor));
+        if opacity <= 0.0 {
+            continue;
+        }
+
+        painter.text(
+            egui::pos2(position.x, position.y),
+            egui::Align2::CENTER_BOTTOM,
+            &name.0,
+            egui::FontId::proportional(14.0),
+            egui::Color32::from_white_alpha((opacity * 255.0) as u8),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_points_flip_to_a_top_left_origin() {
+        let size = Vec2::new(800.0, 600.0);
+        assert_eq!(
+            viewport_to_screen(Vec2::new(400.0, 300.0), size),
+            Some(Vec2::new(400.0, 300.0))
+        );
+        // near the bottom of the viewport is near the bottom of the screen too
+        assert_eq!(
+            viewport_to_screen(Vec2::new(100.0, 50.0), size),
+            Some(Vec2::new(100.0, 550.0))
+        );
+        assert_eq!(
+            viewport_to_screen(Vec2::new(800.0, 600.0), size),
+            Some(Vec2::new(800.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn points_off_screen_get_no_label() {
+        let size = Vec2::new(800.0, 600.0);
+        assert_eq!(viewport_to_screen(Vec2::new(-1.0, 300.0), size), None);
+        assert_eq!(viewport_to_screen(Vec2::new(400.0, 601.0), size), None);
+        assert_eq!(viewport_to_screen(Vec2::new(801.0, -5.0), size), None);
+    }
+
+    #[test]
+    fn labels_fade_out_with_distance() {
+        let config = NameTagConfig::default();
+        assert_eq!(config.opacity(0.0), 1.0);
+        assert_eq!(config.opacity(config.fade_start), 1.0);
+        assert_eq!(config.opacity(15.0), 0.5);
+        assert_eq!(config.opacity(config.fade_end + 1.0), 0.0);
+    }
+}
@@ -0,0 +1,156 @@
+//! Fades out blobs standing between the camera and the player
+use crate::bvh::BvhTree;
+use crate::raymarching::Blob;
+use crate::simulation::SimulationSet;
+use crate::{PlayerGroup, PlayerInput};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+pub struct OcclusionPlugin;
+
+impl Plugin for OcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OcclusionFadeConfig>()
+            .add_system(fade_occluders.after(SimulationSet::Interpolate));
+    }
+}
+
+#[derive(Resource)]
+pub struct OcclusionFadeConfig {
+    pub enabled: bool,
+    /// Opacity of a blob in the way
+    pub faded_opacity: f32,
+    /// Opacity change per second
+    pub fade_speed: f32,
+}
+
+impl Default for OcclusionFadeConfig {
+    fn default() -> Self {
+        OcclusionFadeConfig {
+            enabled: true,
+            faded_opacity: 0.3,
+            fade_speed: 4.0,
+        }
+    }
+}
+
+/// Current opacity of a blob that is or recently was in the way, removed once it's back to fully
+/// opaque
+#[derive(Component, Debug)]
+pub struct OcclusionFade {
+    pub opacity: f32,
+}
+
+/// Whether a sphere at `position` blocks the line from `eye` to `target`
+pub fn is_occluding(eye: Vec3, target: Vec3, position: Vec3, radius: f32) -> bool {
+    let line = target - eye;
+    let length_squared = line.length_squared();
+    if length_squared <= f32::EPSILON {
+        return false;
+    }
+
+    // only the part between the two counts, not what's behind the player or the camera
+    let t = (position - eye).dot(line) / length_squared;
+    if !(0.0..1.0).contains(&t) {
+        return false;
+    }
+
+    position.distance(eye + line * t) < radius
+}
+
+fn fade_occluders(
+    mut commands: Commands,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    players: Query<(&Transform, &Blob), With<PlayerInput>>,
+    mut blobs: Query<(Entity, &Transform, &Blob, Option<&mut OcclusionFade>), Without<PlayerInput>>,
+    bvh: Res<BvhTree>,
+    config: Res<OcclusionFadeConfig>,
+    time: Res<Time>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let eye = camera.translation();
+    let target = PlayerGroup::from_pieces(players.iter()).map(|group| group.centroid);
+
+    // the BVH narrows it down to blobs whose box the line passes through
+    let mut candidates = HashSet::new();
+    if let (true, Some(target)) = (config.enabled, target) {
+        let to_target = target - eye;
+        if let Some(direction) = to_target.try_normalize() {
+            bvh.raycast(eye, direction, to_target.length(), |entity| {
+                candidates.insert(entity);
+            });
+        }
+    }
+
+    // real time, so slow motion doesn't slow the fade
+    let step = config.fade_speed * time.raw_delta_seconds();
+    for (entity, transform, blob, fade) in blobs.iter_mut() {
+        let occluding = candidates.contains(&entity)
+            && target.map_or(false, |target| {
+                is_occluding(eye, target, transform.translation, blob.radius())
+            });
+        let target_opacity = if occluding { config.faded_opacity } else { 1.0 };
+
+        match fade {
+            Some(mut fade) => {
+                let change = (target_opacity - fade.opacity).clamp(-step, step);
+                fade.opacity += change;
+                if !occluding && fade.opacity >= 1.0 {
+                    commands.entity(entity).remove::<OcclusionFade>();
+                }
+            }
+            None if occluding => {
+                commands
+                    .entity(entity)
+                    .insert(OcclusionFade { opacity: 1.0 });
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Aabb;
+
+    #[test]
+    fn blob_on_the_line_to_the_player_is_flagged() {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<OcclusionFadeConfig>()
+            .init_resource::<BvhTree>()
+            .add_system(fade_occluders);
+        app.world.spawn((
+            Camera3d::default(),
+            GlobalTransform::from_xyz(0.0, -10.0, 10.0),
+        ));
+        app.world
+            .spawn((Transform::default(), Blob::default(), PlayerInput));
+
+        let blob = Blob::default();
+        let radius = blob.radius();
+        let spawn = |app: &mut App, position: Vec3| {
+            let entity = app
+                .world
+                .spawn((Transform::from_translation(position), Blob::default()))
+                .id();
+            let aabb = Aabb {
+                min: position - radius,
+                max: position + radius,
+            };
+            app.world.resource_mut::<BvhTree>().insert(entity, aabb);
+            entity
+        };
+        let in_the_way = spawn(&mut app, Vec3::new(0.0, -5.0, 5.0));
+        let beside = spawn(&mut app, Vec3::new(5.0, -5.0, 5.0));
+        let behind_the_player = spawn(&mut app, Vec3::new(0.0, 5.0, -5.0));
+
+        app.update();
+        assert!(app.world.get::<OcclusionFade>(in_the_way).is_some());
+        assert!(app.world.get::<OcclusionFade>(beside).is_none());
+        assert!(app.world.get::<OcclusionFade>(behind_the_player).is_none());
+    }
+}
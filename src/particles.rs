@@ -0,0 +1,176 @@
+//! Short lived bursts of dots where blobs get eaten
+use crate::raymarching::BlobMergeEvent;
+use crate::rng::GameRng;
+use bevy::prelude::*;
+use bevy_mod_gizmos::Gizmo;
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticleConfig>()
+            .add_system(spawn_merge_particles)
+            .add_system(update_particles.after(spawn_merge_particles));
+    }
+}
+
+#[derive(Resource)]
+pub struct ParticleConfig {
+    /// Particles per unit of eaten size
+    pub particles_per_size: f32,
+    /// Every burst gets at least this many
+    pub min_particles: usize,
+    /// and at most this many
+    pub max_particles_per_burst: usize,
+    /// No new particles while this many are alive
+    pub max_particles: usize,
+    /// Seconds a particle lives for, fading out all the way
+    pub lifetime: f32,
+    /// Speed of the fastest particles, also grows with eaten size
+    pub speed: f32,
+    /// Diameter of a particle dot
+    pub size: f32,
+}
+
+impl Default for ParticleConfig {
+    fn default() -> Self {
+        ParticleConfig {
+            particles_per_size: 24.0,
+            min_particles: 4,
+            max_particles_per_burst: 32,
+            max_particles: 256,
+            lifetime: 0.6,
+            speed: 2.0,
+            size: 0.05,
+        }
+    }
+}
+
+impl ParticleConfig {
+    /// How many particles eating a blob of `eaten_size` bursts into
+    pub fn burst_count(&self, eaten_size: f32) -> usize {
+        ((eaten_size * self.particles_per_size).round() as usize).clamp(
+            self.min_particles,
+            self.max_particles_per_burst.max(self.min_particles),
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct Particle {
+    pub velocity: Vec3,
+    pub lifetime: Timer,
+    pub color: Color,
+}
+
+fn spawn_merge_particles(
+    mut commands: Commands,
+    mut merge_events: EventReader<BlobMergeEvent>,
+    particles: Query<(), With<Particle>>,
+    mut rng: ResMut<GameRng>,
+    config: Res<ParticleConfig>,
+) {
+    let mut alive = particles.iter().count();
+    for event in merge_events.iter() {
+        let count = config
+            .burst_count(event.eaten_size)
+            .min(config.max_particles.saturating_sub(alive));
+        for i in 0..count {
+            // evenly around the circle with a bit of jitter, so bursts don't look stamped out
+            let angle = std::f32::consts::TAU * (i as f32 + rng.next_f32()) / count as f32;
+            let speed = config.speed * (0.5 + event.eaten_size) * rng.range(0.5, 1.0);
+            commands.spawn((
+                TransformBundle::from_transform(Transform::from_translation(event.position)),
+                Particle {
+                    velocity: (Vec2::from_angle(angle) * speed).extend(rng.range(0.0, speed)),
+                    lifetime: Timer::from_seconds(config.lifetime, TimerMode::Once),
+                    color: event.eaten_color,
+                },
+            ));
+        }
+        alive += count;
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    mut particles: Query<(Entity, &mut Transform, &mut Particle)>,
+    config: Res<ParticleConfig>,
+    time: Res<Time>,
+) {
+    for (entity, mut transform, mut particle) in particles.iter_mut() {
+        if particle.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        // slow down as they go
+        let drag = 1.0 - particle.lifetime.percent();
+        transform.translation += particle.velocity * drag * time.delta_seconds();
+
+        let color = particle.color.with_a(particle.lifetime.percent_left());
+        bevy_mod_gizmos::draw_gizmo(Gizmo::sphere(transform.translation, config.size, color));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::{TimePlugin, TimeUpdateStrategy};
+    use std::time::Duration;
+
+    fn particle_count(app: &mut App) -> usize {
+        app.world
+            .query_filtered::<(), With<Particle>>()
+            .iter(&app.world)
+            .count()
+    }
+
+    #[test]
+    fn merge_bursts_into_particles_that_die_out() {
+        let mut app = App::new();
+        app.add_plugin(TimePlugin)
+            .init_resource::<ParticleConfig>()
+            .init_resource::<GameRng>()
+            .add_event::<BlobMergeEvent>()
+            .add_system(spawn_merge_particles)
+            .add_system(update_particles.after(spawn_merge_particles));
+        app.world.send_event(BlobMergeEvent {
+            eater: Entity::from_raw(0),
+            eaten: Entity::from_raw(1),
+            eaten_size: 0.5,
+            eaten_color: Color::RED,
+            position: Vec3::new(1.0, 2.0, 1.0),
+            overflow: 0.0,
+        });
+        let mut now = app.world.resource::<Time>().startup();
+        // frames of a tenth of a second
+        let mut step = |app: &mut App| {
+            now += Duration::from_millis(100);
+            app.insert_resource(TimeUpdateStrategy::ManualInstant(now));
+            app.update();
+        };
+        step(&mut app);
+
+        let config = ParticleConfig::default();
+        assert_eq!(particle_count(&mut app), config.burst_count(0.5));
+        assert_eq!(config.burst_count(0.5), 12);
+
+        // 0.6 seconds of lifetime, counting from the first frame which has no time passing.
+        // A frame of slack for the lifetime not being exactly 0.6 as an f32.
+        for _ in 0..5 {
+            step(&mut app);
+        }
+        assert_eq!(particle_count(&mut app), 12);
+        step(&mut app);
+        step(&mut app);
+        assert_eq!(particle_count(&mut app), 0);
+    }
+
+    #[test]
+    fn burst_size_is_clamped() {
+        let config = ParticleConfig::default();
+        assert_eq!(config.burst_count(0.0), config.min_particles);
+        assert_eq!(config.burst_count(100.0), config.max_particles_per_burst);
+    }
+}
@@ -0,0 +1,179 @@
+//! Food scattered around the arena for blobs to grow on
+use crate::game::BlobPalette;
+use crate::raymarching::{Blob, BlobBundle, BlobLimit, BlobMaterial, BlobMesh};
+use crate::rng::GameRng;
+use crate::Arena;
+use bevy::prelude::*;
+
+pub struct PelletPlugin;
+
+impl Plugin for PelletPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PelletConfig>()
+            .init_resource::<PelletSpawner>();
+    }
+}
+
+/// A blob that doesn't move or eat, only gets eaten. Eating it grows the eater by
+/// `value` times [`MergeConfig::pellet_gain`](crate::raymarching::MergeConfig::pellet_gain).
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Pellet {
+    pub value: f32,
+}
+
+/// One kind of pellet the spawner can pick, its color comes from the [`BlobPalette`]
+#[derive(Clone, Debug)]
+pub struct PelletTier {
+    pub value: f32,
+    /// Relative chance of being picked, doesn't need to add up to anything
+    pub weight: f32,
+}
+
+#[derive(Resource)]
+pub struct PelletConfig {
+    /// No more pellets are spawned while there are this many
+    pub max_pellets: usize,
+    /// Seconds between spawns
+    pub spawn_interval: f32,
+    /// Size of a pellet worth 1, size goes with the square root of the value so area matches it
+    pub base_size: f32,
+    pub tiers: Vec<PelletTier>,
+}
+
+impl Default for PelletConfig {
+    fn default() -> Self {
+        PelletConfig {
+            max_pellets: 40,
+            spawn_interval: 0.25,
+            base_size: 0.08,
+            tiers: vec![
+                PelletTier {
+                    value: 1.0,
+                    weight: 0.75,
+                },
+                PelletTier {
+                    value: 3.0,
+                    weight: 0.2,
+                },
+                PelletTier {
+                    value: 10.0,
+                    weight: 0.05,
+                },
+            ],
+        }
+    }
+}
+
+impl PelletConfig {
+    /// Picks a tier by weight, mostly the cheap ones
+    pub fn sample_tier(&self, rng: &mut GameRng) -> Option<&PelletTier> {
+        rng.weighted_index(self.tiers.iter().map(|tier| tier.weight))
+            .map(|index| &self.tiers[index])
+    }
+
+    pub fn size_for(&self, value: f32) -> f32 {
+        self.base_size * value.max(0.0).sqrt()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PelletSpawner {
+    since_last_spawn: f32,
+}
+
+pub(crate) fn spawn_pellets(
+    mut commands: Commands,
+    pellets: Query<(), With<Pellet>>,
+    blobs: Query<(), With<Blob>>,
+    mut spawner: ResMut<PelletSpawner>,
+    mut rng: ResMut<GameRng>,
+    config: Res<PelletConfig>,
+    palette: Res<BlobPalette>,
+    limit: Res<BlobLimit>,
+    arena: Res<Arena>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+    fixed_time: Res<FixedTime>,
+) {
+    spawner.since_last_spawn += fixed_time.period.as_secs_f32();
+    if spawner.since_last_spawn < config.spawn_interval {
+        return;
+    }
+    spawner.since_last_spawn = 0.0;
+
+    if pellets.iter().count() >= config.max_pellets || !limit.allows(blobs.iter().count()) {
+        return;
+    }
+    let Some(tier) = config.sample_tier(&mut rng) else {
+        return;
+    };
+
+    let size = config.size_for(tier.value);
+    let color = palette.pick(&mut rng);
+    let Some(position) = arena.random_point(&mut rng, size * 0.33, 1.0) else {
+        return;
+    };
+
+    commands.spawn((
+        BlobBundle::new(
+            &material,
+            &mesh,
+            Transform::from_translation(position),
+            Blob {
+                size,
+                color,
+                ..default()
+            },
+        ),
+        Pellet { value: tier.value },
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app that tries to spawn a pellet every update, with `blobs` blobs already around
+    fn pellet_app(limit: usize, blobs: usize) -> App {
+        let mut app = App::new();
+        app.insert_resource(PelletConfig {
+            spawn_interval: 0.0,
+            ..default()
+        })
+        .insert_resource(BlobLimit(limit))
+        .insert_resource(BlobMaterial(Handle::default()))
+        .insert_resource(BlobMesh(Handle::default()))
+        .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+        .init_resource::<PelletSpawner>()
+        .init_resource::<GameRng>()
+        .init_resource::<BlobPalette>()
+        .init_resource::<Arena>()
+        .add_system(spawn_pellets);
+        for _ in 0..blobs {
+            app.world.spawn(Blob::default());
+        }
+        app
+    }
+
+    fn blob_count(app: &mut App) -> usize {
+        app.world
+            .query_filtered::<(), With<Blob>>()
+            .iter(&app.world)
+            .count()
+    }
+
+    #[test]
+    fn spawning_at_the_blob_limit_is_rejected() {
+        let mut app = pellet_app(3, 3);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(blob_count(&mut app), 3);
+
+        let mut app = pellet_app(4, 3);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(blob_count(&mut app), 4, "one more fits under the limit");
+    }
+}
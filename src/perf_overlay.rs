@@ -0,0 +1,244 @@
+//! Frame time, scene size and GPU limits readout, toggled with F3
+use crate::bvh::{
+    BvhBuildTiming, BvhLeafSize, SweptAabbs, BVH_BUILD_TIME, BVH_NODE_COUNT, MAX_LEAF_ENTITIES,
+};
+use crate::raymarching::{Blob, Broadphase, MergeConfig, BLOB_BUFFER_BYTES};
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy::render::renderer::RenderDevice;
+use bevy::render::settings::WgpuLimits;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct PerfOverlayPlugin;
+
+impl Plugin for PerfOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PerfOverlay>()
+            .init_resource::<RenderLimits>()
+            .add_startup_system(read_render_limits)
+            .add_system(toggle_perf_overlay)
+            .add_system(perf_overlay.after(toggle_perf_overlay));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PerfOverlay {
+    pub visible: bool,
+}
+
+fn toggle_perf_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<PerfOverlay>) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+/// The GPU limits that matter for the blob and BVH buffers, read once at startup
+#[derive(Resource, Default, Clone, Debug, PartialEq)]
+pub struct RenderLimits {
+    /// Largest storage buffer a shader can bind, the blob and BVH buffers have to fit
+    pub max_storage_buffer_binding_size: u64,
+    pub max_buffer_size: u64,
+    pub max_bind_groups: u32,
+    pub max_texture_dimension_2d: u32,
+}
+
+impl From<&WgpuLimits> for RenderLimits {
+    fn from(limits: &WgpuLimits) -> Self {
+        RenderLimits {
+            max_storage_buffer_binding_size: limits.max_storage_buffer_binding_size as u64,
+            max_buffer_size: limits.max_buffer_size,
+            max_bind_groups: limits.max_bind_groups,
+            max_texture_dimension_2d: limits.max_texture_dimension_2d,
+        }
+    }
+}
+
+impl RenderLimits {
+    /// Label and value pairs, one per row of the panel
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "Max storage buffer",
+                format_bytes(self.max_storage_buffer_binding_size),
+            ),
+            ("Max buffer", format_bytes(self.max_buffer_size)),
+            ("Max bind groups", self.max_bind_groups.to_string()),
+            (
+                "Max texture size",
+                format!("{0}x{0}", self.max_texture_dimension_2d),
+            ),
+        ]
+    }
+}
+
+/// `bytes` in the largest binary unit that keeps the number at least 1
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn read_render_limits(mut limits: ResMut<RenderLimits>, render_device: Res<RenderDevice>) {
+    *limits = RenderLimits::from(&render_device.limits());
+}
+
+fn perf_overlay(
+    overlay: Res<PerfOverlay>,
+    render_limits: Res<RenderLimits>,
+    mut bvh_timing: ResMut<BvhBuildTiming>,
+    mut leaf_size: ResMut<BvhLeafSize>,
+    mut swept: ResMut<SweptAabbs>,
+    mut merge_config: ResMut<MergeConfig>,
+    diagnostics: Res<Diagnostics>,
+    blobs: Query<(), With<Blob>>,
+    mut egui_contexts: EguiContexts,
+) {
+    if !overlay.visible {
+        return;
+    }
+
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed());
+    let frame_time = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed());
+    let node_count = diagnostics
+        .get(BVH_NODE_COUNT)
+        .and_then(|node_count| node_count.value());
+    let blob_bytes = diagnostics
+        .get(BLOB_BUFFER_BYTES)
+        .and_then(|blob_bytes| blob_bytes.value());
+    let bvh_build_time = diagnostics
+        .get(BVH_BUILD_TIME)
+        .and_then(|build_time| build_time.smoothed());
+
+    egui::Window::new("Performance")
+        .resizable(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            ui.label(format!("FPS: {}", format_diagnostic(fps, 0, "")));
+            ui.label(format!(
+                "Frame time: {}",
+                format_diagnostic(frame_time, 2, " ms")
+            ));
+            ui.label(format!("Blobs: {}", blobs.iter().count()));
+            ui.label(format!(
+                "BVH nodes: {}",
+                format_diagnostic(node_count, 0, "")
+            ));
+            ui.label(format!(
+                "Blob buffer: {}",
+                format_diagnostic(blob_bytes, 0, " B")
+            ));
+
+            ui.checkbox(&mut bvh_timing.enabled, "Time BVH builds");
+            if bvh_timing.enabled {
+                ui.label(format!(
+                    "BVH build: {}",
+                    format_diagnostic(bvh_build_time, 3, " ms")
+                ));
+            }
+
+            // only touch the resource on actual edits, changing it rebuilds the BVH
+            let mut max_leaf = leaf_size.get();
+            ui.add(egui::Slider::new(&mut max_leaf, 1..=MAX_LEAF_ENTITIES).text("BVH leaf size"));
+            if max_leaf != leaf_size.get() {
+                leaf_size.0 = max_leaf;
+            }
+
+            let mut broadphase = merge_config.broadphase;
+            egui::ComboBox::from_label("Merge broadphase")
+                .selected_text(format!("{:?}", broadphase))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut broadphase, Broadphase::AllPairs, "AllPairs");
+                    ui.selectable_value(&mut broadphase, Broadphase::Quadtree, "Quadtree");
+                });
+            if broadphase != merge_config.broadphase {
+                merge_config.broadphase = broadphase;
+            }
+
+            let mut enabled = swept.enabled;
+            ui.checkbox(&mut enabled, "Swept AABBs");
+            let mut max_padding = swept.max_padding;
+            if enabled {
+                ui.add(egui::Slider::new(&mut max_padding, 0.0..=5.0).text("Max sweep"));
+            }
+            if enabled != swept.enabled || max_padding != swept.max_padding {
+                swept.enabled = enabled;
+                swept.max_padding = max_padding;
+            }
+
+            egui::CollapsingHeader::new("GPU limits").show(ui, |ui| {
+                egui::Grid::new("render_limits").show(ui, |ui| {
+                    for (label, value) in render_limits.rows() {
+                        ui.label(label);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+}
+
+/// Formats a diagnostic value with the given precision and unit suffix, or a dash if there
+/// isn't a value yet
+pub fn format_diagnostic(value: Option<f64>, precision: usize, suffix: &str) -> String {
+    match value {
+        Some(value) if value.is_finite() => format!("{:.*}{}", precision, value, suffix),
+        _ => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_format_with_precision_and_unit() {
+        assert_eq!(format_diagnostic(Some(59.94), 0, ""), "60");
+        assert_eq!(format_diagnostic(Some(16.6667), 2, " ms"), "16.67 ms");
+        assert_eq!(format_diagnostic(Some(0.0), 3, " ms"), "0.000 ms");
+    }
+
+    #[test]
+    fn missing_or_broken_diagnostics_show_a_dash() {
+        assert_eq!(format_diagnostic(None, 2, " ms"), "-");
+        assert_eq!(format_diagnostic(Some(f64::NAN), 2, " ms"), "-");
+        assert_eq!(format_diagnostic(Some(f64::INFINITY), 0, ""), "-");
+    }
+
+    #[test]
+    fn bytes_get_the_biggest_unit_that_fits() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(128 << 20), "128.0 MiB");
+        // nothing past GiB
+        assert_eq!(format_bytes(2048 << 30), "2048.0 GiB");
+    }
+
+    #[test]
+    fn render_limits_make_a_row_each() {
+        let limits = RenderLimits::from(&WgpuLimits::downlevel_webgl2_defaults());
+        assert_eq!(
+            limits.rows(),
+            [
+                ("Max storage buffer", "0 B".to_string()),
+                ("Max buffer", "256.0 MiB".to_string()),
+                ("Max bind groups", "4".to_string()),
+                ("Max texture size", "2048x2048".to_string()),
+            ]
+        );
+    }
+}
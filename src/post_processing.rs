@@ -0,0 +1,274 @@
+//! Tonemapping and other screen space settings for the main camera
+use bevy::core_pipeline::bloom::BloomSettings;
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct PostProcessingPlugin;
+
+impl Plugin for PostProcessingPlugin {
+    fn build(&self, app: &mut App) {
+        // bevy's own default, spelled out so there's something for the picker to edit
+        app.insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
+            .init_resource::<PostProcessing>()
+            .add_system(post_processing_window)
+            .add_system(apply_tonemapping.after(post_processing_window))
+            .add_system(apply_bloom.after(post_processing_window))
+            .add_system(apply_msaa.after(post_processing_window));
+    }
+}
+
+/// Every tonemapper bevy ships, in the order the dropdown lists them
+pub const TONEMAPPERS: [Tonemapping; 8] = [
+    Tonemapping::None,
+    Tonemapping::Reinhard,
+    Tonemapping::ReinhardLuminance,
+    Tonemapping::AcesFitted,
+    Tonemapping::AgX,
+    Tonemapping::SomewhatBoringDisplayTransform,
+    Tonemapping::TonyMcMapface,
+    Tonemapping::BlenderFilmic,
+];
+
+/// Antialiasing modes for the dropdown. `main` starts with `Msaa::Off`. Bevy picks up a change
+/// on its own, materials and the prepass get specialized again for the new sample count, which
+/// stalls the first frame after switching.
+pub const MSAA_MODES: [Msaa; 4] = [Msaa::Off, Msaa::Sample2, Msaa::Sample4, Msaa::Sample8];
+
+/// `Msaa` has no `Debug`
+fn msaa_label(msaa: Msaa) -> String {
+    match msaa {
+        Msaa::Off => "Off".to_string(),
+        samples => format!("{}x", samples.samples()),
+    }
+}
+
+fn rgb(color: Color) -> [f32; 3] {
+    [color.r(), color.g(), color.b()]
+}
+
+/// The color a color picker returned as `picked`, None if the picker didn't change `color`
+fn edited_color(color: Color, picked: [f32; 3]) -> Option<Color> {
+    let [r, g, b] = picked;
+    (picked != rgb(color)).then(|| Color::rgb(r, g, b))
+}
+
+#[derive(Resource)]
+pub struct PostProcessing {
+    /// Copied onto every camera by [`apply_tonemapping`]
+    pub tonemapping: Tonemapping,
+    /// Makes bright blobs glow. Off by default, it costs a few extra passes. Only cameras with
+    /// `hdr` on get bloom, see [`apply_bloom`].
+    pub bloom: bool,
+    pub bloom_intensity: f32,
+    /// Copied onto the `Msaa` resource by [`apply_msaa`]
+    pub msaa: Msaa,
+}
+
+impl Default for PostProcessing {
+    fn default() -> Self {
+        PostProcessing {
+            tonemapping: Tonemapping::BlenderFilmic,
+            bloom: false,
+            bloom_intensity: BloomSettings::default().intensity,
+            msaa: Msaa::Off,
+        }
+    }
+}
+
+fn post_processing_window(
+    mut post_processing: ResMut<PostProcessing>,
+    mut clear_color: ResMut<ClearColor>,
+    mut egui_contexts: EguiContexts,
+) {
+    egui::Window::new("Post processing").show(egui_contexts.ctx_mut(), |ui| {
+        let mut selected = post_processing.tonemapping;
+        egui::ComboBox::from_label("Tonemapping")
+            .selected_text(format!("{:?}", selected))
+            .show_ui(ui, |ui| {
+                for tonemapping in TONEMAPPERS {
+                    ui.selectable_value(&mut selected, tonemapping, format!("{:?}", tonemapping));
+                }
+            });
+
+        let mut samples = post_processing.msaa;
+        egui::ComboBox::from_label("Antialiasing")
+            .selected_text(msaa_label(samples))
+            .show_ui(ui, |ui| {
+                for mode in MSAA_MODES {
+                    ui.selectable_value(&mut samples, mode, msaa_label(mode));
+                }
+            });
+        // cameras clear with `ClearColorConfig::Default`, which reads this resource every frame.
+        // The environment map only lights the blobs, it isn't drawn behind them.
+        let mut background = rgb(clear_color.0);
+        ui.horizontal(|ui| {
+            ui.label("Background");
+            ui.color_edit_button_rgb(&mut background);
+        });
+        if let Some(color) = edited_color(clear_color.0, background) {
+            clear_color.0 = color;
+        }
+
+        let mut bloom = post_processing.bloom;
+        let mut bloom_intensity = post_processing.bloom_intensity;
+        ui.checkbox(&mut bloom, "Bloom");
+        ui.add_enabled(
+            bloom,
+            egui::Slider::new(&mut bloom_intensity, 0.0..=1.0).text("Bloom intensity"),
+        );
+
+        // only touch the resource on actual edits, so change detection stays quiet
+        if selected != post_processing.tonemapping {
+            post_processing.tonemapping = selected;
+        }
+        if bloom != post_processing.bloom || bloom_intensity != post_processing.bloom_intensity {
+            post_processing.bloom = bloom;
+            post_processing.bloom_intensity = bloom_intensity;
+        }
+        if samples != post_processing.msaa {
+            post_processing.msaa = samples;
+        }
+    });
+}
+
+pub(crate) fn apply_msaa(mut msaa: ResMut<Msaa>, post_processing: Res<PostProcessing>) {
+    if *msaa != post_processing.msaa {
+        *msaa = post_processing.msaa;
+    }
+}
+
+/// Swaps the `Tonemapping` component in place, the camera doesn't need respawning
+pub(crate) fn apply_tonemapping(
+    mut cameras: Query<&mut Tonemapping, With<Camera>>,
+    post_processing: Res<PostProcessing>,
+) {
+    for mut tonemapping in cameras.iter_mut() {
+        if *tonemapping != post_processing.tonemapping {
+            *tonemapping = post_processing.tonemapping;
+        }
+    }
+}
+
+/// Adds or removes `BloomSettings` to match [`PostProcessing::bloom`]. Bloom needs an HDR target,
+/// so cameras without `hdr` never get it.
+pub(crate) fn apply_bloom(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, &Camera, Option<&mut BloomSettings>)>,
+    post_processing: Res<PostProcessing>,
+) {
+    for (entity, camera, bloom) in cameras.iter_mut() {
+        let wants_bloom = post_processing.bloom && camera.hdr;
+        match bloom {
+            Some(mut bloom) if wants_bloom => {
+                if bloom.intensity != post_processing.bloom_intensity {
+                    bloom.intensity = post_processing.bloom_intensity;
+                }
+            }
+            Some(_) => {
+                commands.entity(entity).remove::<BloomSettings>();
+            }
+            None if wants_bloom => {
+                commands.entity(entity).insert(BloomSettings {
+                    intensity: post_processing.bloom_intensity,
+                    ..default()
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picked_tonemapper_lands_on_the_camera() {
+        let mut app = App::new();
+        app.init_resource::<PostProcessing>()
+            .add_system(apply_tonemapping);
+        let camera = app.world.spawn((Camera::default(), Tonemapping::None)).id();
+        let not_a_camera = app.world.spawn(Tonemapping::None).id();
+
+        app.update();
+        assert_eq!(
+            *app.world.get::<Tonemapping>(camera).unwrap(),
+            Tonemapping::BlenderFilmic
+        );
+
+        app.world.resource_mut::<PostProcessing>().tonemapping = Tonemapping::AgX;
+        app.update();
+        assert_eq!(
+            *app.world.get::<Tonemapping>(camera).unwrap(),
+            Tonemapping::AgX
+        );
+        assert_eq!(
+            *app.world.get::<Tonemapping>(not_a_camera).unwrap(),
+            Tonemapping::None
+        );
+    }
+
+    #[test]
+    fn bloom_toggle_adds_and_removes_the_settings() {
+        let mut app = App::new();
+        app.init_resource::<PostProcessing>()
+            .add_system(apply_bloom);
+        let hdr = app
+            .world
+            .spawn(Camera {
+                hdr: true,
+                ..default()
+            })
+            .id();
+        let ldr = app.world.spawn(Camera::default()).id();
+        app.update();
+        assert!(app.world.get::<BloomSettings>(hdr).is_none());
+
+        {
+            let mut post_processing = app.world.resource_mut::<PostProcessing>();
+            post_processing.bloom = true;
+            post_processing.bloom_intensity = 0.5;
+        }
+        app.update();
+        assert_eq!(app.world.get::<BloomSettings>(hdr).unwrap().intensity, 0.5);
+        // no HDR target to bloom on
+        assert!(app.world.get::<BloomSettings>(ldr).is_none());
+
+        app.world.resource_mut::<PostProcessing>().bloom_intensity = 0.2;
+        app.update();
+        assert_eq!(app.world.get::<BloomSettings>(hdr).unwrap().intensity, 0.2);
+
+        app.world.resource_mut::<PostProcessing>().bloom = false;
+        app.update();
+        assert!(app.world.get::<BloomSettings>(hdr).is_none());
+    }
+
+    #[test]
+    fn background_starts_grey_and_only_changes_on_edits() {
+        let mut app = App::new();
+        app.add_plugin(PostProcessingPlugin);
+        let background = app.world.resource::<ClearColor>().0;
+        assert_eq!(background, Color::rgb(0.4, 0.4, 0.4));
+
+        assert_eq!(edited_color(background, rgb(background)), None);
+        assert_eq!(
+            edited_color(background, [0.1, 0.2, 0.3]),
+            Some(Color::rgb(0.1, 0.2, 0.3))
+        );
+    }
+
+    #[test]
+    fn picked_sample_count_lands_on_msaa() {
+        let mut app = App::new();
+        app.insert_resource(Msaa::Off)
+            .init_resource::<PostProcessing>()
+            .add_system(apply_msaa);
+        app.update();
+        assert_eq!(app.world.resource::<Msaa>().samples(), 1);
+
+        app.world.resource_mut::<PostProcessing>().msaa = Msaa::Sample4;
+        app.update();
+        assert_eq!(app.world.resource::<Msaa>().samples(), 4);
+    }
+}
@@ -0,0 +1,154 @@
+//! Levels the player gains by growing
+use crate::game::GameTime;
+use crate::raymarching::Blob;
+use crate::{PlayerGroup, PlayerInput};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct ProgressPlugin;
+
+impl Plugin for ProgressPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProgressConfig>()
+            .init_resource::<PlayerProgress>()
+            .add_event::<LevelUpEvent>()
+            .add_system(level_up_banner);
+    }
+}
+
+/// Sent once for every level the player reaches
+pub struct LevelUpEvent {
+    pub level: u32,
+}
+
+#[derive(Resource)]
+pub struct ProgressConfig {
+    /// Size of the player's biggest piece needed for each level after the first, in increasing
+    /// order
+    pub thresholds: Vec<f32>,
+    /// How much faster the player moves and turns per level gained, 0 for no perk
+    pub perk_per_level: f32,
+    /// Seconds the level up banner stays up
+    pub banner_duration: f32,
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        ProgressConfig {
+            thresholds: vec![0.8, 1.2, 1.8, 2.6, 3.6],
+            perk_per_level: 0.05,
+            banner_duration: 2.0,
+        }
+    }
+}
+
+impl ProgressConfig {
+    /// Speed and turn rate multiplier at `level`
+    pub fn perk_multiplier(&self, level: u32) -> f32 {
+        1.0 + level.saturating_sub(1) as f32 * self.perk_per_level
+    }
+}
+
+/// The player's level this life, it doesn't go back down when the player shrinks
+#[derive(Resource, Debug)]
+pub struct PlayerProgress {
+    /// Starts at 1
+    pub level: u32,
+    /// `GameTime` of the latest level up
+    pub last_level_up: Option<f32>,
+}
+
+impl Default for PlayerProgress {
+    fn default() -> Self {
+        PlayerProgress {
+            level: 1,
+            last_level_up: None,
+        }
+    }
+}
+
+impl PlayerProgress {
+    /// Raises the level to match how many of `thresholds` `size` is past. Returns the levels
+    /// gained, empty if there were none.
+    pub fn advance(&mut self, size: f32, thresholds: &[f32]) -> std::ops::RangeInclusive<u32> {
+        let reached = 1 + thresholds
+            .iter()
+            .take_while(|threshold| size >= **threshold)
+            .count() as u32;
+        let gained = self.level + 1..=reached;
+        self.level = self.level.max(reached);
+        gained
+    }
+}
+
+pub(crate) fn track_progress(
+    players: Query<(&Transform, &Blob), With<PlayerInput>>,
+    mut progress: ResMut<PlayerProgress>,
+    config: Res<ProgressConfig>,
+    game_time: Res<GameTime>,
+    mut level_ups: EventWriter<LevelUpEvent>,
+) {
+    let Some(group) = PlayerGroup::from_pieces(players.iter()) else {
+        return;
+    };
+
+    for level in progress.advance(group.lead_size, &config.thresholds) {
+        level_ups.send(LevelUpEvent { level });
+        progress.last_level_up = Some(game_time.elapsed);
+    }
+}
+
+fn level_up_banner(
+    progress: Res<PlayerProgress>,
+    config: Res<ProgressConfig>,
+    game_time: Res<GameTime>,
+    mut egui_contexts: EguiContexts,
+) {
+    let Some(last_level_up) = progress.last_level_up else {
+        return;
+    };
+    if game_time.elapsed - last_level_up > config.banner_duration {
+        return;
+    }
+
+    egui::Area::new("level_up_banner")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .show(egui_contexts.ctx_mut(), |ui| {
+            ui.heading(format!("Level {}!", progress.level));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::ManualEventReader;
+
+    #[test]
+    fn growing_past_a_threshold_levels_up_once() {
+        let mut app = App::new();
+        app.init_resource::<ProgressConfig>()
+            .init_resource::<PlayerProgress>()
+            .init_resource::<GameTime>()
+            .add_event::<LevelUpEvent>()
+            .add_system(track_progress);
+        let player = app
+            .world
+            .spawn((Transform::default(), Blob::default(), PlayerInput))
+            .id();
+        let mut reader = ManualEventReader::<LevelUpEvent>::default();
+        let mut levels = Vec::new();
+        let mut play = |app: &mut App, size: f32| {
+            app.world.get_mut::<Blob>(player).unwrap().size = size;
+            for _ in 0..3 {
+                app.update();
+                let events = app.world.resource::<Events<LevelUpEvent>>();
+                levels.extend(reader.iter(events).map(|event| event.level));
+            }
+        };
+
+        play(&mut app, 0.5);
+        play(&mut app, 0.9);
+        assert_eq!(levels, [2]);
+        assert_eq!(app.world.resource::<PlayerProgress>().level, 2);
+    }
+}
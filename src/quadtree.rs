@@ -0,0 +1,182 @@
+//! Quadtree over blob positions on the ground plane, a cheaper broadphase for gameplay than the
+//! BVH, which stays for rendering
+use bevy::prelude::*;
+
+/// Nodes split once they hold more entries than this
+const NODE_CAPACITY: usize = 8;
+/// Entries stacked on the same spot would split forever otherwise
+const MAX_DEPTH: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+struct QuadEntry {
+    entity: Entity,
+    position: Vec2,
+    radius: f32,
+}
+
+/// Circles on the xy plane, rebuilt from scratch whenever positions change
+#[derive(Resource, Default)]
+pub struct Quadtree {
+    root: Option<QuadNode>,
+}
+
+impl Quadtree {
+    /// Builds a tree over `(entity, position, radius)` circles
+    pub fn build(entries: impl IntoIterator<Item = (Entity, Vec2, f32)>) -> Self {
+        let entries: Vec<QuadEntry> = entries
+            .into_iter()
+            .map(|(entity, position, radius)| QuadEntry {
+                entity,
+                position,
+                radius,
+            })
+            .collect();
+        if entries.is_empty() {
+            return Quadtree::default();
+        }
+
+        let (min, max) = entries.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), entry| (min.min(entry.position), max.max(entry.position)),
+        );
+        let half_size = ((max - min) * 0.5).max_element().max(f32::EPSILON);
+        let mut root = QuadNode::new((min + max) * 0.5, half_size);
+        for entry in entries {
+            root.insert(entry, 0);
+        }
+
+        Quadtree { root: Some(root) }
+    }
+
+    /// Calls `f` for every entity whose circle overlaps the circle of `radius` around `center`
+    pub fn query_range(&self, center: Vec2, radius: f32, mut f: impl FnMut(Entity)) {
+        if let Some(root) = &self.root {
+            root.query_range(center, radius, &mut f);
+        }
+    }
+}
+
+struct QuadNode {
+    center: Vec2,
+    half_size: f32,
+    /// Largest radius of any entry in this subtree, entries only go by their center so they can
+    /// poke out of the node by this much
+    max_radius: f32,
+    entries: Vec<QuadEntry>,
+    children: Option<Box<[QuadNode; 4]>>,
+}
+
+impl QuadNode {
+    fn new(center: Vec2, half_size: f32) -> Self {
+        QuadNode {
+            center,
+            half_size,
+            max_radius: 0.0,
+            entries: Vec::new(),
+            children: None,
+        }
+    }
+
+    /// Bit 0 for the +x half, bit 1 for the +y half
+    fn quadrant(&self, position: Vec2) -> usize {
+        (position.x >= self.center.x) as usize | ((position.y >= self.center.y) as usize) << 1
+    }
+
+    fn insert(&mut self, entry: QuadEntry, depth: usize) {
+        self.max_radius = self.max_radius.max(entry.radius);
+
+        let quadrant = self.quadrant(entry.position);
+        if let Some(children) = &mut self.children {
+            children[quadrant].insert(entry, depth + 1);
+            return;
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > NODE_CAPACITY && depth < MAX_DEPTH {
+            self.subdivide(depth);
+        }
+    }
+
+    fn subdivide(&mut self, depth: usize) {
+        let quarter = self.half_size * 0.5;
+        let center = self.center;
+        self.children = Some(Box::new([0, 1, 2, 3].map(|quadrant| {
+            let offset = Vec2::new(
+                if quadrant & 1 != 0 { quarter } else { -quarter },
+                if quadrant & 2 != 0 { quarter } else { -quarter },
+            );
+            QuadNode::new(center + offset, quarter)
+        })));
+
+        for entry in std::mem::take(&mut self.entries) {
+            self.insert(entry, depth);
+        }
+    }
+
+    fn query_range(&self, center: Vec2, radius: f32, f: &mut impl FnMut(Entity)) {
+        // distance from `center` to the node's square
+        let distance = ((center - self.center).abs() - Vec2::splat(self.half_size))
+            .max(Vec2::ZERO)
+            .length();
+        if distance >= radius + self.max_radius {
+            return;
+        }
+
+        for entry in &self.entries {
+            if entry.position.distance(center) < radius + entry.radius {
+                f(entry.entity);
+            }
+        }
+
+        if let Some(children) = &self.children {
+            for child in children.iter() {
+                child.query_range(center, radius, f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::GameRng;
+
+    #[test]
+    fn query_range_matches_brute_force() {
+        let mut rng = GameRng::new(3);
+        let mut circles: Vec<(Entity, Vec2, f32)> = (0..300)
+            .map(|i| {
+                let position = Vec2::new(rng.range(-20.0, 20.0), rng.range(-20.0, 20.0));
+                (Entity::from_raw(i), position, rng.range(0.05, 1.5))
+            })
+            .collect();
+        // a pile on one spot, deeper than the tree can split
+        circles.extend((300..320).map(|i| (Entity::from_raw(i), Vec2::new(5.0, 5.0), 0.2)));
+        let tree = Quadtree::build(circles.iter().copied());
+
+        let queries: Vec<(Vec2, f32)> = (0..100)
+            .map(|_| {
+                let center = Vec2::new(rng.range(-25.0, 25.0), rng.range(-25.0, 25.0));
+                (center, rng.range(0.0, 6.0))
+            })
+            .chain([(Vec2::new(5.0, 5.0), 0.1)])
+            .collect();
+        for (center, radius) in queries {
+            let mut found = Vec::new();
+            tree.query_range(center, radius, |entity| found.push(entity));
+            found.sort();
+            let expected: Vec<Entity> = circles
+                .iter()
+                .filter(|(_, position, r)| position.distance(center) < radius + r)
+                .map(|(entity, ..)| *entity)
+                .collect();
+            assert_eq!(found, expected, "around {} radius {}", center, radius);
+        }
+    }
+
+    #[test]
+    fn empty_tree_finds_nothing() {
+        let tree = Quadtree::build([]);
+        tree.query_range(Vec2::ZERO, 100.0, |entity| panic!("found {:?}", entity));
+    }
+}
@@ -1,7 +1,24 @@
 //! Raymarching for bevy
+use crate::ai::AiBlob;
+use crate::bvh::update_material_buffer;
 use crate::bvh::CalculateBvh;
 use crate::bvh::LocalBoundingBox;
+use crate::bvh::SweptAabbs;
+use crate::game::{BlobPalette, GameConfig, GameTime};
+use crate::occlusion::OcclusionFade;
+use crate::pellets::Pellet;
+use crate::quadtree::Quadtree;
+use crate::rng::GameRng;
+use crate::rotating_buffer::RotatingBuffer;
+use crate::selection::{OutlineConfig, Outlined};
+use crate::simulation::{add_gameplay_systems, SimulatedPosition};
+use crate::split::Recombining;
+use crate::teams::{Team, PLAYER_TEAM};
+use crate::trail::Trail;
+use crate::viruses::Virus;
+use crate::PlayerInput;
 use bevy::core_pipeline::core_2d::Transparent2d;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, FrameTimeDiagnosticsPlugin};
 use bevy::math::{vec3, vec4, Vec3Swizzles};
 use bevy::pbr::{MaterialPipeline, MaterialPipelineKey, NotShadowCaster, NotShadowReceiver};
 use bevy::prelude::*;
@@ -12,13 +29,18 @@ use bevy::render::render_resource::{
     RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError, SpecializedMeshPipelines,
     StorageBuffer, TextureDimension, TextureFormat, TextureUsages,
 };
-use bevy::render::renderer::RenderDevice;
+use bevy::render::renderer::{RenderDevice, RenderQueue};
 use bevy::render::texture::BevyDefault;
 use bevy::render::RenderApp;
+use bevy::utils::{HashMap, HashSet};
 use bevy::{
     reflect::TypeUuid,
     render::render_resource::{AsBindGroup, ShaderRef},
 };
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
 
 pub struct RaymarchingPlugin;
 
@@ -28,9 +50,159 @@ impl Plugin for RaymarchingPlugin {
             prepass_enabled: false,
             ..default()
         })
+        // the prepass is fixed per material type, so the prepass path gets a type of its own
+        .add_plugin(MaterialPlugin::<VoxelPrepassMaterial>::default())
+        .init_resource::<RenderPath>()
+        .init_resource::<PrepassBlobMaterial>()
+        .add_system(render_path_window)
+        .add_system(apply_render_path.after(render_path_window))
+        .add_system(
+            sync_prepass_material
+                .in_base_set(CoreSet::PostUpdate)
+                .after(update_material_buffer),
+        )
         .add_startup_system(spawn_debug_voxel)
-        .add_system(update_material)
-        .add_system(blob_merger);
+        .add_startup_system(setup_blob_diagnostics)
+        .init_resource::<MergeConfig>()
+        .init_resource::<Quadtree>()
+        .init_resource::<BlobLimit>()
+        .init_resource::<RaymarchQuality>()
+        .init_resource::<DebugGridConfig>()
+        .insert_resource(StartingLayout::load(Path::new(LAYOUT_PATH)))
+        .init_resource::<SquashStretch>()
+        .init_resource::<IdleBob>()
+        .init_resource::<DespawnQueue>()
+        .init_resource::<BlobBuffer>()
+        .add_event::<BlobMergeEvent>()
+        .add_system(adapt_raymarch_quality.before(update_material))
+        .add_system(update_material);
+        // the other plugins only bring their resources, every gameplay system is added here
+        add_gameplay_systems(app);
+    }
+}
+
+/// Bytes of blob data in use in the material buffer
+pub const BLOB_BUFFER_BYTES: DiagnosticId =
+    DiagnosticId::from_u128(266513707212400791186337893553893627309);
+
+fn setup_blob_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(
+        Diagnostic::new(BLOB_BUFFER_BYTES, "blob_buffer_bytes", 20)
+            .with_suffix(" B")
+            .with_smoothing_factor(0.0),
+    );
+}
+
+/// Grid of blobs the game starts with when there's no [`StartingLayout`] file. The player is
+/// the first cell, sizes come from [`GameConfig`].
+#[derive(Resource)]
+pub struct DebugGridConfig {
+    pub cols: usize,
+    pub rows: usize,
+    /// Distance between neighbouring blobs
+    pub spacing: f32,
+}
+
+impl Default for DebugGridConfig {
+    fn default() -> Self {
+        DebugGridConfig {
+            cols: 4,
+            rows: 4,
+            spacing: 2.0,
+        }
+    }
+}
+
+impl DebugGridConfig {
+    /// Positions of the grid cells on the z=0 plane, column by column
+    pub fn positions(&self) -> impl Iterator<Item = Vec2> + '_ {
+        let offset = Vec2::new(self.cols as f32, self.rows as f32) * self.spacing * 0.5;
+        (0..self.cols).flat_map(move |x| {
+            (0..self.rows).map(move |y| Vec2::new(x as f32, y as f32) * self.spacing - offset)
+        })
+    }
+
+    pub fn layout(&self) -> BlobLayout {
+        BlobLayout {
+            blobs: self
+                .positions()
+                .enumerate()
+                .map(|(index, position)| LayoutBlob {
+                    position,
+                    size: None,
+                    player: index == 0,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Where [`StartingLayout`] is loaded from at startup
+pub const LAYOUT_PATH: &str = "assets/layout.ron";
+
+/// Blobs placed by hand, for example
+///
+/// ```text
+/// (blobs: [
+///     (position: (0.0, 0.0), player: true),
+///     (position: (3.0, 1.0), size: 0.8),
+///     (position: (-2.0, 4.0)),
+/// ])
+/// ```
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct BlobLayout {
+    pub blobs: Vec<LayoutBlob>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct LayoutBlob {
+    pub position: Vec2,
+    /// `Blob::size`, the [`GameConfig`] starting size if left out
+    #[serde(default)]
+    pub size: Option<f32>,
+    /// Tags the player. Any number of blobs can be, without any the first blob is the player.
+    #[serde(default)]
+    pub player: bool,
+}
+
+impl BlobLayout {
+    /// Parses a layout, optional fields can be written without `Some(..)`
+    pub fn from_ron(text: &str) -> ron::error::SpannedResult<Self> {
+        ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(text)
+    }
+
+    /// Whether the blob at `index` is tagged as the player, or is the first blob if none are
+    pub fn is_player(&self, index: usize) -> bool {
+        if self.blobs.iter().any(|blob| blob.player) {
+            matches!(self.blobs.get(index), Some(blob) if blob.player)
+        } else {
+            index == 0
+        }
+    }
+}
+
+/// The [`BlobLayout`] in [`LAYOUT_PATH`], None if there's no such file and the game starts with
+/// the [`DebugGridConfig`] grid instead
+#[derive(Resource, Default, Debug)]
+pub struct StartingLayout(pub Option<BlobLayout>);
+
+impl StartingLayout {
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path).map(|text| BlobLayout::from_ron(&text)) {
+            Ok(Ok(layout)) => StartingLayout(Some(layout)),
+            Ok(Err(error)) => {
+                warn!("couldn't read the layout in {}: {}", path.display(), error);
+                StartingLayout(None)
+            }
+            Err(_) => StartingLayout(None),
+        }
+    }
+
+    /// What the game starts with
+    pub fn resolve(&self, grid: &DebugGridConfig) -> BlobLayout {
+        self.0.clone().unwrap_or_else(|| grid.layout())
     }
 }
 
@@ -39,6 +211,12 @@ fn spawn_debug_voxel(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<VoxelMaterial>>,
     render_device: Res<RenderDevice>,
+    limit: Res<BlobLimit>,
+    grid: Res<DebugGridConfig>,
+    layout: Res<StartingLayout>,
+    game_config: Res<GameConfig>,
+    palette: Res<BlobPalette>,
+    mut rng: ResMut<GameRng>,
 ) {
     let empty_buffer = render_device.create_buffer(&BufferDescriptor {
         label: None,
@@ -46,46 +224,187 @@ fn spawn_debug_voxel(
         usage: BufferUsages::STORAGE,
         mapped_at_creation: false,
     });
-    let material = materials.add(VoxelMaterial {
-        blobs: BlobData::default(),
-        bvh: empty_buffer,
+    // replaced by the rotating buffers once `update_material` and the BVH have run
+    let empty_blobs = render_device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: BlobData::min_size().get(),
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
     });
+    let material = BlobMaterial(materials.add(VoxelMaterial {
+        blobs: empty_blobs,
+        bvh: empty_buffer,
+    }));
+    let mesh = BlobMesh(meshes.add(Mesh::from(shape::Cube { size: 2.0 })));
+
+    spawn_starting_blobs(
+        &mut commands,
+        &material,
+        &mesh,
+        &layout.resolve(&grid),
+        &limit,
+        &game_config,
+        &palette,
+        &mut rng,
+    );
+
+    commands.insert_resource(material);
+    commands.insert_resource(mesh);
+}
+
+/// Spawns the player and AI blobs the game starts with. The AI blobs get their colors from
+/// `palette`.
+pub fn spawn_starting_blobs(
+    commands: &mut Commands,
+    material: &BlobMaterial,
+    mesh: &BlobMesh,
+    layout: &BlobLayout,
+    limit: &BlobLimit,
+    game_config: &GameConfig,
+    palette: &BlobPalette,
+    rng: &mut GameRng,
+) {
+    for (index, layout_blob) in layout.blobs.iter().enumerate() {
+        if !limit.allows(index) {
+            break;
+        }
 
-    for x_ in 0..4 {
-        for y_ in 0..4 {
-            let x = (x_ as f32) * 2. - 4.0;
-            let y = (y_ as f32) * 2. - 4.0;
-
-            let mut e = commands.spawn((
-                MaterialMeshBundle {
-                    mesh: meshes.add(Mesh::from(shape::Cube { size: 2.0 })),
-                    transform: Transform::from_xyz(x, y, 1.0).with_scale(vec3(1., 1., 1.)),
-                    material: material.clone(),
-                    ..default()
-                },
-                NotShadowCaster,
-                Blob::default(),
-                CalculateBvh,
-                LocalBoundingBox {
-                    min: vec3(-1., -1., -1.),
-                    max: vec3(1., 1., 1.),
-                },
+        let is_player = layout.is_player(index);
+        let (size, color) = if is_player {
+            (game_config.player_start_size, palette.player)
+        } else {
+            (game_config.ai_start_size, palette.pick(rng))
+        };
+        let mut e = commands.spawn(BlobBundle::new(
+            material,
+            mesh,
+            Transform::from_translation(layout_blob.position.extend(1.0)),
+            Blob {
+                size: layout_blob.size.unwrap_or(size),
+                color,
+                ..default()
+            },
+        ));
+
+        if is_player {
+            e.insert((
+                crate::PlayerInput,
+                PLAYER_TEAM,
+                Trail::new(40, Color::WHITE),
             ));
+        } else {
+            e.insert(AiBlob::default());
+        }
+    }
+}
 
-            if x_ == 0 && y_ == 0 {
-                e.insert((crate::PlayerInput));
-            }
+/// Everything a blob entity needs to be rendered and take part in the simulation
+#[derive(Bundle)]
+pub struct BlobBundle {
+    pub mesh_bundle: MaterialMeshBundle<VoxelMaterial>,
+    pub not_shadow_caster: NotShadowCaster,
+    pub blob: Blob,
+    pub velocity: Velocity,
+    pub calculate_bvh: CalculateBvh,
+    pub local_bounding_box: LocalBoundingBox,
+    pub buffer_index: EntityBufferIndex,
+}
+
+impl BlobBundle {
+    /// The scale of `transform` is replaced with [`Blob::scale`]
+    pub fn new(material: &BlobMaterial, mesh: &BlobMesh, transform: Transform, blob: Blob) -> Self {
+        BlobBundle {
+            mesh_bundle: MaterialMeshBundle {
+                mesh: mesh.0.clone(),
+                transform: transform.with_scale(blob.scale()),
+                material: material.0.clone(),
+                ..default()
+            },
+            not_shadow_caster: NotShadowCaster,
+            blob,
+            velocity: Velocity::default(),
+            calculate_bvh: CalculateBvh,
+            local_bounding_box: LocalBoundingBox::blob(),
+            buffer_index: EntityBufferIndex(-1),
         }
     }
+}
+
+/// Movement on top of whatever the blob does on its own, slows down over time
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Velocity(pub Vec2);
+
+/// How much of its velocity a blob keeps after a second
+const VELOCITY_RETAINED_PER_SECOND: f32 = 0.05;
 
-    commands.insert_resource(BlobMaterial(material));
+pub(crate) fn apply_velocity(
+    mut blobs: Query<(&mut Transform, &mut Velocity)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let delta = fixed_time.period.as_secs_f32();
+    let damping = VELOCITY_RETAINED_PER_SECOND.powf(delta);
+
+    for (mut transform, mut velocity) in blobs.iter_mut() {
+        if velocity.0 == Vec2::ZERO {
+            continue;
+        }
+
+        transform.translation += (velocity.0 * delta).extend(0.0);
+        velocity.0 *= damping;
+        if velocity.0.length_squared() < 0.0001 {
+            velocity.0 = Vec2::ZERO;
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct Blob {
+    /// Radius of the blob, see [`Blob::radius`] and [`Blob::area`]
     pub size: f32,
     pub direction: f32,
-    pub last_ate: f32,
+    /// `GameTime` of the blob's last merge, None until it first eats
+    pub last_ate: Option<f32>,
+    pub color: Color,
+    /// Size the blob is growing towards after eating, see [`grow_blobs`]
+    pub target_size: Option<f32>,
+    /// From 0 to [`MAX_HEALTH`]. Drains while a bigger blob overlaps this one, which only gets
+    /// eaten once it hits zero.
+    pub health: f32,
+}
+
+pub const MAX_HEALTH: f32 = 1.0;
+
+/// How much bigger the mesh (and its bounding box) is than the blob's `size`, leaving room for the
+/// wobble and nose the shader adds on top
+const SCALE_PER_SIZE: f32 = 2.0;
+
+impl Blob {
+    /// Same as `size`, which is the radius of the sphere the shader draws
+    pub fn radius(&self) -> f32 {
+        self.size
+    }
+
+    /// Area of the blob's footprint on the ground
+    pub fn area(&self) -> f32 {
+        std::f32::consts::PI * self.size * self.size
+    }
+
+    /// Sets `size` to match `area`
+    pub fn set_area(&mut self, area: f32) {
+        self.size = (area.max(0.0) / std::f32::consts::PI).sqrt();
+    }
+
+    /// Unit vector the blob is facing on the ground, -y rotated by `direction`
+    pub fn forward(&self) -> Vec2 {
+        Vec2::from_angle(self.direction).rotate(Vec2::NEG_Y)
+    }
+
+    /// `size` is the source of truth for how big a blob is, this is the `Transform::scale` that
+    /// goes with it. The BVH gets its AABB from this scale and [`LocalBoundingBox::blob`], not
+    /// from the transform, so the transform is free to be squashed or stretched for looks.
+    pub fn scale(&self) -> Vec3 {
+        Vec3::splat(self.size * SCALE_PER_SIZE)
+    }
 }
 
 impl Default for Blob {
@@ -93,40 +412,139 @@ impl Default for Blob {
         Blob {
             size: 0.5,
             direction: 0.0,
-            last_ate: 0.0,
+            last_ate: None,
+            color: Color::rgb(1.0, 0.51, 0.41),
+            target_size: None,
+            health: MAX_HEALTH,
         }
     }
 }
 
 fn update_material(
     mut commands: Commands,
-    blobs: Query<(Entity, &Transform, &Blob)>,
+    mut blobs: Query<(
+        Entity,
+        &Transform,
+        &Blob,
+        Option<&Velocity>,
+        Option<&Outlined>,
+        Option<&SpawnProtection>,
+        Option<&OcclusionFade>,
+        Option<&SimulatedPosition>,
+        Option<&mut EntityBufferIndex>,
+    )>,
     mut materials: ResMut<Assets<VoxelMaterial>>,
     material: Res<BlobMaterial>,
+    limit: Res<BlobLimit>,
+    quality: Res<RaymarchQuality>,
+    game_time: Res<GameTime>,
+    outline: Res<OutlineConfig>,
+    squash_stretch: Res<SquashStretch>,
+    idle_bob: Res<IdleBob>,
+    fixed_time: Res<FixedTime>,
+    mut diagnostics: ResMut<Diagnostics>,
+    mut blob_buffer: ResMut<BlobBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
 ) {
-    if let Some(instance) = materials.get_mut(&material.0) {
-        instance.blobs.clear();
+    let Some(instance) = materials.get_mut(&material.0) else {
+        return;
+    };
+
+    let buffer = blob_buffer.write_next(&render_device, &render_queue, |blob_data| {
+        blob_data.clear();
+        blob_data.time = game_time.elapsed;
+        blob_data.outline_color = Vec4::from(outline.color.as_linear_rgba_f32());
+        blob_data.bob_frequency = idle_bob.frequency;
+        let tick = fixed_time.period.as_secs_f32();
 
-        for (e, transform, blob) in blobs.iter() {
+        let blobs = blobs.iter_mut().map(
+            |(e, transform, blob, velocity, outlined, protection, fade, simulated, index)| {
+                let details = (
+                    transform, blob, velocity, outlined, protection, fade, simulated,
+                );
+                (e, index, details)
+            },
+        );
+        assign_buffer_indices(&mut commands, blobs, |e, details| {
+            let (transform, blob, velocity, outlined, protection, fade, simulated) = details;
             let transform: &Transform = transform;
             let blob: &Blob = blob;
 
-            let buffer_index = instance.blobs.push(BlobEntity {
-                position: transform.translation.xy(),
-                size: blob.size,
-                direction: blob.direction,
-                last_ate: blob.last_ate,
-                color: Default::default(),
-            });
+            // blobs past the limit or the buffer's capacity aren't uploaded
+            if !limit.allows(blob_data.blob_count as usize) {
+                return None;
+            }
+            let buffer_index = blob_data.push(BlobEntity {
+                stretch: velocity.map_or(1.0, |velocity| squash_stretch.stretch(velocity.0)),
+                stretch_direction: velocity
+                    .and_then(|velocity| velocity.0.try_normalize())
+                    .unwrap_or(Vec2::X),
+                protection: protection.map_or(0.0, |protection| protection.0.percent_left()),
+                opacity: fade.map_or(1.0, |fade| fade.opacity),
+                bob: simulated.map_or(0.0, |position| {
+                    let speed = (position.current - position.previous).xy().length() / tick;
+                    idle_bob.amplitude(speed)
+                }),
+                bob_phase: bob_phase(e),
+                ..BlobEntity::new(blob, transform.translation.xy())
+            })?;
+            if outlined.is_some() {
+                blob_data.outlined = buffer_index;
+            }
+            Some(buffer_index)
+        });
+
+        blob_data.max_steps = quality.steps_for_blob_count(blob_data.blob_count as usize);
+
+        diagnostics.add_measurement(BLOB_BUFFER_BYTES, || {
+            (blob_data.blob_count as u64 * u64::from(BlobEntity::min_size())) as f64
+        });
+    });
+    instance.blobs = buffer.clone();
+}
 
-            commands.entity(e).insert((EntityBufferIndex(buffer_index)));
+/// Goes through `blobs` in entity order, giving each to `push` and pointing its
+/// [`EntityBufferIndex`] at the slot `push` put it in, or -1 if it returned None. Every index is
+/// rewritten in this one pass, so the BVH buffer never sees a mix of old and new ones.
+fn assign_buffer_indices<'a, T>(
+    commands: &mut Commands,
+    blobs: impl Iterator<Item = (Entity, Option<Mut<'a, EntityBufferIndex>>, T)>,
+    mut push: impl FnMut(Entity, T) -> Option<i32>,
+) {
+    for (e, index, details) in in_entity_order(blobs, |(e, ..)| *e) {
+        let buffer_index = push(e, details).unwrap_or(-1);
+        match index {
+            Some(mut index) => {
+                if index.0 != buffer_index {
+                    index.0 = buffer_index;
+                }
+            }
+            // blobs spawned without a `BlobBundle`
+            None => {
+                commands.entity(e).insert(EntityBufferIndex(buffer_index));
+            }
         }
     }
 }
 
+/// Query order isn't stable, this sorts by entity so every blob keeps its buffer index between
+/// frames
+fn in_entity_order<T>(items: impl Iterator<Item = T>, entity: impl Fn(&T) -> Entity) -> Vec<T> {
+    let mut sorted: Vec<T> = items.collect();
+    sorted.sort_unstable_by_key(entity);
+    sorted
+}
+
+#[derive(Debug, Resource)]
+pub struct BlobMaterial(pub Handle<VoxelMaterial>);
+
+/// Cube mesh shared by all blobs, the shader does the rest
 #[derive(Debug, Resource)]
-struct BlobMaterial(Handle<VoxelMaterial>);
+pub struct BlobMesh(pub Handle<Mesh>);
 
+/// Where the blob is in the material's blob array, or -1 if it didn't make it in. Kept up to
+/// date by `update_material`.
 #[derive(Debug, Component)]
 pub struct EntityBufferIndex(pub i32);
 
@@ -137,44 +555,257 @@ struct BlobEntity {
     direction: f32,
     last_ate: f32,
     color: Vec3,
+    /// Visual stretch along `stretch_direction`, 1 when round. See [`SquashStretch`]
+    stretch: f32,
+    stretch_direction: Vec2,
+    /// Fraction of [`SpawnProtection`] left, for the shader to show
+    protection: f32,
+    /// Below 1 the shader drops this blob from a matching share of pixels, see [`OcclusionFade`]
+    opacity: f32,
+    /// Height of the idle bob, 0 while moving. See [`IdleBob`]
+    bob: f32,
+    /// Keeps blobs from bobbing in sync, see [`bob_phase`]
+    bob_phase: f32,
+}
+
+impl BlobEntity {
+    /// `blob` at `position`, round, unprotected and fully visible
+    fn new(blob: &Blob, position: Vec2) -> Self {
+        BlobEntity {
+            position,
+            size: blob.size,
+            direction: blob.direction,
+            // long enough ago for the shader's ease_out to have settled
+            last_ate: blob.last_ate.unwrap_or(-1000.0),
+            color: Vec4::from(blob.color.as_linear_rgba_f32()).truncate(),
+            stretch: 1.0,
+            stretch_direction: Vec2::X,
+            protection: 0.0,
+            opacity: 1.0,
+            bob: 0.0,
+            bob_phase: 0.0,
+        }
+    }
+}
+
+/// Size of the blob array in the [`BlobBuffer`], must match `BlobData` in the shader
+pub const BLOB_CAPACITY: usize = 64;
+
+/// Upper limit for raymarching steps, must match `MAX_RT_STEPS` in the shader
+pub const MAX_RAYMARCH_STEPS: u32 = 64;
+
+/// Adaptive raymarching quality. The step limit goes down as the blob count goes up, and the
+/// point where that starts moves with the frame time.
+#[derive(Resource)]
+pub struct RaymarchQuality {
+    /// Frame time we try to stay under, in milliseconds
+    pub target_frame_time: f32,
+    pub min_steps: u32,
+    pub max_steps: u32,
+    /// Up to this many blobs get the full `max_steps`
+    pub full_quality_blobs: usize,
+}
+
+impl Default for RaymarchQuality {
+    fn default() -> Self {
+        RaymarchQuality {
+            target_frame_time: 1000. / 60.,
+            min_steps: 24,
+            max_steps: MAX_RAYMARCH_STEPS,
+            full_quality_blobs: 16,
+        }
+    }
+}
+
+impl RaymarchQuality {
+    /// Step limit for the given number of blobs, falling linearly from `max_steps` at
+    /// `full_quality_blobs` to `min_steps` at [`BLOB_CAPACITY`]
+    pub fn steps_for_blob_count(&self, blob_count: usize) -> u32 {
+        let max_steps = self.max_steps.min(MAX_RAYMARCH_STEPS);
+        let min_steps = self.min_steps.min(max_steps);
+        if blob_count <= self.full_quality_blobs {
+            return max_steps;
+        }
+
+        let range = BLOB_CAPACITY.saturating_sub(self.full_quality_blobs).max(1);
+        let t = ((blob_count - self.full_quality_blobs) as f32 / range as f32).min(1.0);
+        max_steps - ((max_steps - min_steps) as f32 * t).round() as u32
+    }
+}
+
+/// Nudges `full_quality_blobs` towards whatever keeps the frame time near the target
+fn adapt_raymarch_quality(
+    mut quality: ResMut<RaymarchQuality>,
+    diagnostics: Res<Diagnostics>,
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+) {
+    if timer.duration().is_zero() {
+        *timer = Timer::from_seconds(0.25, TimerMode::Repeating);
+    }
+    // real time, slow motion doesn't change how long frames take
+    if !timer.tick(time.raw_delta()).just_finished() {
+        return;
+    }
+
+    let frame_time = match diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed())
+    {
+        Some(frame_time) => frame_time as f32,
+        None => return,
+    };
+
+    if frame_time > quality.target_frame_time * 1.1 && quality.full_quality_blobs > 1 {
+        quality.full_quality_blobs -= 1;
+    } else if frame_time < quality.target_frame_time * 0.8
+        && quality.full_quality_blobs < BLOB_CAPACITY
+    {
+        quality.full_quality_blobs += 1;
+    }
+}
+
+/// Blobs moving with a [`Velocity`] get stretched along it and squashed across it in the shader.
+/// Only the drawn shape changes, `Blob::size` and the AABB stay as they are.
+#[derive(Resource)]
+pub struct SquashStretch {
+    /// Stretch per unit of speed
+    pub intensity: f32,
+    /// Must stay below `SCALE_PER_SIZE` or the shape gets clipped by its bounding box
+    pub max_stretch: f32,
+}
+
+impl Default for SquashStretch {
+    fn default() -> Self {
+        SquashStretch {
+            intensity: 0.06,
+            max_stretch: 1.6,
+        }
+    }
+}
+
+impl SquashStretch {
+    /// How much longer than round a blob moving at `velocity` is drawn
+    pub fn stretch(&self, velocity: Vec2) -> f32 {
+        (1.0 + velocity.length() * self.intensity).min(self.max_stretch.min(SCALE_PER_SIZE))
+    }
+}
+
+/// Blobs standing still bob up and down in the shader so the arena doesn't look frozen. Only
+/// the drawn shape moves.
+#[derive(Resource)]
+pub struct IdleBob {
+    /// Height of the bob for a blob standing completely still
+    pub height: f32,
+    /// Radians per second
+    pub frequency: f32,
+    /// Blobs moving at least this fast don't bob, slower ones bob less the faster they go
+    pub max_speed: f32,
+}
+
+impl Default for IdleBob {
+    fn default() -> Self {
+        IdleBob {
+            height: 0.04,
+            frequency: 2.0,
+            max_speed: 0.5,
+        }
+    }
+}
+
+impl IdleBob {
+    /// Height of the bob for a blob moving at `speed`
+    pub fn amplitude(&self, speed: f32) -> f32 {
+        if self.max_speed <= 0.0 {
+            return 0.0;
+        }
+        self.height * (1.0 - speed / self.max_speed).clamp(0.0, 1.0)
+    }
+}
+
+/// Offset into the bob animation for `entity`, from 0 to TAU. Always the same for the same
+/// entity.
+pub fn bob_phase(entity: Entity) -> f32 {
+    // small seeds come out nearly evenly spaced and 0 is bumped to 1, mix the bits up first
+    GameRng::new(entity.to_bits() ^ 0x9e37_79b9_7f4a_7c15).next_f32() * std::f32::consts::TAU
+}
+
+/// Maximum number of blobs alive at once. Spawners should check [`BlobLimit::allows`] first,
+/// and anything past it won't be uploaded to the GPU.
+#[derive(Resource)]
+pub struct BlobLimit(pub usize);
+
+impl Default for BlobLimit {
+    fn default() -> Self {
+        BlobLimit(BLOB_CAPACITY)
+    }
+}
+
+impl BlobLimit {
+    /// Whether one more blob can be spawned with `blob_count` blobs already alive
+    pub fn allows(&self, blob_count: usize) -> bool {
+        blob_count < self.0.min(BLOB_CAPACITY)
+    }
 }
 
 #[derive(ShaderType, Debug, Clone)]
-struct BlobData {
+pub(crate) struct BlobData {
+    /// [`GameTime`], used for animations instead of `globals.time` so they stop while paused
+    time: f32,
     blob_count: u32,
-    blobs: [BlobEntity; 64],
+    /// Raymarching step limit, see [`RaymarchQuality`]
+    max_steps: u32,
+    /// Buffer index of the blob drawn with an outline, or -1
+    outlined: i32,
+    /// See [`IdleBob::frequency`]
+    bob_frequency: f32,
+    outline_color: Vec4,
+    blobs: [BlobEntity; BLOB_CAPACITY],
 }
 
 impl Default for BlobData {
     fn default() -> Self {
         BlobData {
+            time: 0.0,
             blob_count: 0,
-            blobs: [BlobEntity::default(); 64],
+            max_steps: MAX_RAYMARCH_STEPS,
+            outlined: -1,
+            bob_frequency: 0.0,
+            outline_color: Vec4::ZERO,
+            blobs: [BlobEntity::default(); BLOB_CAPACITY],
         }
     }
 }
 
+/// GPU copies of the blob array, see [`RotatingBuffer`]
+pub(crate) type BlobBuffer = RotatingBuffer<BlobData>;
+
 impl BlobData {
     fn clear(&mut self) {
         self.blob_count = 0;
+        self.outlined = -1;
     }
 
-    fn push(&mut self, blob: BlobEntity) -> i32 {
-        assert!(self.blob_count < 63);
+    /// Returns the buffer index of the blob, or None if the buffer is full
+    fn push(&mut self, blob: BlobEntity) -> Option<i32> {
+        if self.blob_count as usize >= BLOB_CAPACITY {
+            return None;
+        }
         let index = self.blob_count as i32;
 
         self.blobs[self.blob_count as usize] = blob;
         self.blob_count += 1;
 
-        index
+        Some(index)
     }
 }
 
 #[derive(AsBindGroup, TypeUuid, Debug, Clone)]
 #[uuid = "f690fdae-d598-45ab-8225-97e2a3f056e0"]
 pub struct VoxelMaterial {
-    #[uniform(0)]
-    blobs: BlobData,
+    /// The [`BlobBuffer`] written this frame
+    #[storage(0, read_only, buffer)]
+    pub blobs: Buffer,
     #[storage(1, read_only, buffer)]
     pub bvh: Buffer,
 }
@@ -189,24 +820,1428 @@ impl Material for VoxelMaterial {
     }
 }
 
-fn blob_merger(
+/// Same bindings as [`VoxelMaterial`], but with the prepass enabled, so blob depth and normals
+/// come from `voxel_raymarch.wgsl`. Only used with [`RenderPath::Prepass`].
+#[derive(AsBindGroup, TypeUuid, Debug, Clone)]
+#[uuid = "3c0b0b52-8d8f-4a4e-9a55-5f1c3f2e7d10"]
+pub struct VoxelPrepassMaterial {
+    /// The [`BlobBuffer`] written this frame
+    #[storage(0, read_only, buffer)]
+    pub blobs: Buffer,
+    #[storage(1, read_only, buffer)]
+    pub bvh: Buffer,
+}
+
+impl From<&VoxelMaterial> for VoxelPrepassMaterial {
+    fn from(material: &VoxelMaterial) -> Self {
+        VoxelPrepassMaterial {
+            blobs: material.blobs.clone(),
+            bvh: material.bvh.clone(),
+        }
+    }
+}
+
+impl Material for VoxelPrepassMaterial {
+    fn fragment_shader() -> ShaderRef {
+        VoxelMaterial::fragment_shader()
+    }
+
+    fn prepass_fragment_shader() -> ShaderRef {
+        VoxelMaterial::prepass_fragment_shader()
+    }
+}
+
+/// Which pipeline draws the blobs
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    /// `voxel_material.wgsl` raymarches everything in the forward pass
+    #[default]
+    Forward,
+    /// `voxel_raymarch.wgsl` raymarches depth and normals in the prepass first
+    Prepass,
+}
+
+/// Created the first time [`RenderPath::Prepass`] is picked
+#[derive(Resource, Default)]
+pub struct PrepassBlobMaterial(Option<Handle<VoxelPrepassMaterial>>);
+
+fn render_path_window(mut render_path: ResMut<RenderPath>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("Rendering").show(egui_contexts.ctx_mut(), |ui| {
+        let mut selected = *render_path;
+        ui.horizontal(|ui| {
+            ui.label("Raymarch in");
+            ui.radio_value(&mut selected, RenderPath::Forward, "Forward pass");
+            ui.radio_value(&mut selected, RenderPath::Prepass, "Prepass");
+        });
+
+        // only touch the resource on actual edits, so change detection stays quiet
+        if selected != *render_path {
+            *render_path = selected;
+        }
+    });
+}
+
+/// Moves the blobs over to the material for the current [`RenderPath`]. Blobs always spawn with
+/// [`BlobMaterial`], so this also catches new ones.
+fn apply_render_path(
+    mut commands: Commands,
+    forward_blobs: Query<Entity, (With<Blob>, With<Handle<VoxelMaterial>>)>,
+    prepass_blobs: Query<Entity, (With<Blob>, With<Handle<VoxelPrepassMaterial>>)>,
+    render_path: Res<RenderPath>,
+    blob_material: Res<BlobMaterial>,
+    mut prepass_material: ResMut<PrepassBlobMaterial>,
+    materials: Res<Assets<VoxelMaterial>>,
+    mut prepass_materials: ResMut<Assets<VoxelPrepassMaterial>>,
+) {
+    match *render_path {
+        RenderPath::Forward => {
+            for entity in prepass_blobs.iter() {
+                commands
+                    .entity(entity)
+                    .remove::<Handle<VoxelPrepassMaterial>>()
+                    .insert(blob_material.0.clone());
+            }
+        }
+        RenderPath::Prepass => {
+            if prepass_material.0.is_none() {
+                let Some(material) = materials.get(&blob_material.0) else {
+                    return;
+                };
+                prepass_material.0 = Some(prepass_materials.add(material.into()));
+            }
+            let Some(handle) = &prepass_material.0 else {
+                return;
+            };
+
+            for entity in forward_blobs.iter() {
+                commands
+                    .entity(entity)
+                    .remove::<Handle<VoxelMaterial>>()
+                    .insert(handle.clone());
+            }
+        }
+    }
+}
+
+/// [`update_material`] and the BVH only write to [`BlobMaterial`], copy that over
+fn sync_prepass_material(
+    render_path: Res<RenderPath>,
+    blob_material: Res<BlobMaterial>,
+    prepass_material: Res<PrepassBlobMaterial>,
+    materials: Res<Assets<VoxelMaterial>>,
+    mut prepass_materials: ResMut<Assets<VoxelPrepassMaterial>>,
+) {
+    if *render_path != RenderPath::Prepass {
+        return;
+    }
+    let (Some(material), Some(handle)) = (materials.get(&blob_material.0), &prepass_material.0)
+    else {
+        return;
+    };
+    if let Some(prepass) = prepass_materials.get_mut(handle) {
+        *prepass = material.into();
+    }
+}
+
+/// Sent when one blob eats another
+pub struct BlobMergeEvent {
+    pub eater: Entity,
+    pub eaten: Entity,
+    /// Size of the eaten blob before it got eaten
+    pub eaten_size: f32,
+    pub eaten_color: Color,
+    /// Where the eaten blob was
+    pub position: Vec3,
+    /// Growth the eater missed out on by hitting `GameConfig::max_size`
+    pub overflow: f32,
+}
+
+/// A pair `blob_merger` found close enough for `bigger` to eat `smaller`
+#[derive(Clone, Copy, Debug)]
+pub struct MergeCandidate {
+    pub bigger: Entity,
+    pub smaller: Entity,
+    /// Between the centers
+    pub distance: f32,
+    /// How far the two blobs' radii overlap
+    pub overlap: f32,
+}
+
+impl MergeCandidate {
+    /// Order merges are applied in, nearest pairs first. Deeper overlaps and then lower entity
+    /// ids break ties, so the order never depends on query iteration.
+    pub fn priority(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(other.overlap.total_cmp(&self.overlap))
+            .then(self.smaller.cmp(&other.smaller))
+            .then(self.bigger.cmp(&other.bigger))
+    }
+}
+
+#[derive(Resource)]
+pub struct MergeConfig {
+    /// Blobs too close in size to eat each other are pushed apart when closer than their
+    /// combined radius times this
+    pub merge_factor: f32,
+    /// A blob can eat a smaller one once the smaller one's center is within its radius times
+    /// this. 1.0 matches what the shader draws, sizes are radii (see [`Blob::radius`]).
+    pub eat_factor: f32,
+    /// How much of the eaten blob's size the eater gains
+    pub gain_factor: f32,
+    /// Size gained per unit of [`Pellet::value`], pellets don't go by `gain_factor`
+    pub pellet_gain: f32,
+    /// How many times bigger the eater has to be, blobs closer in size push each other apart
+    pub size_ratio: f32,
+    /// Upper limit for merges applied in a single frame, the rest wait for the next one. The
+    /// nearest pairs go first. `usize::MAX` for no limit.
+    pub max_merges_per_frame: usize,
+    /// Roughly how long it takes an eater to grow to its new size, in seconds
+    pub grow_time: f32,
+    /// Seconds after eating before a blob can eat again, going by `Blob::last_ate`
+    pub eat_cooldown: f32,
+    /// How long new blobs get [`SpawnProtection`] for, in seconds
+    pub spawn_protection: f32,
+    /// Health per second a blob loses while a bigger blob overlaps it, pellets are eaten
+    /// straight away
+    pub health_drain: f32,
+    /// Health per second blobs win back
+    pub health_regen: f32,
+    /// How `blob_merger` finds blobs close enough to interact
+    pub broadphase: Broadphase,
+    /// Blobs can only eat what's in front of them, see [`MergeConfig::in_eat_cone`]
+    pub frontal_eating: bool,
+    /// Half of the cone's opening angle, in radians
+    pub eat_cone_half_angle: f32,
+    /// Most an eater's color can move towards what it ate in one merge, so it keeps its own
+    /// color unless it keeps eating the same one
+    pub max_color_blend: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Broadphase {
+    /// Checks every pair, fine for the usual blob counts
+    #[default]
+    AllPairs,
+    /// Rebuilds the [`Quadtree`] resource every tick and only checks blobs near each other
+    Quadtree,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        MergeConfig {
+            merge_factor: 0.75,
+            eat_factor: 1.0,
+            gain_factor: 0.15,
+            pellet_gain: 0.01,
+            size_ratio: 1.25,
+            max_merges_per_frame: 8,
+            grow_time: 0.3,
+            eat_cooldown: 0.25,
+            spawn_protection: 1.5,
+            health_drain: 2.0,
+            health_regen: 0.25,
+            broadphase: Broadphase::default(),
+            frontal_eating: false,
+            eat_cone_half_angle: std::f32::consts::FRAC_PI_3,
+            max_color_blend: 0.4,
+        }
+    }
+}
+
+impl MergeConfig {
+    /// `eater`'s color after eating `eaten`, moved towards `eaten`'s by its share of the
+    /// combined area
+    pub fn blend_color(&self, eater: &Blob, eaten: &Blob) -> Color {
+        let total_area = eater.area() + eaten.area();
+        if total_area <= 0.0 {
+            return eater.color;
+        }
+
+        let t = (eaten.area() / total_area).min(self.max_color_blend);
+        let from = Vec4::from(eater.color.as_rgba_f32());
+        let to = Vec4::from(eaten.color.as_rgba_f32());
+        let blended = from.lerp(to, t);
+        Color::rgba(blended.x, blended.y, blended.z, blended.w)
+    }
+
+    /// Whether `blob` ate less than `eat_cooldown` seconds before `now` and can't eat yet
+    pub fn digesting(&self, blob: &Blob, now: f32) -> bool {
+        blob.last_ate
+            .map_or(false, |last_ate| now - last_ate < self.eat_cooldown)
+    }
+
+    /// Whether `target` is inside the frontal cone of `eater` at `eater_position`. Always true
+    /// with `frontal_eating` off.
+    pub fn in_eat_cone(&self, eater: &Blob, eater_position: Vec2, target: Vec2) -> bool {
+        if !self.frontal_eating {
+            return true;
+        }
+
+        // right on top of the eater counts as in front
+        let Some(to_target) = (target - eater_position).try_normalize() else {
+            return true;
+        };
+        eater.forward().dot(to_target) >= self.eat_cone_half_angle.cos()
+    }
+}
+
+/// Blobs eaten this tick. They're despawned together by [`apply_despawns`] once all of the
+/// gameplay systems are done, instead of one command at a time.
+#[derive(Resource, Default, Debug)]
+pub struct DespawnQueue(Vec<Entity>);
+
+impl DespawnQueue {
+    pub fn push(&mut self, entity: Entity) {
+        self.0.push(entity);
+    }
+}
+
+/// Despawns everything in the [`DespawnQueue`], in entity order
+pub(crate) fn apply_despawns(world: &mut World) {
+    let mut queue = std::mem::take(&mut world.resource_mut::<DespawnQueue>().0);
+    queue.sort_unstable();
+    queue.dedup();
+    for entity in queue {
+        world.despawn(entity);
+    }
+}
+
+/// Blobs can't be eaten while this runs. Eating something ends it early.
+#[derive(Component)]
+pub struct SpawnProtection(pub Timer);
+
+/// Gives every new blob [`SpawnProtection`]
+pub(crate) fn protect_new_blobs(
+    mut commands: Commands,
+    new_blobs: Query<Entity, Added<Blob>>,
+    config: Res<MergeConfig>,
+) {
+    for entity in new_blobs.iter() {
+        commands
+            .entity(entity)
+            .insert(SpawnProtection(Timer::from_seconds(
+                config.spawn_protection,
+                TimerMode::Once,
+            )));
+    }
+}
+
+pub(crate) fn tick_spawn_protection(
+    mut commands: Commands,
+    mut protected: Query<(Entity, &mut SpawnProtection)>,
+    fixed_time: Res<FixedTime>,
+) {
+    for (entity, mut protection) in protected.iter_mut() {
+        if protection.0.tick(fixed_time.period).finished() {
+            commands.entity(entity).remove::<SpawnProtection>();
+        }
+    }
+}
+
+pub(crate) fn blob_merger(
     mut commands: Commands,
     mut blobs: Query<(Entity, &mut Transform, &mut Blob)>,
-    time: Res<Time>,
+    protected: Query<(), With<SpawnProtection>>,
+    pellets: Query<&Pellet>,
+    players: Query<Option<&Recombining>, With<PlayerInput>>,
+    viruses: Query<(), With<Virus>>,
+    teams: Query<&Team>,
+    mut quadtree: ResMut<Quadtree>,
+    config: Res<MergeConfig>,
+    mut merge_events: EventWriter<BlobMergeEvent>,
+    mut despawns: ResMut<DespawnQueue>,
+    game_config: Res<GameConfig>,
+    game_time: Res<GameTime>,
+    fixed_time: Res<FixedTime>,
+    moved: Query<&SimulatedPosition>,
+    swept: Res<SweptAabbs>,
 ) {
-    let merge_factor = 0.75;
-    let gain_factor = 0.15;
+    // where a blob was at the start of this tick, the simulated position isn't recorded yet
+    let start = |entity: Entity, current: Vec2| {
+        moved
+            .get(entity)
+            .map_or(current, |position| position.current.xy())
+    };
 
-    let mut combinations = blobs.iter_combinations_mut();
-    while let Some([mut a, mut b]) = combinations.fetch_next() {
-        if a.1.translation.distance(b.1.translation) < (a.2.size + b.2.size) * merge_factor {
-            let (smaller, mut bigger) = if a.2.size > b.2.size { (b, a) } else { (a, b) };
-            commands.entity(smaller.0).despawn();
+    // collect the merges first, despawning while iterating could eat a blob twice
+    let mut merges = Vec::new();
+    let mut drains = Vec::new();
+    let mut separations = Vec::new();
+    let candidates: Vec<[Entity; 2]> = match config.broadphase {
+        Broadphase::AllPairs => blobs.iter_combinations().map(|[a, b]| [a.0, b.0]).collect(),
+        Broadphase::Quadtree => {
+            // far enough to catch both pushing apart and eating
+            let reach = config.merge_factor.max(config.eat_factor);
+            // padded to cover where fast blobs were during the tick
+            let radius = |entity: Entity, transform: &Transform, blob: &Blob| {
+                let position = transform.translation.xy();
+                blob.radius() * reach + swept.padding(start(entity, position), position)
+            };
+            *quadtree = Quadtree::build(blobs.iter().map(|(entity, transform, blob)| {
+                (
+                    entity,
+                    transform.translation.xy(),
+                    radius(entity, transform, blob),
+                )
+            }));
+            let mut candidates = Vec::new();
+            for (entity, transform, blob) in blobs.iter() {
+                let radius = radius(entity, transform, blob);
+                quadtree.query_range(transform.translation.xy(), radius, |other| {
+                    // each pair once
+                    if entity < other {
+                        candidates.push([entity, other]);
+                    }
+                });
+            }
+            candidates
+        }
+    };
+
+    for pair in candidates {
+        let Ok([a, b]) = blobs.get_many(pair) else {
+            continue;
+        };
+        let distance = a.1.translation.distance(b.1.translation);
+        let touching = distance < (a.2.radius() + b.2.radius()) * config.merge_factor;
+        // a fast blob can skip right over a smaller one between ticks, so eating goes by the
+        // closest they got during the tick
+        let (a_position, b_position) = (a.1.translation.xy(), b.1.translation.xy());
+        let eat_distance = swept.closest_approach(
+            start(a.0, a_position),
+            a_position,
+            start(b.0, b_position),
+            b_position,
+        );
+
+        // viruses neither eat nor get eaten, see viruses.rs
+        if viruses.contains(a.0) || viruses.contains(b.0) {
+            continue;
+        }
+
+        // the player's own pieces never eat each other, they recombine once split.rs says so
+        if let (Ok(a_recombining), Ok(b_recombining)) = (players.get(a.0), players.get(b.0)) {
+            if touching && (a_recombining.is_none() || b_recombining.is_none()) {
+                separations.push((a.0, b.0));
+            }
+            continue;
+        }
+
+        // teammates get in each other's way like blobs too close in size
+        if !Team::hostile(teams.get(a.0).ok(), teams.get(b.0).ok()) {
+            if touching {
+                separations.push((a.0, b.0));
+            }
+            continue;
+        }
+
+        // exact ties go to the lower entity id, not whichever came first in the iteration
+        let a_is_bigger = a.2.size > b.2.size || (a.2.size == b.2.size && a.0 < b.0);
+        let (smaller, bigger) = if a_is_bigger { (b, a) } else { (a, b) };
+        if bigger.2.size >= smaller.2.size * config.size_ratio {
+            // the smaller blob's center has to be inside the bigger one, the way it looks
+            if eat_distance >= bigger.2.radius() * config.eat_factor {
+                continue;
+            }
+            // pellets only get eaten
+            if pellets.contains(bigger.0) {
+                continue;
+            }
+            // sneaking up from behind is safe with frontal eating on
+            if !config.in_eat_cone(
+                bigger.2,
+                bigger.1.translation.xy(),
+                smaller.1.translation.xy(),
+            ) {
+                continue;
+            }
+            // still digesting, the smaller one has to wait
+            if config.digesting(bigger.2, game_time.elapsed) {
+                continue;
+            }
+            if protected.contains(smaller.0) {
+                continue;
+            }
+            let overlap = bigger.2.radius() + smaller.2.radius() - eat_distance;
+            let merge = MergeCandidate {
+                bigger: bigger.0,
+                smaller: smaller.0,
+                distance: eat_distance,
+                overlap,
+            };
+            if pellets.contains(smaller.0) {
+                merges.push(merge);
+            } else {
+                drains.push(merge);
+            }
+        } else if touching {
+            separations.push((a.0, b.0));
+        }
+    }
+
+    // blobs too close in size to eat each other get pushed apart instead
+    for (a, b) in separations {
+        if let Ok([(_, mut a_transform, a_blob), (_, mut b_transform, b_blob)]) =
+            blobs.get_many_mut([a, b])
+        {
+            let min_distance = (a_blob.radius() + b_blob.radius()) * config.merge_factor;
+            let offset = (b_transform.translation - a_transform.translation).truncate();
+            let push_direction = offset.try_normalize().unwrap_or(Vec2::X);
+            let push = push_direction * (min_distance - offset.length()) * 0.5;
+
+            a_transform.translation -= push.extend(0.0);
+            b_transform.translation += push.extend(0.0);
+        }
+    }
+
+    // overlapped blobs wear down and only get eaten once they run out of health. A blob wears
+    // down once a tick however many blobs overlap it, and then any of them may eat it.
+    let drain = config.health_drain * fixed_time.period.as_secs_f32();
+    let mut drained = HashMap::new();
+    for merge in drains {
+        let out_of_health = *drained.entry(merge.smaller).or_insert_with(|| {
+            blobs
+                .get_mut(merge.smaller)
+                .is_ok_and(|(_, _, mut smaller_blob)| {
+                    smaller_blob.health -= drain;
+                    smaller_blob.health <= 0.0
+                })
+        });
+        if out_of_health {
+            merges.push(merge);
+        }
+    }
+
+    // a blob overlapping several smaller ones eats the nearest, and with more merges than the
+    // limit allows the nearest pairs go first and the rest are found again next frame
+    merges.sort_by(MergeCandidate::priority);
+
+    // every blob takes part in at most one merge per frame
+    let mut merged = HashSet::new();
+    let mut merge_count = 0;
+    for MergeCandidate {
+        bigger, smaller, ..
+    } in merges
+    {
+        if merge_count >= config.max_merges_per_frame {
+            break;
+        }
+        if merged.contains(&bigger) || merged.contains(&smaller) {
+            continue;
+        }
+
+        if let Ok([(_, smaller_transform, smaller_blob), (_, _, mut bigger_blob)]) =
+            blobs.get_many_mut([smaller, bigger])
+        {
+            merged.insert(smaller);
+            merged.insert(bigger);
+            despawns.push(smaller);
+            if protected.contains(bigger) {
+                commands.entity(bigger).remove::<SpawnProtection>();
+            }
+
+            let grow_size = match pellets.get(smaller) {
+                Ok(pellet) => pellet.value * config.pellet_gain,
+                Err(_) => smaller_blob.size * config.gain_factor,
+            };
+            let (target_size, overflow) = game_config.grow(
+                bigger_blob.target_size.unwrap_or(bigger_blob.size),
+                grow_size,
+            );
+            bigger_blob.target_size = Some(target_size);
+            bigger_blob.last_ate = Some(game_time.elapsed);
+            bigger_blob.color = config.blend_color(&bigger_blob, &smaller_blob);
+
+            merge_events.send(BlobMergeEvent {
+                eater: bigger,
+                eaten: smaller,
+                eaten_size: smaller_blob.size,
+                eaten_color: smaller_blob.color,
+                position: smaller_transform.translation,
+                overflow,
+            });
+            merge_count += 1;
+        }
+    }
+}
+
+pub(crate) fn regenerate_health(
+    mut blobs: Query<&mut Blob>,
+    config: Res<MergeConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    let regen = config.health_regen * fixed_time.period.as_secs_f32();
+    for mut blob in blobs.iter_mut() {
+        // checked through Deref first so full health blobs don't get marked as changed
+        if blob.health < MAX_HEALTH {
+            blob.health = (blob.health + regen).min(MAX_HEALTH);
+        }
+    }
+}
+
+/// Eases blobs towards their `target_size`, so eating doesn't pop
+pub(crate) fn grow_blobs(
+    mut blobs: Query<&mut Blob>,
+    config: Res<MergeConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    let delta = fixed_time.period.as_secs_f32();
+    // 99.9% of the way there after grow_time
+    let t = 1.0 - 0.001f32.powf(delta / config.grow_time.max(f32::EPSILON));
+
+    for mut blob in blobs.iter_mut() {
+        let Some(target_size) = blob.target_size else {
+            continue;
+        };
+
+        if (target_size - blob.size).abs() < 0.001 {
+            blob.target_size = None;
+            blob.size = target_size;
+        } else {
+            blob.size += (target_size - blob.size) * t;
+        }
+    }
+}
+
+/// Keeps `Transform::scale` in line with [`Blob::scale`]
+pub(crate) fn sync_blob_scale(mut blobs: Query<(&mut Transform, &Blob), Changed<Blob>>) {
+    for (mut transform, blob) in blobs.iter_mut() {
+        let scale = blob.scale();
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suction::Suction;
+    use crate::test_support::{TestApp, FRAME_TIME};
+    use crate::Arena;
+    use bevy::asset::HandleId;
+    use bevy::ecs::system::CommandQueue;
+
+    #[test]
+    fn three_overlapping_blobs_merge_without_conflicts() {
+        let mut app = TestApp::new();
+        let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        let middle = app.spawn_blob(vec3(0.2, 0.0, 1.0), 0.6);
+        let small = app.spawn_blob(vec3(0.1, 0.15, 1.0), 0.3);
+
+        let mut eaten = Vec::new();
+        for _ in 0..600 {
+            app.step();
+            let merges = app.merges();
+            // nobody eats and gets eaten, or eats twice, in the same frame
+            let mut involved: Vec<Entity> = merges
+                .iter()
+                .flat_map(|&(eater, eaten)| [eater, eaten])
+                .collect();
+            involved.sort();
+            involved.dedup();
+            assert_eq!(involved.len(), merges.len() * 2);
+
+            for (eater, gone) in merges {
+                assert!(!eaten.contains(&eater), "{:?} ate after being eaten", eater);
+                assert!(!eaten.contains(&gone), "{:?} got eaten twice", gone);
+                eaten.push(gone);
+            }
+            if app.blob_count() == 1 {
+                break;
+            }
+        }
+
+        assert_eq!(app.blob_count(), 1);
+        assert!(app.blob(big).is_some());
+        eaten.sort();
+        let mut expected = vec![middle, small];
+        expected.sort();
+        assert_eq!(eaten, expected);
+    }
+
+    #[test]
+    fn blob_direction_reaches_the_gpu_struct() {
+        let blob = Blob {
+            direction: 1.25,
+            ..default()
+        };
+        let gpu_blob = BlobEntity::new(&blob, Vec2::new(2.0, -1.0));
+        assert_eq!(gpu_blob.direction, 1.25);
+        assert_eq!(gpu_blob.position, Vec2::new(2.0, -1.0));
+
+        let mut blob_data = BlobData::default();
+        assert_eq!(blob_data.push(gpu_blob), Some(0));
+        assert_eq!(blob_data.blobs[0].direction, 1.25);
+    }
+
+    #[test]
+    fn bigger_blob_eats_smaller_overlapping_one() {
+        let mut app = TestApp::new();
+        let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        let small = app.spawn_blob(vec3(0.2, 0.0, 1.0), 0.3);
+        app.step();
+        assert_eq!(app.blob_count(), 2, "spawn protection holds the merge off");
+
+        // protection runs out, then the small one's health drains
+        let config = app.world().resource::<MergeConfig>();
+        let seconds = config.spawn_protection + MAX_HEALTH / config.health_drain + 0.5;
+        app.step_frames((seconds / FRAME_TIME) as usize);
+
+        assert_eq!(app.blob_count(), 1);
+        assert!(app.blob(small).is_none());
+        assert!(app.blob(big).unwrap().size > 1.0);
+    }
+
+    #[test]
+    fn equal_blobs_dont_eat_each_other() {
+        let mut app = TestApp::new();
+        let a = app.spawn_blob(vec3(0.0, 0.0, 1.0), 0.5);
+        let b = app.spawn_blob(vec3(0.3, 0.0, 1.0), 0.5);
+        app.step_frames((3.0 / FRAME_TIME) as usize);
+        assert_eq!(app.blob_count(), 2);
+        assert_eq!(app.blob(a).unwrap().size, 0.5);
+        assert_eq!(app.blob(b).unwrap().size, 0.5);
+
+        // the same overlap with a big enough difference in size
+        let mut app = TestApp::new();
+        let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 0.5);
+        app.spawn_blob(vec3(0.3, 0.0, 1.0), 0.5 / 1.5);
+        app.step_frames((3.0 / FRAME_TIME) as usize);
+        assert_eq!(app.blob_count(), 1);
+        assert!(app.blob(big).is_some());
+    }
+
+    #[test]
+    fn raymarch_steps_fall_off_with_the_blob_count() {
+        let quality = RaymarchQuality {
+            min_steps: 24,
+            max_steps: 64,
+            full_quality_blobs: 16,
+            ..default()
+        };
+        assert_eq!(quality.steps_for_blob_count(0), 64);
+        assert_eq!(quality.steps_for_blob_count(16), 64);
+        // halfway between 16 blobs and the capacity
+        assert_eq!(
+            quality.steps_for_blob_count(16 + (BLOB_CAPACITY - 16) / 2),
+            44
+        );
+        assert_eq!(quality.steps_for_blob_count(BLOB_CAPACITY), 24);
+        assert_eq!(quality.steps_for_blob_count(BLOB_CAPACITY * 2), 24);
+
+        let counts = 0..=BLOB_CAPACITY;
+        let steps: Vec<u32> = counts
+            .map(|count| quality.steps_for_blob_count(count))
+            .collect();
+        assert!(steps.windows(2).all(|pair| pair[1] <= pair[0]));
+
+        // more steps than the shader loops for are cut down to its limit
+        let greedy = RaymarchQuality {
+            max_steps: MAX_RAYMARCH_STEPS * 2,
+            ..default()
+        };
+        assert_eq!(greedy.steps_for_blob_count(0), MAX_RAYMARCH_STEPS);
+    }
+
+    /// A world with just the blobs [`spawn_starting_blobs`] spawns for `layout`
+    fn spawn_layout(layout: &BlobLayout, game_config: &GameConfig, palette: &BlobPalette) -> World {
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        spawn_starting_blobs(
+            &mut commands,
+            &BlobMaterial(Handle::default()),
+            &BlobMesh(Handle::default()),
+            layout,
+            &BlobLimit::default(),
+            game_config,
+            palette,
+            &mut GameRng::default(),
+        );
+        queue.apply(&mut world);
+        world
+    }
+
+    #[test]
+    fn two_by_three_grid_spawns_six_blobs() {
+        let grid = DebugGridConfig {
+            cols: 2,
+            rows: 3,
+            spacing: 2.0,
+        };
+        let mut world = spawn_layout(&grid.layout(), &GameConfig::default(), &default());
+
+        let mut positions: Vec<Vec2> = world
+            .query_filtered::<&Transform, With<Blob>>()
+            .iter(&world)
+            .map(|transform| transform.translation.truncate())
+            .collect();
+        positions.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        assert_eq!(
+            positions,
+            [
+                Vec2::new(-2.0, -3.0),
+                Vec2::new(-2.0, -1.0),
+                Vec2::new(-2.0, 1.0),
+                Vec2::new(0.0, -3.0),
+                Vec2::new(0.0, -1.0),
+                Vec2::new(0.0, 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn player_tagged_in_the_layout_spawns_with_the_configured_size() {
+        let layout = BlobLayout::from_ron(
+            "(blobs: [
+                (position: (0.0, 0.0)),
+                (position: (3.0, 1.0), size: 0.8),
+                (position: (-2.0, 4.0), player: true),
+            ])",
+        )
+        .unwrap();
+        let game_config = GameConfig {
+            player_start_size: 0.9,
+            ai_start_size: 0.4,
+            ..default()
+        };
+        let mut world = spawn_layout(&layout, &game_config, &BlobPalette::default());
+
+        let mut players = world.query_filtered::<(&Blob, &Transform), With<PlayerInput>>();
+        let (player, transform) = players.single(&world);
+        assert_eq!(player.size, 0.9);
+        assert_eq!(transform.translation.truncate(), Vec2::new(-2.0, 4.0));
+
+        let mut sizes: Vec<f32> = world
+            .query_filtered::<&Blob, Without<PlayerInput>>()
+            .iter(&world)
+            .map(|blob| blob.size)
+            .collect();
+        sizes.sort_by(f32::total_cmp);
+        assert_eq!(sizes, [0.4, 0.8]);
+    }
+
+    #[test]
+    fn first_blob_is_the_player_without_a_tag() {
+        let layout = DebugGridConfig::default().layout();
+        assert!(layout.is_player(0));
+        assert!(!layout.is_player(1));
+
+        let untagged: BlobLayout =
+            ron::from_str("(blobs: [(position: (1.0, 0.0)), (position: (2.0, 0.0))])").unwrap();
+        assert!(untagged.is_player(0));
+        assert!(!untagged.is_player(1));
+    }
+
+    #[test]
+    fn grid_blobs_get_palette_colors_and_the_player_its_own() {
+        let mut palette = BlobPalette::default();
+        palette.colors.push(palette.player);
+        let grid = DebugGridConfig::default();
+        let mut world = spawn_layout(&grid.layout(), &GameConfig::default(), &palette);
+
+        let mut blobs = world.query::<(&Blob, Option<&PlayerInput>)>();
+        assert_eq!(blobs.iter(&world).count(), grid.cols * grid.rows);
+        for (blob, player) in blobs.iter(&world) {
+            if player.is_some() {
+                assert_eq!(blob.color, palette.player);
+            } else {
+                assert_ne!(blob.color, palette.player);
+                assert!(palette.colors.contains(&blob.color));
+            }
+        }
+    }
 
-            let grow_size = smaller.2.size * gain_factor;
-            bigger.2.size += grow_size;
-            bigger.1.scale += grow_size;
-            bigger.2.last_ate = time.elapsed_seconds_wrapped();
+    #[test]
+    fn area_and_size_round_trip() {
+        let mut blob = Blob::default();
+        for size in [0.05, 0.5, 1.0, 3.7] {
+            blob.size = size;
+            let area = blob.area();
+            blob.set_area(area);
+            assert!(
+                (blob.size - size).abs() < 1e-5,
+                "{} came back as {}",
+                size,
+                blob.size
+            );
+            assert_eq!(blob.radius(), blob.size);
         }
+
+        // two blobs' worth of area is sqrt(2) times the size
+        blob.size = 1.0;
+        blob.set_area(blob.area() * 2.0);
+        assert!((blob.size - std::f32::consts::SQRT_2).abs() < 1e-5);
+
+        blob.set_area(-1.0);
+        assert_eq!(blob.size, 0.0);
+    }
+
+    #[test]
+    fn blobs_that_never_ate_arent_digesting() {
+        let config = MergeConfig::default();
+        let mut blob = Blob::default();
+        // the start of a game or a restart, where GameTime is 0
+        assert!(!config.digesting(&blob, 0.0));
+
+        blob.last_ate = Some(1.0);
+        assert!(config.digesting(&blob, 1.0 + config.eat_cooldown * 0.5));
+        assert!(!config.digesting(&blob, 1.0 + config.eat_cooldown));
+    }
+
+    #[test]
+    fn fast_blob_eats_a_pellet_it_skips_over_only_when_swept() {
+        for broadphase in [Broadphase::AllPairs, Broadphase::Quadtree] {
+            for enabled in [false, true] {
+                let mut app = TestApp::new();
+                // nothing but the big blob's speed brings them together
+                app.world().resource_mut::<Suction>().enabled = false;
+                app.world().resource_mut::<MergeConfig>().broadphase = broadphase;
+                app.world().resource_mut::<SweptAabbs>().enabled = enabled;
+                let big = app.spawn_blob(vec3(-0.6, 0.0, 1.0), 0.3);
+                let pellet = app.spawn_blob(vec3(0.0, 0.0, 1.0), 0.05);
+                app.world().entity_mut(pellet).insert(Pellet { value: 1.0 });
+
+                let protection = app.world().resource::<MergeConfig>().spawn_protection;
+                app.step_frames((protection / FRAME_TIME) as usize + 10);
+                assert_eq!(app.blob_count(), 2);
+
+                // from 0.6 in front of the pellet to 0.6 past it in one tick, never touching
+                // it at either end
+                app.world()
+                    .entity_mut(big)
+                    .insert(Velocity(Vec2::new(1.2 / FRAME_TIME, 0.0)));
+                app.step();
+                let x = app.world().get::<SimulatedPosition>(big).unwrap().current.x;
+                assert!(x > 0.3, "moved to {}", x);
+
+                let expected = if enabled { 1 } else { 2 };
+                assert_eq!(
+                    app.blob_count(),
+                    expected,
+                    "{:?}, swept: {}",
+                    broadphase,
+                    enabled
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn eater_waits_out_the_cooldown_before_eating_again() {
+        let mut app = TestApp::new();
+        app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        app.spawn_blob(vec3(0.2, 0.0, 1.0), 0.3);
+        app.spawn_blob(vec3(-0.2, 0.0, 1.0), 0.3);
+
+        let mut frames = 0;
+        while app.blob_count() == 3 {
+            app.step();
+            frames += 1;
+            assert!(frames < 600, "the first merge never happened");
+        }
+        assert_eq!(
+            app.blob_count(),
+            2,
+            "both drained at once but only one gets eaten"
+        );
+
+        let cooldown = app.world().resource::<MergeConfig>().eat_cooldown;
+        let cooldown_frames = (cooldown / FRAME_TIME).ceil() as usize;
+        app.step_frames(cooldown_frames - 2);
+        assert_eq!(app.blob_count(), 2);
+
+        // a few frames of slack for the health it won back in the meantime
+        app.step_frames(6);
+        assert_eq!(app.blob_count(), 1);
+    }
+
+    #[test]
+    fn size_eases_towards_the_target() {
+        let mut app = TestApp::new();
+        let blob = app.spawn_blob(vec3(0.0, 0.0, 1.0), 0.5);
+        app.world().get_mut::<Blob>(blob).unwrap().target_size = Some(1.0);
+        // the first frame has no time passing, so no tick
+        app.step();
+
+        let mut previous = 0.5;
+        for _ in 0..5 {
+            app.step();
+            let size = app.blob(blob).unwrap().size;
+            assert!(size > previous && size < 1.0, "{} after {}", size, previous);
+            previous = size;
+        }
+
+        let grow_time = app.world().resource::<MergeConfig>().grow_time;
+        app.step_frames((grow_time / FRAME_TIME) as usize + 10);
+        let grown = app.blob(blob).unwrap();
+        assert_eq!(grown.size, 1.0);
+        assert_eq!(grown.target_size, None);
+    }
+
+    #[test]
+    fn scale_follows_size_through_a_merge() {
+        let mut app = TestApp::new();
+        let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        app.spawn_blob(vec3(0.2, 0.0, 1.0), 0.3);
+
+        let mut frames = 0;
+        loop {
+            app.step();
+            let scale = app.world().get::<Transform>(big).unwrap().scale;
+            let blob = app.blob(big).unwrap();
+            assert_eq!(scale, blob.scale(), "frame {}", frames);
+            let growing = blob.target_size.is_some();
+            if app.blob_count() == 1 && !growing {
+                break;
+            }
+            frames += 1;
+            assert!(frames < 600, "the merge never finished");
+        }
+        assert!(app.blob(big).unwrap().size > 1.0);
+    }
+
+    #[test]
+    fn stretch_grows_with_speed_up_to_the_cap() {
+        let squash_stretch = SquashStretch::default();
+        assert_eq!(squash_stretch.stretch(Vec2::ZERO), 1.0);
+
+        let slow = squash_stretch.stretch(Vec2::new(1.0, 0.0));
+        let fast = squash_stretch.stretch(Vec2::new(0.0, -5.0));
+        assert!(1.0 < slow && slow < fast, "{} {}", slow, fast);
+        assert_eq!(
+            squash_stretch.stretch(Vec2::splat(1000.0)),
+            squash_stretch.max_stretch
+        );
+
+        // a cap past the bounding box is cut down to fit
+        let too_much = SquashStretch {
+            max_stretch: 10.0,
+            ..default()
+        };
+        assert_eq!(too_much.stretch(Vec2::splat(1000.0)), SCALE_PER_SIZE);
+    }
+
+    #[test]
+    fn stretching_leaves_size_and_scale_alone() {
+        let mut app = TestApp::new();
+        let blob = app.spawn_blob(vec3(0.0, 0.0, 1.0), 0.5);
+        app.world()
+            .entity_mut(blob)
+            .insert(Velocity(Vec2::new(6.0, 0.0)));
+        let scale = app.blob(blob).unwrap().scale();
+        for _ in 0..10 {
+            app.step();
+            assert_eq!(app.blob(blob).unwrap().size, 0.5);
+            assert_eq!(app.world().get::<Transform>(blob).unwrap().scale, scale);
+        }
+    }
+
+    #[test]
+    fn protected_blob_cant_be_eaten_until_the_timer_runs_out() {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.spawn_protection = 1.0;
+            // drained in a single tick once it's fair game
+            config.health_drain = 1000.0;
+        }
+        app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        let small = app.spawn_blob(vec3(0.2, 0.0, 1.0), 0.3);
+
+        // the first frame has no tick
+        app.step_frames((1.0 / FRAME_TIME) as usize - 2);
+        assert!(app.blob(small).is_some());
+        assert!(app.world().get::<SpawnProtection>(small).is_some());
+
+        app.step_frames(5);
+        assert!(app.blob(small).is_none());
+    }
+
+    #[test]
+    fn buffer_order_survives_query_order_changes() {
+        let mut world = World::new();
+        let blobs: Vec<Entity> = (0..6).map(|_| world.spawn(Blob::default()).id()).collect();
+        let mut query = world.query::<(Entity, &Blob)>();
+        let before: Vec<Entity> = in_entity_order(query.iter(&world), |(e, _)| *e)
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+
+        // moving some blobs to another archetype changes the order the query visits them in
+        for &blob in &blobs[..3] {
+            world.entity_mut(blob).insert(Outlined);
+        }
+        let after: Vec<Entity> = in_entity_order(query.iter(&world), |(e, _)| *e)
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+        assert_ne!(
+            query.iter(&world).map(|(e, _)| e).collect::<Vec<_>>(),
+            blobs
+        );
+        assert_eq!(before, after);
+        assert_eq!(after, blobs);
+    }
+
+    #[test]
+    fn overlapped_blob_loses_health_before_it_dies() {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.spawn_protection = 0.0;
+            config.health_drain = 2.0;
+        }
+        app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        let small = app.spawn_blob(vec3(0.2, 0.0, 1.0), 0.3);
+
+        // half of the half a second it takes to drain
+        app.step_frames((0.25 / FRAME_TIME) as usize);
+        let health = app.blob(small).unwrap().health;
+        assert!(0.0 < health && health < MAX_HEALTH, "{}", health);
+
+        app.step_frames((0.5 / FRAME_TIME) as usize);
+        assert!(app.blob(small).is_none());
+    }
+
+    #[test]
+    fn eating_needs_the_center_inside_the_eater() {
+        for (distance, eaten) in [(1.05, false), (0.95, true)] {
+            let mut app = TestApp::new();
+            // it would pull the one just outside in
+            app.world().resource_mut::<Suction>().enabled = false;
+            app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+            let small = app.spawn_blob(vec3(distance, 0.0, 1.0), 0.3);
+            // past the spawn protection and the health drain
+            app.step_frames((3.0 / FRAME_TIME) as usize);
+            assert_eq!(app.blob(small).is_none(), eaten, "at {}", distance);
+        }
+    }
+
+    #[test]
+    fn frontal_eating_spares_blobs_behind_the_eater() {
+        // a fresh blob faces -y
+        for (y, eaten) in [(-0.5, true), (0.5, false)] {
+            let mut app = TestApp::new();
+            // it would pull the one behind right into the eater's center
+            app.world().resource_mut::<Suction>().enabled = false;
+            app.world().resource_mut::<MergeConfig>().frontal_eating = true;
+            app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+            let small = app.spawn_blob(vec3(0.0, y, 1.0), 0.3);
+            app.step_frames((3.0 / FRAME_TIME) as usize);
+            assert_eq!(app.blob(small).is_none(), eaten, "at {}", y);
+        }
+    }
+
+    #[test]
+    fn eating_blends_color_by_mass() {
+        let config = MergeConfig::default();
+        let blue = Blob {
+            size: 1.0,
+            color: Color::BLUE,
+            ..default()
+        };
+        let red = |size| Blob {
+            size,
+            color: Color::RED,
+            ..default()
+        };
+
+        let small_meal = config.blend_color(&blue, &red(0.2));
+        assert!(
+            small_meal.b() > 0.9 && small_meal.r() < 0.1,
+            "{:?}",
+            small_meal
+        );
+
+        // an equal meal shifts it a lot, but only as far as the cap
+        let big_meal = config.blend_color(&blue, &red(1.0));
+        assert!(
+            (big_meal.r() - config.max_color_blend).abs() < 1e-5,
+            "{:?}",
+            big_meal
+        );
+        assert!(big_meal.b() > big_meal.r());
+    }
+
+    #[test]
+    fn exact_ties_go_to_the_lower_entity() {
+        for reorder in [false, true] {
+            let mut app = TestApp::new();
+            {
+                let mut config = app.world().resource_mut::<MergeConfig>();
+                config.size_ratio = 1.0;
+                config.health_drain = 1000.0;
+            }
+            let first = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+            let second = app.spawn_blob(vec3(0.5, 0.0, 1.0), 1.0);
+            if reorder {
+                // another archetype, so the query visits it after `second`
+                app.world().entity_mut(first).insert(Outlined);
+            }
+
+            app.step_frames((3.0 / FRAME_TIME) as usize);
+            assert!(app.blob(first).is_some(), "reordered: {}", reorder);
+            assert!(app.blob(second).is_none(), "reordered: {}", reorder);
+        }
+    }
+
+    #[test]
+    fn capped_merges_carry_over_to_the_next_frame() {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.max_merges_per_frame = 3;
+            config.spawn_protection = 0.0;
+            config.health_drain = 1000.0;
+        }
+        // room for all of them
+        app.world().insert_resource(Arena::Circle { radius: 50.0 });
+        // eight pairs far apart, the first ones the closest
+        let eaten: Vec<Entity> = (0..8)
+            .map(|i| {
+                let x = i as f32 * 5.0;
+                app.spawn_blob(vec3(x, 0.0, 1.0), 1.0);
+                app.spawn_blob(vec3(x + 0.1 * (i + 1) as f32, 0.0, 1.0), 0.3)
+            })
+            .collect();
+
+        let mut per_frame = Vec::new();
+        for _ in 0..20 {
+            app.step();
+            let merges = app.merges();
+            if !merges.is_empty() {
+                per_frame.push(
+                    merges
+                        .into_iter()
+                        .map(|(_, eaten)| eaten)
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+        assert_eq!(
+            per_frame,
+            [
+                eaten[..3].to_vec(),
+                eaten[3..6].to_vec(),
+                eaten[6..].to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn nearest_merges_come_first() {
+        let merge = |bigger: u32, smaller: u32, distance: f32, overlap: f32| MergeCandidate {
+            bigger: Entity::from_raw(bigger),
+            smaller: Entity::from_raw(smaller),
+            distance,
+            overlap,
+        };
+        let mut merges = [
+            merge(0, 1, 0.5, 0.2),
+            merge(0, 2, 0.1, 0.2),
+            // same distance, deeper overlap first
+            merge(0, 3, 0.3, 0.1),
+            merge(0, 4, 0.3, 0.4),
+            // a full tie goes by entity
+            merge(5, 7, 0.3, 0.1),
+            merge(5, 6, 0.3, 0.1),
+        ];
+        merges.sort_by(MergeCandidate::priority);
+        let order: Vec<u32> = merges.iter().map(|merge| merge.smaller.index()).collect();
+        assert_eq!(order, [2, 4, 3, 6, 7, 1]);
+    }
+
+    #[test]
+    fn big_blob_eats_the_nearer_small_one_first() {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.spawn_protection = 0.0;
+            config.health_drain = 1000.0;
+        }
+        let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        // the far one spawns first so iteration order alone would pick it
+        let far = app.spawn_blob(vec3(0.7, 0.0, 1.0), 0.3);
+        let near = app.spawn_blob(vec3(-0.3, 0.0, 1.0), 0.3);
+
+        let mut eaten = Vec::new();
+        for _ in 0..20 {
+            app.step();
+            eaten.extend(app.merges());
+        }
+        assert_eq!(eaten, [(big, near), (big, far)]);
+    }
+
+    #[test]
+    fn blobs_at_the_size_cap_stop_growing() {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.spawn_protection = 0.0;
+            config.health_drain = 1000.0;
+        }
+        app.world().resource_mut::<GameConfig>().max_size = 1.0;
+        let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+        let small = app.spawn_blob(vec3(0.5, 0.0, 1.0), 0.3);
+
+        app.step_frames((3.0 / FRAME_TIME) as usize);
+        assert!(app.blob(small).is_none());
+        assert_eq!(app.blob(big).unwrap().size, 1.0);
+    }
+
+    /// Health of a small blob at the origin after a few frames overlapped by a big blob at each
+    /// of `eaters`
+    fn health_after_overlapping(eaters: &[Vec3]) -> f32 {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.spawn_protection = 0.0;
+            config.health_drain = 2.0;
+        }
+        let victim = app.spawn_blob(vec3(0.0, 0.0, 1.0), 0.3);
+        for &eater in eaters {
+            app.spawn_blob(eater, 1.0);
+        }
+        app.step_frames(4);
+        app.blob(victim).unwrap().health
+    }
+
+    #[test]
+    fn two_eaters_drain_a_blob_as_fast_as_one() {
+        let one = health_after_overlapping(&[vec3(-0.5, 0.0, 1.0)]);
+        let two = health_after_overlapping(&[vec3(-0.5, 0.0, 1.0), vec3(0.5, 0.0, 1.0)]);
+        assert!(one < MAX_HEALTH, "{}", one);
+        assert_eq!(one, two);
+    }
+
+    #[test]
+    fn teammates_dont_eat_each_other() {
+        for (small_team, eaten) in [(Team(1), false), (Team(2), true)] {
+            let mut app = TestApp::new();
+            let big = app.spawn_blob(vec3(0.0, 0.0, 1.0), 1.0);
+            let small = app.spawn_blob(vec3(0.5, 0.0, 1.0), 0.3);
+            app.world().entity_mut(big).insert(Team(1));
+            app.world().entity_mut(small).insert(small_team);
+
+            app.step_frames((3.0 / FRAME_TIME) as usize);
+            assert_eq!(app.blob(small).is_none(), eaten, "{:?}", small_team);
+        }
+    }
+
+    #[test]
+    fn bob_phase_is_stable_per_entity() {
+        let entities: Vec<Entity> = (0..50).map(Entity::from_raw).collect();
+        let phases: Vec<f32> = entities.iter().map(|&entity| bob_phase(entity)).collect();
+        assert_eq!(
+            phases,
+            entities
+                .iter()
+                .map(|&entity| bob_phase(entity))
+                .collect::<Vec<_>>()
+        );
+        assert!(phases
+            .iter()
+            .all(|phase| (0.0..std::f32::consts::TAU).contains(phase)));
+
+        // neighbouring entities don't bob in step
+        assert_ne!(phases[0], phases[1]);
+        // a reused index is a new blob
+        let reused = Entity::from_bits(1 << 32);
+        assert_eq!(reused.index(), entities[0].index());
+        assert_ne!(bob_phase(reused), phases[0]);
+    }
+
+    #[test]
+    fn buffer_indices_have_no_gaps_after_a_merge_storm() {
+        let mut app = TestApp::new();
+        {
+            let mut config = app.world().resource_mut::<MergeConfig>();
+            config.spawn_protection = 0.0;
+            config.health_drain = 1000.0;
+        }
+        app.world().insert_resource(Arena::Circle { radius: 50.0 });
+        for i in 0..6 {
+            let x = i as f32 * 5.0;
+            app.spawn_blob(vec3(x, 0.0, 1.0), 1.0);
+            app.spawn_blob(vec3(x + 0.3, 0.0, 1.0), 0.3);
+            app.spawn_blob(vec3(x, 2.5, 1.0), 0.5);
+        }
+        // an index left over from before the storm
+        let stale = app.spawn_blob(vec3(0.0, -2.5, 1.0), 0.5);
+        app.world().entity_mut(stale).insert(EntityBufferIndex(40));
+
+        // all six pairs merge within the first few ticks
+        app.step_frames(3);
+        assert_eq!(app.merges().len(), 6);
+        assert_eq!(app.blob_count(), 13);
+
+        fn assign(
+            mut commands: Commands,
+            mut blobs: Query<(Entity, Option<&mut EntityBufferIndex>), With<Blob>>,
+        ) {
+            let mut next = 0;
+            let blobs = blobs.iter_mut().map(|(e, index)| (e, index, ()));
+            assign_buffer_indices(&mut commands, blobs, |_, ()| {
+                next += 1;
+                Some(next - 1)
+            });
+        }
+        let mut schedule = Schedule::new();
+        schedule.add_system(assign);
+        schedule.run(app.world());
+
+        let mut indices: Vec<(i32, Entity)> = app
+            .world()
+            .query::<(Entity, &EntityBufferIndex)>()
+            .iter(app.world())
+            .map(|(e, index)| (index.0, e))
+            .collect();
+        indices.sort();
+        assert_eq!(
+            indices.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+            (0..13).collect::<Vec<_>>()
+        );
+        // handed out in entity order, and only to blobs still around
+        assert!(indices.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert!(indices.iter().all(|(_, e)| app.blob(*e).is_some()));
+    }
+
+    #[test]
+    fn render_path_moves_blobs_between_materials() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<VoxelMaterial>()
+            .add_asset::<VoxelPrepassMaterial>()
+            .init_resource::<RenderPath>()
+            .add_system(apply_render_path);
+        let forward = Handle::<VoxelMaterial>::weak(HandleId::random::<VoxelMaterial>());
+        let prepass =
+            Handle::<VoxelPrepassMaterial>::weak(HandleId::random::<VoxelPrepassMaterial>());
+        // made up front, creating it needs a GPU buffer to copy
+        app.insert_resource(BlobMaterial(forward.clone()))
+            .insert_resource(PrepassBlobMaterial(Some(prepass.clone())));
+        let blob = app.world.spawn((Blob::default(), forward.clone())).id();
+
+        let materials = |app: &App| {
+            let entity = app.world.entity(blob);
+            (
+                entity.get::<Handle<VoxelMaterial>>().cloned(),
+                entity.get::<Handle<VoxelPrepassMaterial>>().cloned(),
+            )
+        };
+        app.update();
+        assert_eq!(materials(&app), (Some(forward.clone()), None));
+
+        *app.world.resource_mut::<RenderPath>() = RenderPath::Prepass;
+        app.update();
+        assert_eq!(materials(&app), (None, Some(prepass)));
+
+        *app.world.resource_mut::<RenderPath>() = RenderPath::Forward;
+        app.update();
+        assert_eq!(materials(&app), (Some(forward), None));
     }
 }
@@ -0,0 +1,126 @@
+//! Arrow in front of the player blob showing which way it's facing
+use crate::camera;
+use crate::raymarching::Blob;
+use crate::simulation::SimulationSet;
+use crate::PlayerInput;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct ReticlePlugin;
+
+impl Plugin for ReticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AimReticle>()
+            // hidden while flying around freely
+            .add_system(
+                draw_aim_reticle
+                    .after(SimulationSet::Interpolate)
+                    .run_if(camera::following),
+            )
+            .add_system(aim_reticle_window);
+    }
+}
+
+#[derive(Resource)]
+pub struct AimReticle {
+    pub enabled: bool,
+    /// Length of the arrow, starting at the blob's edge
+    pub length: f32,
+    /// Length of each side of the arrowhead
+    pub head_size: f32,
+    pub color: Color,
+}
+
+impl Default for AimReticle {
+    fn default() -> Self {
+        AimReticle {
+            enabled: true,
+            length: 0.6,
+            head_size: 0.15,
+            color: Color::rgba(1.0, 1.0, 1.0, 0.6),
+        }
+    }
+}
+
+impl AimReticle {
+    /// Start and end of the arrow for a blob at `position`, from its edge outwards along its
+    /// direction
+    pub fn arrow(&self, position: Vec3, blob: &Blob) -> (Vec3, Vec3) {
+        let forward = blob.forward().extend(0.0);
+        let start = position + forward * blob.radius();
+        (start, start + forward * self.length)
+    }
+}
+
+fn draw_aim_reticle(
+    players: Query<(&Transform, &Blob), With<PlayerInput>>,
+    config: Res<AimReticle>,
+) {
+    if !config.enabled {
+        return;
+    }
+
+    for (transform, blob) in players.iter() {
+        let (start, end) = config.arrow(transform.translation, blob);
+        bevy_mod_gizmos::draw_line(vec![start, end], config.color);
+
+        // arrowhead, angled back from the tip
+        let back = blob.forward().extend(0.0) * -config.head_size;
+        for side in [-1.0, 1.0] {
+            let wing = Quat::from_rotation_z(side * std::f32::consts::FRAC_PI_4) * back;
+            bevy_mod_gizmos::draw_line(vec![end, end + wing], config.color);
+        }
+    }
+}
+
+fn aim_reticle_window(mut config: ResMut<AimReticle>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("Aim arrow").show(egui_contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut config.enabled, "Enabled");
+        ui.add(egui::Slider::new(&mut config.length, 0.1..=3.0).text("Length"));
+
+        let mut color = config.color.as_rgba_f32();
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.color_edit_button_rgba_unmultiplied(&mut color);
+        });
+        // only touch the resource on actual edits
+        if color != config.color.as_rgba_f32() {
+            let [r, g, b, a] = color;
+            config.color = Color::rgba(r, g, b, a);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrow_runs_from_the_edge_along_the_direction() {
+        let reticle = AimReticle::default();
+        let position = Vec3::new(1.0, 2.0, 1.0);
+        let mut blob = Blob {
+            size: 1.0,
+            ..default()
+        };
+
+        // facing -y
+        let (start, end) = reticle.arrow(position, &blob);
+        assert!(
+            start.abs_diff_eq(Vec3::new(1.0, 1.0, 1.0), 1e-5),
+            "{}",
+            start
+        );
+        assert!(end.abs_diff_eq(Vec3::new(1.0, 0.4, 1.0), 1e-5), "{}", end);
+
+        // a quarter turn counterclockwise faces +x
+        blob.direction = std::f32::consts::FRAC_PI_2;
+        let (start, end) = reticle.arrow(position, &blob);
+        assert!(
+            start.abs_diff_eq(Vec3::new(2.0, 2.0, 1.0), 1e-5),
+            "{}",
+            start
+        );
+        assert!(end.abs_diff_eq(Vec3::new(2.6, 2.0, 1.0), 1e-5), "{}", end);
+    }
+}
@@ -0,0 +1,110 @@
+//! Seedable random numbers for gameplay, so runs can be replayed from a seed
+use bevy::prelude::*;
+
+/// xorshift64*, plenty for spawning things and not worth a dependency
+#[derive(Resource, Clone, Debug)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(0x5eed_b10b)
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        // the state must never be zero
+        GameRng { state: seed.max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `0.0..1.0`
+    pub fn next_f32(&mut self) -> f32 {
+        // top 24 bits, all an f32 mantissa can hold
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `min..max`
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Index into `weights`, picked with probability proportional to its weight. None if there
+    /// is nothing with a positive weight to pick.
+    pub fn weighted_index(
+        &mut self,
+        weights: impl IntoIterator<Item = f32> + Clone,
+    ) -> Option<usize> {
+        let total: f32 = weights.clone().into_iter().map(|w| w.max(0.0)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut pick = self.next_f32() * total;
+        let mut last = None;
+        for (index, weight) in weights.into_iter().enumerate() {
+            if weight <= 0.0 {
+                continue;
+            }
+            if pick < weight {
+                return Some(index);
+            }
+            pick -= weight;
+            last = Some(index);
+        }
+
+        // rounding can leave a sliver past the last weight
+        last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_index_follows_the_weights() {
+        let mut rng = GameRng::new(42);
+        let weights = [1.0, 0.0, 3.0, -2.0, 6.0];
+        let mut counts = [0; 5];
+        let picks = 100_000;
+        for _ in 0..picks {
+            counts[rng.weighted_index(weights).unwrap()] += 1;
+        }
+
+        // nothing for zero or negative weights
+        assert_eq!(counts[1], 0);
+        assert_eq!(counts[3], 0);
+        for (index, expected) in [(0, 0.1), (2, 0.3), (4, 0.6)] {
+            let share = counts[index] as f32 / picks as f32;
+            assert!((share - expected).abs() < 0.01, "{} got {}", index, share);
+        }
+    }
+
+    #[test]
+    fn weighted_index_needs_a_positive_weight() {
+        let mut rng = GameRng::default();
+        assert_eq!(rng.weighted_index([]), None);
+        assert_eq!(rng.weighted_index([0.0, -1.0]), None);
+        assert_eq!(rng.weighted_index([0.0, 2.0, 0.0]), Some(1));
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut a = GameRng::new(7);
+        let mut b = GameRng::new(7);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        // a zero seed would get stuck at zero forever
+        assert_ne!(GameRng::new(0).next_u64(), 0);
+    }
+}
@@ -0,0 +1,80 @@
+//! Storage buffers rewritten every frame, spread over a few GPU buffers
+use bevy::prelude::*;
+use bevy::render::render_resource::encase::internal::WriteInto;
+use bevy::render::render_resource::{Buffer, ShaderType, StorageBuffer};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+
+/// How many buffers a [`RotatingBuffer`] goes through by default, enough for the GPU to be a
+/// couple of frames behind
+pub const ROTATING_BUFFER_COUNT: usize = 3;
+
+/// GPU copies of `T`. Every frame writes the next one in turn and the material binds that, so a
+/// frame never overwrites the buffer the GPU might still be reading for an earlier one.
+#[derive(Resource)]
+pub struct RotatingBuffer<T: ShaderType + WriteInto + Default + Send + Sync + 'static> {
+    buffers: Vec<StorageBuffer<T>>,
+    current: usize,
+}
+
+impl<T: ShaderType + WriteInto + Default + Send + Sync + 'static> Default for RotatingBuffer<T> {
+    fn default() -> Self {
+        RotatingBuffer::new(ROTATING_BUFFER_COUNT)
+    }
+}
+
+impl<T: ShaderType + WriteInto + Default + Send + Sync + 'static> RotatingBuffer<T> {
+    /// At least one buffer, however small `count` is
+    pub fn new(count: usize) -> Self {
+        RotatingBuffer {
+            buffers: (0..count.max(1))
+                .map(|_| StorageBuffer::default())
+                .collect(),
+            current: 0,
+        }
+    }
+
+    /// Moves on to the next buffer, wrapping around, and returns its index
+    pub fn rotate(&mut self) -> usize {
+        self.current = (self.current + 1) % self.buffers.len();
+        self.current
+    }
+
+    /// The buffer written last, the one the material should bind
+    pub fn current(&self) -> &StorageBuffer<T> {
+        &self.buffers[self.current]
+    }
+
+    /// Rotates to the next buffer, lets `write` fill in its value and uploads it. Returns the
+    /// GPU buffer to bind.
+    pub fn write_next(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        write: impl FnOnce(&mut T),
+    ) -> &Buffer {
+        self.rotate();
+        let buffer = &mut self.buffers[self.current];
+        write(buffer.get_mut());
+        buffer.write_buffer(render_device, render_queue);
+        buffer.buffer().expect("written just now")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_cycles_through_every_buffer() {
+        let mut buffer = RotatingBuffer::<f32>::new(3);
+        let indices: Vec<usize> = (0..7).map(|_| buffer.rotate()).collect();
+        assert_eq!(indices, [1, 2, 0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn zero_buffers_still_gets_one() {
+        let mut buffer = RotatingBuffer::<f32>::new(0);
+        assert_eq!(buffer.rotate(), 0);
+        assert_eq!(buffer.rotate(), 0);
+    }
+}
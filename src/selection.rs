@@ -0,0 +1,98 @@
+//! The selected blob and its outline
+use bevy::prelude::*;
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedBlob>()
+            .init_resource::<OutlineConfig>()
+            .add_system(update_outline);
+    }
+}
+
+/// The blob picked by the player, if any
+#[derive(Resource, Default, Debug, PartialEq)]
+pub struct SelectedBlob(pub Option<Entity>);
+
+/// Drawn with an outline by the blob shader. Only the first one in the blob buffer gets it, keep
+/// it on a single entity.
+#[derive(Component)]
+pub struct Outlined;
+
+#[derive(Resource)]
+pub struct OutlineConfig {
+    pub color: Color,
+}
+
+impl Default for OutlineConfig {
+    fn default() -> Self {
+        OutlineConfig {
+            color: Color::rgb(1.0, 1.0, 0.6),
+        }
+    }
+}
+
+/// Moves [`Outlined`] to whatever [`SelectedBlob`] points at
+pub(crate) fn update_outline(
+    mut commands: Commands,
+    selected: Res<SelectedBlob>,
+    outlined: Query<Entity, With<Outlined>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+
+    for entity in outlined.iter() {
+        if Some(entity) != selected.0 {
+            commands.entity(entity).remove::<Outlined>();
+        }
+    }
+
+    if let Some(entity) = selected.0 {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.insert(Outlined);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outlined(app: &mut App) -> Vec<Entity> {
+        app.world
+            .query_filtered::<Entity, With<Outlined>>()
+            .iter(&app.world)
+            .collect()
+    }
+
+    #[test]
+    fn only_the_selected_blob_is_outlined() {
+        let mut app = App::new();
+        app.init_resource::<SelectedBlob>()
+            .add_system(update_outline);
+        let a = app.world.spawn_empty().id();
+        let b = app.world.spawn_empty().id();
+        app.update();
+        assert!(outlined(&mut app).is_empty());
+
+        app.world.resource_mut::<SelectedBlob>().0 = Some(a);
+        app.update();
+        assert_eq!(outlined(&mut app), [a]);
+
+        app.world.resource_mut::<SelectedBlob>().0 = Some(b);
+        app.update();
+        assert_eq!(outlined(&mut app), [b]);
+
+        app.world.resource_mut::<SelectedBlob>().0 = None;
+        app.update();
+        assert!(outlined(&mut app).is_empty());
+
+        // selecting a blob that's gone by now doesn't panic
+        app.world.despawn(b);
+        app.world.resource_mut::<SelectedBlob>().0 = Some(b);
+        app.update();
+        assert!(outlined(&mut app).is_empty());
+    }
+}
@@ -0,0 +1,465 @@
+//! Fixed timestep for the gameplay simulation
+//!
+//! Gameplay systems run in [`CoreSchedule::FixedUpdate`], and see the blob `Transform`s as they
+//! were at the end of the previous tick. In between ticks the rendered `Transform` is
+//! interpolated, so movement stays smooth at any frame rate.
+use crate::ai::{self, AiBlob, AiDifficulty};
+use crate::arena_shrink::{self, ArenaShrink};
+use crate::bvh::SweptAabbs;
+use crate::game::{BlobPalette, GameConfig, GameTime};
+use crate::pellets::{self, PelletConfig, PelletSpawner};
+use crate::progress::{self, LevelUpEvent, PlayerProgress, ProgressConfig};
+use crate::quadtree::Quadtree;
+use crate::raymarching::{
+    self, Blob, BlobLimit, BlobMaterial, BlobMergeEvent, BlobMesh, DebugGridConfig, DespawnQueue,
+    MergeConfig, StartingLayout, LAYOUT_PATH,
+};
+use crate::rng::GameRng;
+use crate::split::{self, SplitBuffer, SplitConfig};
+use crate::suction::{self, Suction};
+use crate::viruses::{self, VirusConfig};
+use crate::{Arena, ArenaBounds, CursorWorldPosition, InputConfig, MovementConfig};
+use bevy::ecs::schedule::ExecutorKind;
+use bevy::prelude::*;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Simulation ticks per second
+pub const TICK_RATE: f32 = 60.0;
+
+/// [`TimeScale`] while slow motion is toggled on
+pub const SLOW_MOTION_SCALE: f32 = 0.25;
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<FixedTime>() {
+            app.insert_resource(FixedTime::new_from_secs(1.0 / TICK_RATE));
+        }
+
+        app.init_resource::<TimeScale>()
+            .add_system(toggle_slow_motion)
+            .add_system(apply_time_scale.after(toggle_slow_motion))
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                schedule.configure_sets(
+                    (
+                        SimulationSet::Restore,
+                        SimulationSet::Gameplay,
+                        SimulationSet::Record,
+                    )
+                        .chain(),
+                );
+            })
+            .add_system(
+                restore_simulated_position
+                    .in_schedule(CoreSchedule::FixedUpdate)
+                    .in_set(SimulationSet::Restore),
+            )
+            .add_system(
+                record_simulated_position
+                    .in_schedule(CoreSchedule::FixedUpdate)
+                    .in_set(SimulationSet::Record),
+            )
+            .add_system(interpolate_transforms.in_set(SimulationSet::Interpolate));
+    }
+}
+
+/// Adds every gameplay system to `FixedUpdate`, in the order the game runs them. The game,
+/// [`TestApp`](crate::test_support::TestApp) and [`Simulation`] all go through here, so tests and
+/// balancing runs play the same game. The resources the systems need come from the plugins, see
+/// [`Simulation::init_world`].
+pub fn add_gameplay_systems(app: &mut App) {
+    app.add_event::<BlobMergeEvent>()
+        .add_event::<LevelUpEvent>()
+        .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+            add_gameplay_systems_to(schedule);
+        });
+}
+
+/// [`add_gameplay_systems`] for a bare `Schedule`
+pub fn add_gameplay_systems_to(schedule: &mut Schedule) {
+    schedule
+        // blobs spawned since the last tick are protected before anything can eat them
+        .add_systems(
+            (raymarching::protect_new_blobs, apply_system_buffers)
+                .chain()
+                .before(SimulationSet::Gameplay),
+        )
+        // movement
+        .add_systems(
+            (
+                crate::handle_player_input,
+                ai::ai_steering,
+                raymarching::apply_velocity,
+                suction::apply_suction,
+                arena_shrink::shrink_arena,
+            )
+                .in_set(SimulationSet::Gameplay)
+                .before(crate::apply_arena_bounds),
+        )
+        .add_systems(
+            (
+                raymarching::tick_spawn_protection,
+                raymarching::regenerate_health,
+                crate::apply_arena_bounds,
+                viruses::spawn_viruses,
+            )
+                .in_set(SimulationSet::Gameplay)
+                .before(raymarching::blob_merger),
+        )
+        .add_system(raymarching::blob_merger.in_set(SimulationSet::Gameplay))
+        .add_systems(
+            (
+                raymarching::grow_blobs,
+                split::recombine_player_pieces,
+                viruses::virus_collisions,
+                pellets::spawn_pellets,
+            )
+                .in_set(SimulationSet::Gameplay)
+                .after(raymarching::blob_merger),
+        )
+        .add_systems(
+            (raymarching::sync_blob_scale, progress::track_progress)
+                .in_set(SimulationSet::Gameplay)
+                .after(raymarching::grow_blobs),
+        )
+        .add_system(
+            raymarching::apply_despawns
+                .after(SimulationSet::Gameplay)
+                .before(SimulationSet::Record),
+        );
+}
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum SimulationSet {
+    /// Puts the simulated positions back before gameplay runs
+    Restore,
+    /// Gameplay systems go here
+    Gameplay,
+    /// Stores the results of the tick
+    Record,
+    /// Runs in `Update`, anything reading blob positions for visuals should run after this
+    Interpolate,
+}
+
+/// How fast game time runs compared to real time, 0.5 is half speed. This becomes `Time`'s
+/// relative speed, so everything going by `Time::delta` slows down with it: the fixed timestep
+/// accumulates less and ticks less often, and `GameTime`, trails and particles slow too. Camera
+/// controls and frame timing go by `Time::raw_delta` and stay real-time.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct TimeScale(pub f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale(1.0)
+    }
+}
+
+/// T toggles between normal speed and [`SLOW_MOTION_SCALE`]
+fn toggle_slow_motion(keys: Res<Input<KeyCode>>, mut time_scale: ResMut<TimeScale>) {
+    if keys.just_pressed(KeyCode::T) {
+        time_scale.0 = if time_scale.0 == 1.0 {
+            SLOW_MOTION_SCALE
+        } else {
+            1.0
+        };
+    }
+}
+
+fn apply_time_scale(time_scale: Res<TimeScale>, mut time: ResMut<Time>) {
+    if time_scale.is_changed() {
+        time.set_relative_speed(time_scale.0.max(0.0));
+    }
+}
+
+/// Position at the last two simulation ticks, the rendered `Transform` is interpolated between
+/// these.
+#[derive(Component)]
+pub struct SimulatedPosition {
+    pub previous: Vec3,
+    pub current: Vec3,
+}
+
+fn restore_simulated_position(mut query: Query<(&mut Transform, &SimulatedPosition)>) {
+    for (mut transform, position) in query.iter_mut() {
+        transform.translation = position.current;
+    }
+}
+
+fn record_simulated_position(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Transform, Option<&mut SimulatedPosition>), With<Blob>>,
+) {
+    for (entity, transform, position) in query.iter_mut() {
+        if let Some(mut position) = position {
+            position.previous = position.current;
+            position.current = transform.translation;
+        } else {
+            commands.entity(entity).insert(SimulatedPosition {
+                previous: transform.translation,
+                current: transform.translation,
+            });
+        }
+    }
+}
+
+fn interpolate_transforms(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &SimulatedPosition)>,
+) {
+    let alpha = interpolation_alpha(&fixed_time);
+
+    for (mut transform, position) in query.iter_mut() {
+        transform.translation = position.previous.lerp(position.current, alpha);
+    }
+}
+
+/// How far we are from the last tick towards the next one, from 0 to 1
+pub fn interpolation_alpha(fixed_time: &FixedTime) -> f32 {
+    (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).clamp(0.0, 1.0)
+}
+
+/// Runs the gameplay systems from [`add_gameplay_systems`] straight against a `World`, without an
+/// `App`, plugins or a GPU. Meant for balancing runs and tests that need thousands of ticks,
+/// there's no BVH, rendering or pausing, and nobody at the keyboard. See [`run_balancing`].
+pub struct Simulation {
+    schedule: Schedule,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new()
+    }
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        let mut schedule = Schedule::new();
+        // deterministic order, and no task pools to set up
+        schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        add_gameplay_systems_to(&mut schedule);
+
+        Simulation { schedule }
+    }
+
+    /// Inserts the resources the gameplay systems need, leaving any `world` already has alone.
+    /// Spawned blobs get default mesh and material handles, there's nothing to draw them with.
+    pub fn init_world(world: &mut World) {
+        world.init_resource::<MergeConfig>();
+        world.init_resource::<SweptAabbs>();
+        world.init_resource::<Quadtree>();
+        world.init_resource::<GameTime>();
+        world.init_resource::<GameConfig>();
+        world.init_resource::<DespawnQueue>();
+        world.init_resource::<AiDifficulty>();
+        world.init_resource::<Suction>();
+        world.init_resource::<Arena>();
+        world.init_resource::<ArenaBounds>();
+        world.init_resource::<ArenaShrink>();
+        world.init_resource::<Input<KeyCode>>();
+        world.init_resource::<InputConfig>();
+        world.init_resource::<MovementConfig>();
+        world.init_resource::<CursorWorldPosition>();
+        world.init_resource::<PlayerProgress>();
+        world.init_resource::<ProgressConfig>();
+        world.init_resource::<SplitConfig>();
+        world.init_resource::<SplitBuffer>();
+        world.init_resource::<VirusConfig>();
+        world.init_resource::<PelletConfig>();
+        world.init_resource::<PelletSpawner>();
+        world.init_resource::<BlobPalette>();
+        world.init_resource::<BlobLimit>();
+        world.init_resource::<GameRng>();
+        if !world.contains_resource::<BlobMaterial>() {
+            world.insert_resource(BlobMaterial(Handle::default()));
+        }
+        if !world.contains_resource::<BlobMesh>() {
+            world.insert_resource(BlobMesh(Handle::default()));
+        }
+        world.init_resource::<Events<BlobMergeEvent>>();
+        world.init_resource::<Events<LevelUpEvent>>();
+    }
+
+    /// Advances `world` by one tick of `dt` seconds
+    pub fn step(&mut self, world: &mut World, dt: f32) {
+        Simulation::init_world(world);
+        world.insert_resource(FixedTime::new_from_secs(dt));
+        world.resource_mut::<GameTime>().elapsed += dt;
+        // events live for two ticks, same as with an App
+        world.resource_mut::<Events<BlobMergeEvent>>().update();
+        world.resource_mut::<Events<LevelUpEvent>>().update();
+
+        self.schedule.run(world);
+    }
+
+    /// Spawns an AI controlled blob, without the mesh and material a rendered one has
+    pub fn spawn_blob(world: &mut World, position: Vec3, size: f32) -> Entity {
+        let blob = Blob { size, ..default() };
+        world
+            .spawn((
+                Transform::from_translation(position).with_scale(blob.scale()),
+                blob,
+                AiBlob::default(),
+            ))
+            .id()
+    }
+
+    pub fn step_ticks(&mut self, world: &mut World, dt: f32, ticks: usize) {
+        for _ in 0..ticks {
+            self.step(world, dt);
+        }
+    }
+}
+
+/// Plays the starting layout headless for `ticks` ticks with every blob AI controlled, writing how
+/// many blobs are left to `out` once per second of game time. `cargo run -- --simulate 3600`
+pub fn run_balancing(ticks: usize, out: &mut impl Write) -> io::Result<()> {
+    let mut world = World::new();
+    let layout = StartingLayout::load(Path::new(LAYOUT_PATH)).resolve(&DebugGridConfig::default());
+    let game_config = GameConfig::default();
+    for blob in layout.blobs {
+        let size = blob.size.unwrap_or(game_config.ai_start_size);
+        Simulation::spawn_blob(&mut world, blob.position.extend(1.0), size);
+    }
+    world.insert_resource(game_config);
+
+    let dt = 1.0 / TICK_RATE;
+    let mut simulation = Simulation::new();
+    let mut done = 0;
+    while done < ticks {
+        let batch = (ticks - done).min(TICK_RATE as usize);
+        simulation.step_ticks(&mut world, dt, batch);
+        done += batch;
+
+        let blobs = world.query::<&Blob>().iter(&world).count();
+        writeln!(out, "tick {}: {} blobs", done, blobs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pellets::Pellet;
+    use crate::raymarching::Velocity;
+    use crate::test_support::TestApp;
+    use crate::viruses::Virus;
+
+    #[test]
+    fn blob_count_converges_over_2000_ticks() {
+        let mut world = World::new();
+        // only the blobs placed here, nothing spawned along the way
+        world.insert_resource(VirusConfig {
+            count: 0,
+            ..default()
+        });
+        world.insert_resource(PelletConfig {
+            max_pellets: 0,
+            ..default()
+        });
+        for index in 0..12 {
+            let angle = std::f32::consts::TAU * index as f32 / 12.0;
+            let size = [0.3, 0.6, 1.0][index % 3];
+            let position = (Vec2::from_angle(angle) * 3.0).extend(1.0);
+            Simulation::spawn_blob(&mut world, position, size);
+        }
+
+        let mut simulation = Simulation::new();
+        let mut counts = Vec::new();
+        for _ in 0..2000 {
+            simulation.step(&mut world, 1.0 / TICK_RATE);
+            counts.push(world.query::<&Blob>().iter(&world).count());
+        }
+
+        assert!(counts.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(counts[1999] < 12);
+        // nothing left small enough to eat for the last few hundred ticks
+        assert!(counts[1500..].iter().all(|&count| count == counts[1999]));
+    }
+
+    #[test]
+    fn balancing_runs_get_pellets_and_viruses_too() {
+        let mut world = World::new();
+        Simulation::new().step_ticks(&mut world, 1.0 / TICK_RATE, TICK_RATE as usize);
+        let pellets = world.query::<&Pellet>().iter(&world).count();
+        let viruses = world.query::<&Virus>().iter(&world).count();
+        assert!(pellets > 0);
+        assert_eq!(viruses, VirusConfig::default().count);
+
+        let mut out = Vec::new();
+        run_balancing(2 * TICK_RATE as usize, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 2);
+    }
+
+    /// Plays two blobs running into each other with frames `frame_ticks` simulation ticks long,
+    /// returns where the bigger one ended up, its size and how many blobs are left
+    fn play(frame_ticks: &[u32]) -> (Vec3, f32, usize) {
+        let mut app = TestApp::new();
+        let period = app.world().resource::<FixedTime>().period;
+        let big = app.spawn_blob(Vec3::new(-0.6, 0.0, 1.0), 0.6);
+        let small = app.spawn_blob(Vec3::new(0.6, 0.1, 1.0), 0.2);
+        app.world()
+            .entity_mut(big)
+            .insert(Velocity(Vec2::new(3.0, 0.5)));
+        app.world()
+            .entity_mut(small)
+            .insert(Velocity(Vec2::new(-1.0, 0.0)));
+
+        for &ticks in frame_ticks {
+            app.step_by(period * ticks);
+        }
+        let position = app.world().get::<SimulatedPosition>(big).unwrap().current;
+        let size = app.blob(big).unwrap().size;
+        (position, size, app.blob_count())
+    }
+
+    #[test]
+    fn render_rate_doesnt_change_the_outcome() {
+        let ticks = 180;
+        let at_60_fps = play(&vec![1; ticks]);
+        let at_20_fps = play(&vec![3; ticks / 3]);
+        // uneven frames, still 180 ticks in all
+        let uneven: Vec<u32> = [1, 2, 3, 2, 1, 3].repeat(ticks / 12);
+        assert_eq!(uneven.iter().sum::<u32>(), ticks as u32);
+        let stuttering = play(&uneven);
+
+        assert_eq!(
+            at_60_fps.2, 1,
+            "the big blob should have eaten the small one"
+        );
+        assert_eq!(at_60_fps, at_20_fps);
+        assert_eq!(at_60_fps, stuttering);
+    }
+
+    /// How far a blob moving at 1 unit a second gets in 120 frames at `time_scale`
+    fn distance_travelled(time_scale: f32) -> f32 {
+        let mut app = TestApp::new();
+        app.world().insert_resource(TimeScale(time_scale));
+        let blob = app.spawn_blob(Vec3::new(0.0, 0.0, 1.0), 0.5);
+        for _ in 0..120 {
+            // topped back up every frame, so damping doesn't get a say
+            app.world().entity_mut(blob).insert(Velocity(Vec2::X));
+            app.step();
+        }
+        app.world()
+            .get::<SimulatedPosition>(blob)
+            .unwrap()
+            .current
+            .x
+    }
+
+    #[test]
+    fn half_speed_travels_half_as_far() {
+        let full = distance_travelled(1.0);
+        let half = distance_travelled(0.5);
+        assert!(full > 1.5, "{}", full);
+        // give or take a tick
+        assert!(
+            (half - full / 2.0).abs() <= 2.0 / TICK_RATE,
+            "{} vs {}",
+            half,
+            full
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Sound effects
+use crate::loading::{CriticalAsset, CriticalAssets};
+use crate::raymarching::BlobMergeEvent;
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EatSoundConfig>()
+            .add_startup_system(load_sounds)
+            .add_system(play_eat_sounds);
+    }
+}
+
+/// Where [`EatSound`] is loaded from
+pub const EAT_SOUND_PATH: &str = "sounds/eat.ogg";
+
+/// Played on every [`BlobMergeEvent`]. Removed if the file fails to load, the game is silent
+/// then.
+#[derive(Resource)]
+pub struct EatSound(pub Handle<AudioSource>);
+
+#[derive(Resource)]
+pub struct EatSoundConfig {
+    /// Volume for eating a blob of `reference_size` right next to the camera
+    pub volume: f32,
+    /// Smaller meals are quieter, bigger ones don't get any louder
+    pub reference_size: f32,
+    /// Merges this far from the camera can't be heard
+    pub falloff_distance: f32,
+    /// Pans sounds towards the side of the screen they happen on
+    pub spatial: bool,
+    /// Sounds playing at once, merges past this during a chain stay quiet
+    pub max_concurrent: usize,
+    /// How long a sound counts towards `max_concurrent`, about the length of the sound
+    pub sound_duration: f32,
+}
+
+impl Default for EatSoundConfig {
+    fn default() -> Self {
+        EatSoundConfig {
+            volume: 0.6,
+            reference_size: 0.5,
+            falloff_distance: 30.0,
+            spatial: true,
+            max_concurrent: 4,
+            sound_duration: 0.3,
+        }
+    }
+}
+
+impl EatSoundConfig {
+    /// Volume for eating a blob of `eaten_size` at `distance` from the camera
+    pub fn volume(&self, eaten_size: f32, distance: f32) -> f32 {
+        let size = (eaten_size / self.reference_size.max(f32::EPSILON)).clamp(0.0, 1.0);
+        let falloff = (1.0 - distance / self.falloff_distance.max(f32::EPSILON)).clamp(0.0, 1.0);
+        self.volume * size * falloff
+    }
+}
+
+/// Where to put the emitter for a sound at `position`, relative to a listener at the origin
+/// facing -z with the camera's right along +x. It's always at unit distance, so only the pan
+/// comes from rodio's spatial audio and [`EatSoundConfig::volume`] handles the falloff.
+pub fn emitter_position(camera: &GlobalTransform, position: Vec3) -> Vec3 {
+    let local = camera.affine().inverse().transform_point3(position);
+    Vec3::new(local.x, 0.0, local.z)
+        .try_normalize()
+        .unwrap_or(Vec3::NEG_Z)
+}
+
+fn load_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut critical_assets: ResMut<CriticalAssets>,
+) {
+    commands.insert_resource(EatSound(critical_assets.load(
+        &asset_server,
+        CriticalAsset::EatSound,
+        EAT_SOUND_PATH,
+    )));
+}
+
+fn play_eat_sounds(
+    mut merge_events: EventReader<BlobMergeEvent>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    sound: Option<Res<EatSound>>,
+    config: Res<EatSoundConfig>,
+    audio: Res<Audio>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut playing: Local<Vec<f32>>,
+) {
+    // the audio queue holds on to sounds until they load, a missing file would pile them up
+    let Some(sound) =
+        sound.filter(|sound| asset_server.get_load_state(&sound.0) == LoadState::Loaded)
+    else {
+        merge_events.clear();
+        return;
+    };
+    let Ok(camera) = cameras.get_single() else {
+        merge_events.clear();
+        return;
+    };
+
+    let now = time.elapsed_seconds();
+    playing.retain(|started| now - *started < config.sound_duration);
+
+    for event in merge_events.iter() {
+        if playing.len() >= config.max_concurrent {
+            continue;
+        }
+
+        let distance = camera.translation().distance(event.position);
+        let volume = config.volume(event.eaten_size, distance);
+        if volume <= 0.0 {
+            continue;
+        }
+
+        let settings = PlaybackSettings::ONCE.with_volume(volume);
+        if config.spatial {
+            audio.play_spatial_with_settings(
+                sound.0.clone(),
+                settings,
+                Transform::IDENTITY,
+                0.3,
+                emitter_position(camera, event.position),
+            );
+        } else {
+            audio.play_with_settings(sound.0.clone(), settings);
+        }
+        playing.push(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_falls_off_with_distance_and_meal_size() {
+        let config = EatSoundConfig::default();
+        assert_eq!(config.volume(config.reference_size, 0.0), config.volume);
+        // bigger meals aren't any louder
+        assert_eq!(
+            config.volume(config.reference_size * 4.0, 0.0),
+            config.volume
+        );
+        assert!(
+            (config.volume(config.reference_size / 2.0, 0.0) - config.volume / 2.0).abs() < 1e-6
+        );
+        assert!(
+            (config.volume(config.reference_size, config.falloff_distance / 2.0)
+                - config.volume / 2.0)
+                .abs()
+                < 1e-6
+        );
+        assert_eq!(
+            config.volume(config.reference_size, config.falloff_distance * 2.0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn sounds_pan_towards_their_side_of_the_screen() {
+        let camera: GlobalTransform = Transform::from_xyz(0.0, -10.0, 10.0)
+            .looking_at(Vec3::ZERO, Vec3::Z)
+            .into();
+
+        let ahead = emitter_position(&camera, Vec3::ZERO);
+        assert!(ahead.abs_diff_eq(Vec3::NEG_Z, 1e-5), "{}", ahead);
+        let right = emitter_position(&camera, Vec3::new(5.0, 0.0, 0.0));
+        assert!(
+            right.x > 0.0 && (right.length() - 1.0).abs() < 1e-5,
+            "{}",
+            right
+        );
+        let left = emitter_position(&camera, Vec3::new(-5.0, 0.0, 0.0));
+        assert!((left.x + right.x).abs() < 1e-5, "{}", left);
+        // right on top of the listener still goes somewhere
+        assert_eq!(emitter_position(&camera, camera.translation()), Vec3::NEG_Z);
+    }
+}
@@ -0,0 +1,97 @@
+//! Debug tool for spawning and removing blobs with the mouse
+use crate::bvh::BvhTree;
+use crate::game::BlobPalette;
+use crate::raymarching::{Blob, BlobBundle, BlobLimit, BlobMaterial, BlobMesh};
+use crate::rng::GameRng;
+use crate::{update_cursor_world_position, CursorWorldPosition};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct SpawnToolPlugin;
+
+impl Plugin for SpawnToolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugSpawnTool>()
+            .init_resource::<DebugSpawnSize>()
+            .add_system(spawn_tool_window)
+            .add_system(
+                use_spawn_tool
+                    .after(update_cursor_world_position)
+                    .run_if(spawn_tool_enabled),
+            );
+    }
+}
+
+/// While on, left click spawns a blob under the cursor and right click removes the one nearest
+/// to it
+#[derive(Resource, Default, Debug)]
+pub struct DebugSpawnTool(pub bool);
+
+/// `Blob::size` of blobs spawned by the [`DebugSpawnTool`]
+#[derive(Resource, Debug)]
+pub struct DebugSpawnSize(pub f32);
+
+impl Default for DebugSpawnSize {
+    fn default() -> Self {
+        DebugSpawnSize(Blob::default().size)
+    }
+}
+
+pub(crate) fn spawn_tool_enabled(tool: Res<DebugSpawnTool>) -> bool {
+    tool.0
+}
+
+fn spawn_tool_window(
+    mut tool: ResMut<DebugSpawnTool>,
+    mut size: ResMut<DebugSpawnSize>,
+    mut egui_contexts: EguiContexts,
+) {
+    egui::Window::new("Spawn tool").show(egui_contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut tool.0, "Enabled");
+        ui.add(egui::Slider::new(&mut size.0, 0.05..=3.0).text("Size"));
+        ui.label("Left click spawns, right click removes");
+    });
+}
+
+fn use_spawn_tool(
+    mut commands: Commands,
+    mouse: Res<Input<MouseButton>>,
+    cursor: Res<CursorWorldPosition>,
+    size: Res<DebugSpawnSize>,
+    limit: Res<BlobLimit>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+    blobs: Query<(), With<Blob>>,
+    bvh: Res<BvhTree>,
+    palette: Res<BlobPalette>,
+    mut rng: ResMut<GameRng>,
+) {
+    // None while egui has the pointer, so clicks on the window don't spawn anything
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        if !limit.allows(blobs.iter().count()) {
+            return;
+        }
+
+        let blob = Blob {
+            size: size.0,
+            color: palette.pick(&mut rng),
+            ..default()
+        };
+        commands.spawn(BlobBundle::new(
+            &material,
+            &mesh,
+            Transform::from_translation(cursor.xy().extend(1.0)),
+            blob,
+        ));
+    } else if mouse.just_pressed(MouseButton::Right) {
+        // the BVH only knows about blobs, but one can be gone already this frame
+        if let Some((entity, _)) = bvh.nearest(cursor, |entity| blobs.contains(entity)) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,660 @@
+//! Splitting blobs into pieces
+use crate::game::{GameConfig, GameTime};
+use crate::raymarching::{
+    Blob, BlobBundle, BlobLimit, BlobMaterial, BlobMesh, DespawnQueue, Velocity,
+};
+use crate::spawn_tool::spawn_tool_enabled;
+use crate::teams::Team;
+use crate::{
+    direction_towards, update_cursor_world_position, CursorWorldPosition, PlayerGroup, PlayerInput,
+};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+pub struct SplitPlugin;
+
+impl Plugin for SplitPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplitConfig>()
+            .init_resource::<DoubleClick>()
+            .init_resource::<SplitBuffer>()
+            // left clicks belong to the spawn tool while it's on
+            .add_system(
+                double_click_split
+                    .after(update_cursor_world_position)
+                    .run_if(not(spawn_tool_enabled)),
+            )
+            .add_system(buffered_split.after(double_click_split));
+    }
+}
+
+#[derive(Resource)]
+pub struct SplitConfig {
+    /// Blobs smaller than this can't split
+    pub min_split_size: f32,
+    /// No piece of a split comes out smaller than this, blobs split into fewer pieces instead
+    pub min_piece_size: f32,
+    /// Initial speed of the split off pieces
+    pub launch_speed: f32,
+    /// Angle in radians the pieces of a split into more than two fan out over, a full circle
+    /// spreads them all the way around
+    pub spread_angle: f32,
+    /// Longest time between two clicks that still counts as a double click, in seconds
+    pub double_click_window: f32,
+    /// Seconds after a split before the player can split again
+    pub cooldown: f32,
+    /// How long a split request waits for the cooldown or size to allow it before it's dropped,
+    /// in seconds
+    pub buffer_window: f32,
+    /// Seconds after the last split before the player's pieces start merging back together
+    pub recombine_delay: f32,
+    /// How fast recombining pieces drift towards each other
+    pub recombine_speed: f32,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        SplitConfig {
+            min_split_size: 0.4,
+            min_piece_size: 0.15,
+            launch_speed: 8.0,
+            spread_angle: std::f32::consts::TAU,
+            double_click_window: 0.3,
+            cooldown: 0.5,
+            buffer_window: 0.3,
+            recombine_delay: 8.0,
+            recombine_speed: 1.5,
+        }
+    }
+}
+
+impl SplitConfig {
+    /// How many pieces a blob of `size` actually splits into when asked for `requested`, as
+    /// many as fit without going under `min_piece_size`. Below 2 it doesn't split at all.
+    pub fn piece_count(&self, size: f32, requested: usize) -> usize {
+        if size < self.min_split_size {
+            return 0;
+        }
+        if self.min_piece_size <= 0.0 {
+            return requested;
+        }
+
+        // pieces share the area, so each one's size is `size / sqrt(pieces)`
+        let fits = (size / self.min_piece_size).powi(2).floor() as usize;
+        requested.min(fits)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct DoubleClick {
+    last_click: Option<f32>,
+}
+
+impl DoubleClick {
+    /// Registers a click at `now`, returns true if it completes a double click
+    pub fn click(&mut self, now: f32, window: f32) -> bool {
+        match self.last_click.take() {
+            Some(last_click) if now - last_click <= window => true,
+            _ => {
+                self.last_click = Some(now);
+                false
+            }
+        }
+    }
+}
+
+/// A split the player asked for that hasn't happened yet
+#[derive(Resource, Default)]
+pub struct SplitBuffer {
+    /// When the split was requested and the point to split towards
+    pub request: Option<(f32, Vec3)>,
+    pub last_split: Option<f32>,
+}
+
+impl SplitBuffer {
+    pub fn request(&mut self, now: f32, target: Vec3) {
+        self.request = Some((now, target));
+    }
+
+    /// The pending request's target if it's still fresh and the cooldown is over. Requests older
+    /// than `buffer_window` are dropped.
+    pub fn ready(&mut self, now: f32, config: &SplitConfig) -> Option<Vec3> {
+        let (requested_at, target) = self.request?;
+        if now - requested_at > config.buffer_window {
+            self.request = None;
+            return None;
+        }
+        if self
+            .last_split
+            .map_or(false, |last_split| now - last_split < config.cooldown)
+        {
+            return None;
+        }
+
+        Some(target)
+    }
+
+    pub fn fired(&mut self, now: f32) {
+        self.request = None;
+        self.last_split = Some(now);
+    }
+}
+
+/// Splits `blob` in half, keeping its area, and spawns the other half launched along `direction`.
+///
+/// Returns the new half, or None if the blob is too small to split.
+pub fn split_blob(
+    commands: &mut Commands,
+    material: &BlobMaterial,
+    mesh: &BlobMesh,
+    transform: &mut Transform,
+    blob: &mut Blob,
+    direction: Vec2,
+    config: &SplitConfig,
+) -> Option<Entity> {
+    if config.piece_count(blob.size, 2) < 2 {
+        return None;
+    }
+
+    let direction = direction.try_normalize().unwrap_or(Vec2::X);
+    shrink_for_split(transform, blob, 2);
+
+    Some(spawn_piece(
+        commands,
+        material,
+        mesh,
+        transform,
+        blob,
+        direction,
+        config.launch_speed,
+    ))
+}
+
+/// Splits `blob` into up to `pieces` blobs of equal area fanned out over
+/// `SplitConfig::spread_angle`, centered on `direction`. The parent stays one of the pieces and
+/// gets launched too. Small blobs split into fewer pieces, see [`SplitConfig::piece_count`].
+///
+/// Returns every piece, `parent` first, so there are as many as were produced. Empty if the blob
+/// is too small to split.
+pub fn split_blob_into(
+    commands: &mut Commands,
+    material: &BlobMaterial,
+    mesh: &BlobMesh,
+    parent: Entity,
+    transform: &mut Transform,
+    blob: &mut Blob,
+    pieces: usize,
+    direction: Vec2,
+    config: &SplitConfig,
+) -> Vec<Entity> {
+    let pieces = config.piece_count(blob.size, pieces);
+    if pieces < 2 {
+        return Vec::new();
+    }
+
+    shrink_for_split(transform, blob, pieces);
+
+    let directions = spread_directions(direction, pieces, config.spread_angle);
+    blob.direction = direction_towards(Vec2::ZERO, directions[0]);
+    commands
+        .entity(parent)
+        .insert(Velocity(directions[0] * config.launch_speed));
+
+    let new_pieces = directions[1..].iter().map(|direction| {
+        spawn_piece(
+            commands,
+            material,
+            mesh,
+            transform,
+            blob,
+            *direction,
+            config.launch_speed,
+        )
+    });
+    std::iter::once(parent).chain(new_pieces).collect()
+}
+
+/// `pieces` unit directions spread evenly over `spread_angle` radians centered on `direction`.
+/// A full circle or more spaces them evenly all the way around.
+pub fn spread_directions(direction: Vec2, pieces: usize, spread_angle: f32) -> Vec<Vec2> {
+    let direction = direction.try_normalize().unwrap_or(Vec2::X);
+    if pieces < 2 {
+        return vec![direction; pieces];
+    }
+
+    let full_circle = spread_angle >= std::f32::consts::TAU;
+    let step = if full_circle {
+        std::f32::consts::TAU / pieces as f32
+    } else {
+        spread_angle / (pieces - 1) as f32
+    };
+    let start = if full_circle {
+        0.0
+    } else {
+        -spread_angle * 0.5
+    };
+
+    (0..pieces)
+        .map(|i| Vec2::from_angle(start + step * i as f32).rotate(direction))
+        .collect()
+}
+
+/// Divides the blob's area between `pieces`
+fn shrink_for_split(transform: &mut Transform, blob: &mut Blob, pieces: usize) {
+    let size_factor = (pieces as f32).sqrt().recip();
+    blob.set_area(blob.area() / pieces as f32);
+    blob.target_size = blob
+        .target_size
+        .map(|target_size| target_size * size_factor);
+    transform.scale = blob.scale();
+}
+
+/// Spawns a copy of an already shrunk `blob` next to it, launched along `direction`
+fn spawn_piece(
+    commands: &mut Commands,
+    material: &BlobMaterial,
+    mesh: &BlobMesh,
+    transform: &Transform,
+    blob: &Blob,
+    direction: Vec2,
+    speed: f32,
+) -> Entity {
+    let mut piece_transform = *transform;
+    piece_transform.translation += (direction * blob.size).extend(0.0);
+
+    commands
+        .spawn(BlobBundle::new(
+            material,
+            mesh,
+            piece_transform,
+            Blob {
+                size: blob.size,
+                direction: direction_towards(Vec2::ZERO, direction),
+                color: blob.color,
+                target_size: blob.target_size,
+                ..default()
+            },
+        ))
+        .insert(Velocity(direction * speed))
+        .id()
+}
+
+fn double_click_split(
+    mouse: Res<Input<MouseButton>>,
+    mut double_click: ResMut<DoubleClick>,
+    mut buffer: ResMut<SplitBuffer>,
+    config: Res<SplitConfig>,
+    cursor: Res<CursorWorldPosition>,
+    game_time: Res<GameTime>,
+) {
+    // clicks on egui don't count, CursorWorldPosition is None while egui has the pointer
+    let cursor = match cursor.0 {
+        Some(cursor) if mouse.just_pressed(MouseButton::Left) => cursor,
+        _ => return,
+    };
+    if double_click.click(game_time.elapsed, config.double_click_window) {
+        buffer.request(game_time.elapsed, cursor);
+    }
+}
+
+/// Splits the player blobs once a buffered request is allowed, so a double click during the
+/// cooldown isn't lost
+fn buffered_split(
+    mut commands: Commands,
+    mut buffer: ResMut<SplitBuffer>,
+    config: Res<SplitConfig>,
+    limit: Res<BlobLimit>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+    mut players: Query<(&mut Transform, &mut Blob, Option<&Team>), With<PlayerInput>>,
+    blobs: Query<(), With<Blob>>,
+    game_time: Res<GameTime>,
+) {
+    let now = game_time.elapsed;
+    let Some(target) = buffer.ready(now, &config) else {
+        return;
+    };
+
+    let mut blob_count = blobs.iter().count();
+    let mut split_any = false;
+    for (mut transform, mut blob, team) in players.iter_mut() {
+        if !limit.allows(blob_count) {
+            break;
+        }
+
+        let direction = target.xy() - transform.translation.xy();
+        if let Some(half) = split_blob(
+            &mut commands,
+            &material,
+            &mesh,
+            &mut transform,
+            &mut blob,
+            direction,
+            &config,
+        ) {
+            commands.entity(half).insert(PlayerInput);
+            if let Some(team) = team {
+                commands.entity(half).insert(*team);
+            }
+            blob_count += 1;
+            split_any = true;
+        }
+    }
+
+    // otherwise keep the request around, the player may grow big enough in time
+    if split_any {
+        buffer.fired(now);
+    }
+}
+
+/// Player pieces that may merge back into each other, `blob_merger` leaves them alone
+#[derive(Component)]
+pub struct Recombining;
+
+/// Once `recombine_delay` has passed since the last split, pulls the player's pieces together and
+/// merges the ones that overlap. Merging adds up the areas, up to [`GameConfig::max_size`], and
+/// doesn't score.
+pub(crate) fn recombine_player_pieces(
+    mut commands: Commands,
+    mut players: Query<
+        (Entity, &mut Transform, &mut Blob, Option<&Recombining>),
+        With<PlayerInput>,
+    >,
+    buffer: Res<SplitBuffer>,
+    (config, game_config): (Res<SplitConfig>, Res<GameConfig>),
+    mut despawns: ResMut<DespawnQueue>,
+    game_time: Res<GameTime>,
+    fixed_time: Res<FixedTime>,
+) {
+    let ready = buffer.last_split.map_or(true, |last_split| {
+        game_time.elapsed - last_split >= config.recombine_delay
+    });
+    for (entity, _, _, recombining) in players.iter() {
+        match (ready, recombining.is_some()) {
+            (true, false) => {
+                commands.entity(entity).insert(Recombining);
+            }
+            (false, true) => {
+                commands.entity(entity).remove::<Recombining>();
+            }
+            _ => {}
+        }
+    }
+    if !ready || players.iter().count() < 2 {
+        return;
+    }
+
+    let Some(group) = PlayerGroup::from_pieces(
+        players
+            .iter()
+            .map(|(_, transform, blob, _)| (transform, blob)),
+    ) else {
+        return;
+    };
+    let step = config.recombine_speed * fixed_time.period.as_secs_f32();
+    for (_, mut transform, _, _) in players.iter_mut() {
+        let offset = (group.centroid - transform.translation).xy();
+        transform.translation += offset.clamp_length_max(step).extend(0.0);
+    }
+
+    // a piece merges into a bigger one once its center is inside it
+    let mut merges = Vec::new();
+    for [a, b] in players.iter_combinations() {
+        let (smaller, bigger) = if a.2.size > b.2.size { (b, a) } else { (a, b) };
+        if smaller.1.translation.distance(bigger.1.translation) < bigger.2.size {
+            merges.push((bigger.0, smaller.0));
+        }
+    }
+
+    let mut merged = HashSet::new();
+    for (bigger, smaller) in merges {
+        if merged.contains(&bigger) || merged.contains(&smaller) {
+            continue;
+        }
+
+        if let Ok([(_, _, mut bigger_blob, _), (_, _, smaller_blob, _)]) =
+            players.get_many_mut([bigger, smaller])
+        {
+            merged.insert(bigger);
+            merged.insert(smaller);
+            despawns.push(smaller);
+
+            // areas add up, so radii add in quadrature. Pieces of a player already scored, so
+            // whatever the cap cuts off is just lost
+            let target = |blob: &Blob| blob.target_size.unwrap_or(blob.size);
+            let size = target(&bigger_blob);
+            let (target_size, _overflow) =
+                game_config.grow(size, size.hypot(target(&smaller_blob)) - size);
+            bigger_blob.target_size = Some(target_size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    #[test]
+    fn quick_clicks_double_click_and_slow_ones_dont() {
+        let window = SplitConfig::default().double_click_window;
+
+        let mut double_click = DoubleClick::default();
+        assert!(!double_click.click(1.0, window));
+        assert!(double_click.click(1.0 + window * 0.5, window));
+
+        let mut double_click = DoubleClick::default();
+        assert!(!double_click.click(1.0, window));
+        assert!(!double_click.click(1.0 + window * 2.0, window));
+        // the slow second click starts a new double click
+        assert!(double_click.click(1.0 + window * 2.5, window));
+
+        // a third quick click doesn't complete another one
+        let mut double_click = DoubleClick::default();
+        double_click.click(1.0, window);
+        assert!(double_click.click(1.1, window));
+        assert!(!double_click.click(1.2, window));
+    }
+
+    #[test]
+    fn buffered_split_waits_out_the_cooldown_within_the_window() {
+        let config = SplitConfig {
+            cooldown: 0.5,
+            buffer_window: 0.3,
+            ..default()
+        };
+        let target = Vec3::new(1.0, 2.0, 0.0);
+
+        let mut buffer = SplitBuffer::default();
+        buffer.fired(10.0);
+        buffer.request(10.3, target);
+        // still cooling down, the request waits
+        assert_eq!(buffer.ready(10.4, &config), None);
+        assert_eq!(buffer.ready(10.55, &config), Some(target));
+        buffer.fired(10.55);
+        assert_eq!(buffer.ready(10.6, &config), None);
+
+        // the cooldown outlasts the window, the request is dropped
+        let mut buffer = SplitBuffer::default();
+        buffer.fired(10.0);
+        buffer.request(10.1, target);
+        assert_eq!(buffer.ready(10.3, &config), None);
+        assert_eq!(buffer.ready(10.6, &config), None);
+        assert_eq!(buffer.request, None);
+    }
+
+    #[test]
+    fn buffered_split_doesnt_expire_while_paused() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<SplitBuffer>()
+            .init_resource::<SplitConfig>()
+            .init_resource::<BlobLimit>()
+            .init_resource::<GameTime>()
+            .insert_resource(BlobMaterial(Handle::default()))
+            .insert_resource(BlobMesh(Handle::default()))
+            .add_system(buffered_split);
+        // no player blob yet, so the request stays pending
+        app.world
+            .resource_mut::<SplitBuffer>()
+            .request(0.0, Vec3::X);
+
+        // real time runs on but game time doesn't, like during a pause
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(1)));
+        app.update();
+        app.update();
+        assert!(app.world.resource::<SplitBuffer>().request.is_some());
+
+        app.world.resource_mut::<GameTime>().elapsed = 1.0;
+        app.update();
+        assert!(app.world.resource::<SplitBuffer>().request.is_none());
+    }
+
+    #[test]
+    fn pieces_dont_start_recombining_during_a_pause() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(SplitBuffer {
+                request: None,
+                last_split: Some(0.0),
+            })
+            .init_resource::<SplitConfig>()
+            .init_resource::<GameConfig>()
+            .init_resource::<DespawnQueue>()
+            .init_resource::<GameTime>()
+            .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(60)))
+            .add_system(recombine_player_pieces);
+        let piece = app
+            .world
+            .spawn((Transform::default(), Blob::default(), PlayerInput))
+            .id();
+
+        // a minute of real time paused a second after the split
+        app.world.resource_mut::<GameTime>().elapsed = 1.0;
+        app.update();
+        app.update();
+        assert!(!app.world.entity(piece).contains::<Recombining>());
+
+        let delay = app.world.resource::<SplitConfig>().recombine_delay;
+        app.world.resource_mut::<GameTime>().elapsed = delay;
+        app.update();
+        assert!(app.world.entity(piece).contains::<Recombining>());
+    }
+
+    #[test]
+    fn recombining_doesnt_grow_past_the_cap() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<SplitBuffer>()
+            .init_resource::<SplitConfig>()
+            .init_resource::<GameConfig>()
+            .init_resource::<DespawnQueue>()
+            .init_resource::<GameTime>()
+            .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+            .add_system(recombine_player_pieces);
+        let max = app.world.resource::<GameConfig>().max_size;
+        let piece = |x| {
+            (
+                Transform::from_xyz(x, 0.0, 0.0),
+                Blob {
+                    size: max,
+                    ..default()
+                },
+                PlayerInput,
+            )
+        };
+        app.world.spawn(piece(0.0));
+        app.world.spawn(piece(0.1));
+
+        app.update();
+        // the pieces are the same size, either one can be the one merged into
+        let targets: Vec<_> = app
+            .world
+            .query::<&Blob>()
+            .iter(&app.world)
+            .filter_map(|blob| blob.target_size)
+            .collect();
+        assert_eq!(targets, [max]);
+    }
+
+    #[test]
+    fn full_circle_spreads_four_pieces_evenly() {
+        let directions = spread_directions(Vec2::new(0.0, 2.0), 4, std::f32::consts::TAU);
+        assert_eq!(directions.len(), 4);
+        assert!(directions[0].abs_diff_eq(Vec2::Y, 1e-5));
+        for (i, direction) in directions.iter().enumerate() {
+            assert!((direction.length() - 1.0).abs() < 1e-5);
+            // a quarter turn to the next one, all the way around
+            let next = directions[(i + 1) % 4];
+            assert!(direction.dot(next).abs() < 1e-5, "{} {}", direction, next);
+            assert!(direction.perp_dot(next) > 0.0);
+        }
+    }
+
+    #[test]
+    fn narrow_spread_is_centered_on_the_direction() {
+        let directions = spread_directions(Vec2::X, 3, std::f32::consts::FRAC_PI_2);
+        assert!(directions[1].abs_diff_eq(Vec2::X, 1e-5));
+        let angle = |v: Vec2| v.y.atan2(v.x);
+        assert!((angle(directions[0]) + std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+        assert!((angle(directions[2]) - std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+
+        assert_eq!(spread_directions(Vec2::ZERO, 1, 1.0), [Vec2::X]);
+        assert!(spread_directions(Vec2::X, 0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn small_blobs_split_into_fewer_pieces() {
+        let config = SplitConfig::default();
+        let size = 0.4;
+        let pieces = config.piece_count(size, 8);
+        assert!((2..8).contains(&pieces), "{pieces} pieces");
+        assert!(size / (pieces as f32).sqrt() >= config.min_piece_size);
+
+        assert_eq!(config.piece_count(4.0, 8), 8);
+        assert_eq!(config.piece_count(config.min_split_size * 0.5, 8), 0);
+    }
+
+    #[test]
+    fn split_returns_every_piece_it_produced() {
+        let config = SplitConfig::default();
+        let material = BlobMaterial(Handle::default());
+        let mesh = BlobMesh(Handle::default());
+        let mut world = World::new();
+        let parent = world.spawn_empty().id();
+        let mut transform = Transform::default();
+        let mut blob = Blob {
+            size: 0.4,
+            ..default()
+        };
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let pieces = split_blob_into(
+            &mut commands,
+            &material,
+            &mesh,
+            parent,
+            &mut transform,
+            &mut blob,
+            8,
+            Vec2::X,
+            &config,
+        );
+        queue.apply(&mut world);
+
+        assert_eq!(pieces.len(), config.piece_count(0.4, 8));
+        assert_eq!(pieces[0], parent);
+        assert_eq!(
+            world.query::<&Blob>().iter(&world).count(),
+            pieces.len() - 1
+        );
+        assert!(pieces[1..]
+            .iter()
+            .all(|piece| world.get::<Blob>(*piece).is_some()));
+    }
+}
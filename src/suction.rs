@@ -0,0 +1,158 @@
+//! Small blobs get sucked in by much bigger ones just before being eaten
+use crate::raymarching::{Blob, MergeConfig};
+use crate::teams::Team;
+use crate::viruses::Virus;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+pub struct SuctionPlugin;
+
+impl Plugin for SuctionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Suction>().add_system(suction_window);
+    }
+}
+
+#[derive(Resource)]
+pub struct Suction {
+    pub enabled: bool,
+    /// Units per second a blob gets pulled with when touching the bigger blob's edge, the pull
+    /// fades out linearly towards `range`
+    pub strength: f32,
+    /// How far past the bigger blob's edge the pull reaches
+    pub range: f32,
+    /// How many times bigger a blob has to be to pull, on top of `MergeConfig::size_ratio`
+    pub size_ratio: f32,
+}
+
+impl Default for Suction {
+    fn default() -> Self {
+        Suction {
+            enabled: true,
+            strength: 0.6,
+            range: 0.75,
+            size_ratio: 1.5,
+        }
+    }
+}
+
+impl Suction {
+    /// Speed a blob `distance` away from a blob with radius `bigger_radius` gets pulled in with
+    pub fn pull(&self, distance: f32, bigger_radius: f32) -> f32 {
+        if self.range <= 0.0 {
+            return 0.0;
+        }
+        let gap = (distance - bigger_radius).max(0.0);
+        self.strength * (1.0 - gap / self.range).max(0.0)
+    }
+}
+
+pub(crate) fn apply_suction(
+    mut blobs: Query<(Entity, &mut Transform, &Blob)>,
+    viruses: Query<(), With<Virus>>,
+    teams: Query<&Team>,
+    config: Res<Suction>,
+    merge_config: Res<MergeConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    if !config.enabled {
+        return;
+    }
+
+    let delta = fixed_time.period.as_secs_f32();
+    let size_ratio = merge_config.size_ratio * config.size_ratio;
+
+    // collected first so every blob is pulled towards where the others were this tick
+    let mut pulls = Vec::new();
+    for [a, b] in blobs.iter_combinations() {
+        // viruses don't take part in eating, see viruses.rs
+        if viruses.contains(a.0) || viruses.contains(b.0) {
+            continue;
+        }
+        if !Team::hostile(teams.get(a.0).ok(), teams.get(b.0).ok()) {
+            continue;
+        }
+
+        let (smaller, bigger) = if a.2.size < b.2.size { (a, b) } else { (b, a) };
+        if bigger.2.size < smaller.2.size * size_ratio {
+            continue;
+        }
+
+        let offset = bigger.1.translation.xy() - smaller.1.translation.xy();
+        let distance = offset.length();
+        let speed = config.pull(distance, bigger.2.radius());
+        if speed <= 0.0 {
+            continue;
+        }
+
+        // never past the bigger blob's center
+        let step = (speed * delta).min(distance);
+        if let Some(direction) = offset.try_normalize() {
+            pulls.push((smaller.0, direction * step));
+        }
+    }
+
+    for (entity, pull) in pulls {
+        if let Ok((_, mut transform, _)) = blobs.get_mut(entity) {
+            transform.translation += pull.extend(0.0);
+        }
+    }
+}
+
+fn suction_window(mut config: ResMut<Suction>, mut egui_contexts: EguiContexts) {
+    egui::Window::new("Suction").show(egui_contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut config.enabled, "Enabled");
+        ui.add(egui::Slider::new(&mut config.strength, 0.0..=3.0).text("Strength"));
+        ui.add(egui::Slider::new(&mut config.range, 0.0..=3.0).text("Range"));
+        ui.add(egui::Slider::new(&mut config.size_ratio, 1.0..=4.0).text("Size ratio"));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How far a small blob starting `gap` past a big blob's edge moves in half a second
+    fn pulled_in_half_a_second(enabled: bool, gap: f32) -> f32 {
+        let mut app = App::new();
+        app.insert_resource(Suction {
+            enabled,
+            ..default()
+        })
+        .init_resource::<MergeConfig>()
+        .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
+        .add_system(apply_suction);
+        let big = Blob {
+            size: 1.0,
+            ..default()
+        };
+        let start = big.radius() + gap;
+        app.world.spawn((Transform::default(), big));
+        let small = app
+            .world
+            .spawn((
+                Transform::from_xyz(start, 0.0, 0.0),
+                Blob {
+                    size: 0.2,
+                    ..default()
+                },
+            ))
+            .id();
+
+        for _ in 0..30 {
+            app.update();
+        }
+        start - app.world.get::<Transform>(small).unwrap().translation.x
+    }
+
+    #[test]
+    fn small_blobs_drift_into_big_ones() {
+        assert_eq!(pulled_in_half_a_second(false, 0.3), 0.0);
+        let pulled = pulled_in_half_a_second(true, 0.3);
+        assert!(pulled > 0.1, "{}", pulled);
+
+        // out of range nothing happens
+        assert_eq!(pulled_in_half_a_second(true, 1.0), 0.0);
+    }
+}
@@ -0,0 +1,52 @@
+//! Teams of blobs that leave each other alone
+use bevy::prelude::*;
+
+pub struct TeamPlugin;
+
+impl Plugin for TeamPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TeamColors>();
+    }
+}
+
+/// Blobs on the same team push each other apart instead of eating. Blobs without a team eat
+/// and get eaten by anyone.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Team(pub u8);
+
+/// The player and all of its pieces
+pub const PLAYER_TEAM: Team = Team(0);
+
+impl Team {
+    /// Whether blobs on teams `a` and `b` are allowed to eat each other
+    pub fn hostile(a: Option<&Team>, b: Option<&Team>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => a != b,
+            _ => true,
+        }
+    }
+}
+
+/// Colors teams are marked with, indexed by team and wrapping around
+#[derive(Resource)]
+pub struct TeamColors(pub Vec<Color>);
+
+impl Default for TeamColors {
+    fn default() -> Self {
+        TeamColors(vec![
+            Color::WHITE,
+            Color::rgb(0.3, 0.6, 1.0),
+            Color::rgb(1.0, 0.35, 0.3),
+            Color::rgb(0.4, 0.9, 0.4),
+        ])
+    }
+}
+
+impl TeamColors {
+    pub fn color(&self, team: Team) -> Color {
+        if self.0.is_empty() {
+            return Color::WHITE;
+        }
+        self.0[team.0 as usize % self.0.len()]
+    }
+}
@@ -0,0 +1,130 @@
+//! Headless app for exercising gameplay systems without a GPU
+//!
+//! Only the gameplay side is wired up here, anything that touches the
+//! `RenderDevice` (material and BVH buffer uploads) is left out.
+use crate::bvh::{
+    self, BvhBuildTiming, BvhLeafSize, BvhRebuildPolicy, BvhTree, CalculateBvh, LocalBoundingBox,
+    SceneBounds,
+};
+use crate::game::{self, GameState};
+use crate::pellets::PelletConfig;
+use crate::raymarching::{Blob, BlobMergeEvent};
+use crate::simulation::{add_gameplay_systems, Simulation, SimulationPlugin};
+use crate::viruses::VirusConfig;
+use bevy::diagnostic::DiagnosticsPlugin;
+use bevy::ecs::event::ManualEventReader;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::Instant;
+use std::time::Duration;
+
+/// Frame length used by [`TestApp::step`], also the simulation tick length
+pub const FRAME_TIME: f32 = 1.0 / 60.0;
+
+pub struct TestApp {
+    pub app: App,
+    /// What `Time` gets updated to on the next [`TestApp::step`]
+    now: Instant,
+    merge_reader: ManualEventReader<BlobMergeEvent>,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(DiagnosticsPlugin)
+            // no InputPlugin, but SimulationPlugin reads the keyboard for slow motion
+            .init_resource::<Input<KeyCode>>()
+            // one simulation tick per frame
+            .insert_resource(FixedTime::new_from_secs(FRAME_TIME))
+            .add_plugin(SimulationPlugin)
+            .add_state::<GameState>()
+            .insert_resource(BvhTree::default())
+            .init_resource::<BvhBuildTiming>()
+            .init_resource::<BvhRebuildPolicy>()
+            .init_resource::<BvhLeafSize>()
+            .init_resource::<SceneBounds>()
+            // tests place every blob themselves
+            .insert_resource(VirusConfig {
+                count: 0,
+                ..default()
+            })
+            .insert_resource(PelletConfig {
+                max_pellets: 0,
+                ..default()
+            })
+            .add_startup_system(bvh::setup_bvh_diagnostics)
+            .add_system(game::advance_game_time.in_base_set(CoreSet::PreUpdate))
+            .add_system(bvh::update_bvh_aabb)
+            .add_system(bvh::update_bvh.after(bvh::update_bvh_aabb));
+        add_gameplay_systems(&mut app);
+        Simulation::init_world(&mut app.world);
+
+        // `ManualDuration` goes from the wall clock, so the instants are handed out by hand
+        let now = app.world.resource::<Time>().startup();
+        TestApp {
+            app,
+            now,
+            merge_reader: default(),
+        }
+    }
+
+    pub fn world(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    /// Spawns a blob the same way `spawn_debug_voxel` does, minus the mesh and material
+    pub fn spawn_blob(&mut self, position: Vec3, size: f32) -> Entity {
+        let blob = Blob { size, ..default() };
+        self.app
+            .world
+            .spawn((
+                Transform::from_translation(position).with_scale(blob.scale()),
+                blob,
+                CalculateBvh,
+                LocalBoundingBox::blob(),
+            ))
+            .id()
+    }
+
+    /// Runs a single frame of [`FRAME_TIME`] seconds
+    pub fn step(&mut self) {
+        self.step_by(Duration::from_secs_f32(FRAME_TIME));
+    }
+
+    /// Runs a single frame of `frame_time`, as many simulation ticks as fit into it
+    pub fn step_by(&mut self, frame_time: Duration) {
+        self.now += frame_time;
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualInstant(self.now));
+        self.app.update();
+    }
+
+    pub fn step_frames(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.step();
+        }
+    }
+
+    pub fn blob_count(&mut self) -> usize {
+        self.app
+            .world
+            .query_filtered::<(), With<Blob>>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    pub fn blob(&self, entity: Entity) -> Option<&Blob> {
+        self.app.world.get::<Blob>(entity)
+    }
+
+    /// Eater and eaten of every merge since the last call. Call it every frame or two, events
+    /// only stick around that long.
+    pub fn merges(&mut self) -> Vec<(Entity, Entity)> {
+        let events = self.app.world.resource::<Events<BlobMergeEvent>>();
+        self.merge_reader
+            .iter(events)
+            .map(|event| (event.eater, event.eaten))
+            .collect()
+    }
+}
@@ -0,0 +1,115 @@
+//! Motion trails for blobs
+use crate::simulation::SimulationSet;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+/// How often a new point gets added to the trails, in seconds
+const TRAIL_SAMPLE_INTERVAL: f32 = 0.05;
+
+pub struct TrailPlugin;
+
+impl Plugin for TrailPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TrailTimer(Timer::from_seconds(
+            TRAIL_SAMPLE_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_system(update_trails.after(SimulationSet::Interpolate))
+        .add_system(draw_trails.after(update_trails));
+    }
+}
+
+/// Position history of an entity, drawn as a fading line behind it
+#[derive(Component)]
+pub struct Trail {
+    /// Oldest point first
+    pub points: VecDeque<Vec3>,
+    pub max_len: usize,
+    pub color: Color,
+}
+
+impl Trail {
+    pub fn new(max_len: usize, color: Color) -> Self {
+        Trail {
+            points: VecDeque::with_capacity(max_len),
+            max_len,
+            color,
+        }
+    }
+
+    /// Adds a point to the end of the trail, dropping the oldest ones past `max_len`
+    pub fn push(&mut self, point: Vec3) {
+        self.points.push_back(point);
+        while self.points.len() > self.max_len {
+            self.points.pop_front();
+        }
+    }
+
+    /// Color of the line from point `index` to the next one, older segments fade out
+    pub fn segment_color(&self, index: usize) -> Color {
+        let segments = self.points.len().saturating_sub(1).max(1);
+        let alpha = (index + 1) as f32 / segments as f32;
+        self.color.with_a(self.color.a() * alpha)
+    }
+}
+
+#[derive(Resource)]
+struct TrailTimer(Timer);
+
+fn update_trails(
+    mut timer: ResMut<TrailTimer>,
+    mut trails: Query<(&Transform, &mut Trail)>,
+    time: Res<Time>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    for (transform, mut trail) in trails.iter_mut() {
+        trail.push(transform.translation);
+    }
+}
+
+fn draw_trails(trails: Query<&Trail>) {
+    for trail in trails.iter() {
+        for (i, (from, to)) in trail
+            .points
+            .iter()
+            .zip(trail.points.iter().skip(1))
+            .enumerate()
+        {
+            bevy_mod_gizmos::draw_line(vec![*from, *to], trail.segment_color(i));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_evicts_oldest_points_past_max_len() {
+        let mut trail = Trail::new(3, Color::WHITE);
+        for x in 0..5 {
+            trail.push(Vec3::X * x as f32);
+        }
+
+        assert_eq!(trail.points.len(), 3);
+        assert_eq!(trail.points.front(), Some(&(Vec3::X * 2.0)));
+        assert_eq!(trail.points.back(), Some(&(Vec3::X * 4.0)));
+    }
+
+    #[test]
+    fn older_segments_fade_out_keeping_their_color() {
+        let mut trail = Trail::new(5, Color::rgba(1.0, 0.5, 0.25, 0.8));
+        for x in 0..5 {
+            trail.push(Vec3::X * x as f32);
+        }
+
+        let oldest = trail.segment_color(0);
+        let newest = trail.segment_color(3);
+        assert!((oldest.a() - 0.2).abs() < 1e-6);
+        assert!((newest.a() - 0.8).abs() < 1e-6);
+        assert_eq!([oldest.r(), oldest.g(), oldest.b()], [1.0, 0.5, 0.25]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Viruses, spiky obstacles that burst big blobs into pieces
+use crate::ai::AiBlob;
+use crate::game::GameTime;
+use crate::raymarching::{Blob, BlobBundle, BlobLimit, BlobMaterial, BlobMesh};
+use crate::rng::GameRng;
+use crate::split::{split_blob_into, SplitBuffer, SplitConfig};
+use crate::teams::Team;
+use crate::{Arena, PlayerInput};
+use bevy::prelude::*;
+
+pub struct VirusPlugin;
+
+impl Plugin for VirusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VirusConfig>();
+    }
+}
+
+/// A blob that splits anything big enough running into it. `blob_merger` ignores viruses.
+#[derive(Component)]
+pub struct Virus;
+
+#[derive(Resource)]
+pub struct VirusConfig {
+    /// How many viruses are kept in the arena
+    pub count: usize,
+    pub size: f32,
+    /// Blobs smaller than this pass through viruses untouched
+    pub virus_threshold: f32,
+    /// How many pieces a blob bursts into
+    pub pieces: usize,
+}
+
+impl Default for VirusConfig {
+    fn default() -> Self {
+        VirusConfig {
+            count: 4,
+            size: 0.6,
+            virus_threshold: 0.8,
+            pieces: 4,
+        }
+    }
+}
+
+impl VirusConfig {
+    /// Whether a blob of `size` gets split by a virus
+    pub fn splits(&self, size: f32) -> bool {
+        size >= self.virus_threshold
+    }
+}
+
+pub(crate) fn spawn_viruses(
+    mut commands: Commands,
+    viruses: Query<(), With<Virus>>,
+    blobs: Query<(), With<Blob>>,
+    mut rng: ResMut<GameRng>,
+    config: Res<VirusConfig>,
+    limit: Res<BlobLimit>,
+    arena: Res<Arena>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+) {
+    let mut blob_count = blobs.iter().count();
+    for _ in viruses.iter().count()..config.count {
+        if !limit.allows(blob_count) {
+            return;
+        }
+        let Some(position) = arena.random_point(&mut rng, config.size, 1.0) else {
+            return;
+        };
+
+        commands.spawn((
+            BlobBundle::new(
+                &material,
+                &mesh,
+                Transform::from_translation(position),
+                Blob {
+                    size: config.size,
+                    color: Color::rgb(0.2, 0.9, 0.3),
+                    ..default()
+                },
+            ),
+            Virus,
+        ));
+        blob_count += 1;
+    }
+}
+
+/// Blobs past `virus_threshold` that touch a virus burst into pieces, using up the virus
+pub(crate) fn virus_collisions(
+    mut commands: Commands,
+    viruses: Query<(Entity, &Transform, &Blob), With<Virus>>,
+    mut blobs: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Blob,
+            Option<&PlayerInput>,
+            Option<&AiBlob>,
+            Option<&Team>,
+        ),
+        Without<Virus>,
+    >,
+    config: Res<VirusConfig>,
+    split_config: Res<SplitConfig>,
+    mut split_buffer: ResMut<SplitBuffer>,
+    limit: Res<BlobLimit>,
+    material: Res<BlobMaterial>,
+    mesh: Res<BlobMesh>,
+    game_time: Res<GameTime>,
+) {
+    let mut blob_count = blobs.iter().count() + viruses.iter().count();
+    for (virus, virus_transform, virus_blob) in viruses.iter() {
+        for (entity, mut transform, mut blob, player, ai, team) in blobs.iter_mut() {
+            if !config.splits(blob.size)
+                || transform.translation.distance(virus_transform.translation)
+                    >= blob.size + virus_blob.size * 0.5
+            {
+                continue;
+            }
+
+            // as many extra pieces as the blob limit has room for
+            let pieces = 1
+                + (0..config.pieces.saturating_sub(1))
+                    .take_while(|i| limit.allows(blob_count + i))
+                    .count();
+            let direction = (Quat::from_rotation_z(blob.direction) * Vec3::NEG_Y).truncate();
+            let produced = split_blob_into(
+                &mut commands,
+                &material,
+                &mesh,
+                entity,
+                &mut transform,
+                &mut blob,
+                pieces,
+                direction,
+                &split_config,
+            );
+            // the first piece is the blob itself, it has all of this already
+            let Some((_, new_pieces)) = produced.split_first() else {
+                continue;
+            };
+            // the pieces wait out the recombine delay, same as after splitting by hand
+            if player.is_some() {
+                split_buffer.last_split = Some(game_time.elapsed);
+            }
+            for piece in new_pieces {
+                if player.is_some() {
+                    commands.entity(*piece).insert(PlayerInput);
+                }
+                if ai.is_some() {
+                    commands.entity(*piece).insert(AiBlob::default());
+                }
+                if let Some(team) = team {
+                    commands.entity(*piece).insert(*team);
+                }
+            }
+            blob_count += new_pieces.len();
+
+            commands.entity(virus).despawn();
+            blob_count -= 1;
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `virus_collisions` on its own, with a virus at the origin
+    fn virus_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.init_resource::<VirusConfig>()
+            .init_resource::<SplitConfig>()
+            .init_resource::<SplitBuffer>()
+            .init_resource::<GameTime>()
+            .init_resource::<BlobLimit>()
+            .insert_resource(BlobMaterial(Handle::default()))
+            .insert_resource(BlobMesh(Handle::default()))
+            .add_system(virus_collisions);
+        let virus = app
+            .world
+            .spawn((
+                Transform::default(),
+                Blob {
+                    size: 0.6,
+                    ..default()
+                },
+                Virus,
+            ))
+            .id();
+        (app, virus)
+    }
+
+    #[test]
+    fn only_blobs_past_the_threshold_burst() {
+        let (mut app, virus) = virus_app();
+        let small = app
+            .world
+            .spawn((
+                Transform::from_xyz(-0.3, 0.0, 0.0),
+                Blob {
+                    size: 0.5,
+                    ..default()
+                },
+            ))
+            .id();
+        let big = app
+            .world
+            .spawn((
+                Transform::from_xyz(0.3, 0.0, 0.0),
+                Blob {
+                    size: 1.2,
+                    ..default()
+                },
+            ))
+            .id();
+        app.update();
+
+        assert!(app.world.get_entity(virus).is_none(), "used up");
+        assert_eq!(app.world.get::<Blob>(small).unwrap().size, 0.5);
+        assert!(app.world.get::<Blob>(big).unwrap().size < 1.2);
+        let blob_count = app
+            .world
+            .query_filtered::<(), With<Blob>>()
+            .iter(&app.world)
+            .count();
+        assert_eq!(blob_count, 1 + VirusConfig::default().pieces);
+        // not the player's, so no recombine delay
+        assert_eq!(app.world.resource::<SplitBuffer>().last_split, None);
+    }
+
+    #[test]
+    fn bursting_the_player_starts_the_recombine_delay() {
+        let (mut app, _) = virus_app();
+        app.world.resource_mut::<GameTime>().elapsed = 5.0;
+        app.world.spawn((
+            Transform::from_xyz(0.3, 0.0, 0.0),
+            Blob {
+                size: 1.2,
+                ..default()
+            },
+            PlayerInput,
+        ));
+        app.update();
+
+        let players = app
+            .world
+            .query_filtered::<(), With<PlayerInput>>()
+            .iter(&app.world)
+            .count();
+        assert_eq!(players, VirusConfig::default().pieces);
+        assert_eq!(app.world.resource::<SplitBuffer>().last_split, Some(5.0));
+    }
+}